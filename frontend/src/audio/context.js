@@ -1,6 +1,14 @@
 // Audio context management
 import { workletProcessorCode } from './worklet-processor-code.js';
 
+// Offline renders waiting for a session snapshot from the worklet, oldest first
+const pendingRenders = [];
+
+// Worker the renders run on, created with the first one, and the renders it is running by id
+let renderWorker = null;
+const runningRenders = new Map();
+let nextRenderId = 0;
+
 // Create and initialize the audio context
 export async function initAudioContext() {
     // Create audio context with options
@@ -161,6 +169,10 @@ function handleProcessorMessage(event) {
             updateLevelMeters(data.peak, data.rms);
             break;
             
+        case 'renderSnapshot':
+            startRender(data);
+            break;
+            
         case 'error':
             console.error('Processor error:', data.message);
             break;
@@ -170,6 +182,54 @@ function handleProcessorMessage(event) {
     }
 }
 
+// Bounce the sampler to a WAV file, resolving to its bytes. `events` are { time, data }
+// MIDI messages; `playSample` renders the loaded sample through the effects from its
+// start; a `sampleRate` of 0 keeps the context rate. The worklet only sends a snapshot
+// of the session; the render runs on a processor of its own in a worker, so neither
+// live playback nor the page stalls while it runs.
+export function renderOffline(samplerNode, options = {}) {
+    return new Promise((resolve, reject) => {
+        pendingRenders.push({ options, contextRate: samplerNode.context.sampleRate, resolve, reject });
+        samplerNode.port.postMessage({ type: 'renderSnapshot' });
+    });
+}
+
+// Hand the oldest pending bounce to the render worker with the snapshot the worklet sent
+function startRender({ type, error, ...snapshot }) {
+    const render = pendingRenders.shift();
+    if (!render) {
+        return;
+    }
+    if (error) {
+        render.reject(new Error(error));
+        return;
+    }
+    
+    if (!renderWorker) {
+        renderWorker = new Worker(new URL('./render-worker.js', import.meta.url), { type: 'module' });
+        renderWorker.onmessage = finishRender;
+    }
+    const id = nextRenderId++;
+    runningRenders.set(id, render);
+    renderWorker.postMessage({ id, snapshot, options: render.options, contextRate: render.contextRate });
+}
+
+// Settle a bounce with the WAV bytes, or the error, the render worker sent back
+function finishRender(event) {
+    const { id, wav, error } = event.data;
+    const render = runningRenders.get(id);
+    if (!render) {
+        return;
+    }
+    runningRenders.delete(id);
+    if (error) {
+        console.error('Failed to render:', error);
+        render.reject(new Error(error));
+    } else {
+        render.resolve(wav);
+    }
+}
+
 // Update level meters (will be implemented in meters.js)
 function updateLevelMeters(peak, rms) {
     // This is a placeholder - the actual implementation will be in meters.js
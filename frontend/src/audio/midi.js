@@ -12,6 +12,7 @@ let midiInputs = [];
 let noteOnCallback = null;
 let noteOffCallback = null;
 let controlChangeCallback = null;
+let rawMessageCallback = null;
 
 // MIDI message types
 const MIDI_NOTE_ON = 0x90;
@@ -88,6 +89,13 @@ function handleMIDIStateChange(event) {
  * @param {MIDIMessageEvent} event - The MIDI message event
  */
 function handleMIDIMessage(event) {
+    // Forward the untouched bytes (e.g. to the audio worklet) before decoding. The
+    // time is the event's DOMHighResTimeStamp in milliseconds, not the frame offset
+    // the worklet's 'midi' message takes, so it has to be converted on the way.
+    if (rawMessageCallback) {
+        rawMessageCallback(event.data, event.timeStamp);
+    }
+    
     const [status, data1, data2] = event.data;
     
     // Extract message type and channel
@@ -253,6 +261,10 @@ export function setMIDICallbacks(callbacks) {
     if (callbacks.controlChange) {
         controlChangeCallback = callbacks.controlChange;
     }
+    
+    if (callbacks.rawMessage) {
+        rawMessageCallback = callbacks.rawMessage;
+    }
 }
 
 /**
@@ -0,0 +1,45 @@
+// Offline render worker: bounces run here on a processor of their own, so neither the
+// audio thread nor the page waits for them
+
+// Wasm module, loaded with the first render
+let wasmModule = null;
+
+self.onmessage = async (event) => {
+    const { id, snapshot, options, contextRate } = event.data;
+    
+    try {
+        if (!wasmModule) {
+            const module = await import('/sampler-wasm/pkg/sampler_wasm.js');
+            await module.default();
+            wasmModule = module;
+        }
+        
+        const wav = render(snapshot, options, contextRate);
+        self.postMessage({ id, wav }, [wav.buffer]);
+    } catch (error) {
+        self.postMessage({ id, error: error instanceof Error ? error.message : String(error) });
+    }
+};
+
+// Rebuild the session from the worklet's snapshot, either a project file (`bytes`) or
+// the loaded sample (`sample` and `sampleRate`), and render it to WAV bytes
+function render(snapshot, options, contextRate) {
+    const { duration = 0, events = [], encoding = 0, dither = false, playSample = false, sampleRate = 0 } = options;
+    const processor = new wasmModule.SamplerProcessorState();
+    
+    try {
+        processor.set_output_sample_rate(contextRate);
+        if (snapshot.bytes) {
+            processor.import_project(snapshot.bytes, () => null);
+        } else if (snapshot.sample) {
+            processor.load_sample_data(snapshot.sample, snapshot.sampleRate);
+        }
+        if (playSample) {
+            processor.seek(0);
+            processor.set_playback_state(true);
+        }
+        return processor.render_to_wav(duration, events, encoding, dither, sampleRate);
+    } finally {
+        processor.free();
+    }
+}
\ No newline at end of file
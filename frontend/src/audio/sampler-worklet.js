@@ -63,6 +63,90 @@ class SamplerProcessor extends AudioWorkletProcessor {
                 this.setEffectParameter(data.effectType, data.paramName, data.value);
                 break;
                 
+            case 'scheduleParameter':
+                this.scheduleParameter(data.effectId, data.paramName, data.value, data.frameTime, data.ramp);
+                break;
+                
+            case 'cancelScheduledParameters':
+                this.cancelScheduledParameters(data.effectId, data.paramName, data.frameTime);
+                break;
+                
+            case 'loadZones':
+                this.loadZones(data.zones);
+                break;
+                
+            case 'loadSfz':
+                this.loadSfz(data.text, data.samples);
+                break;
+                
+            case 'loadSf2':
+                this.loadSf2(data.bytes, data.bank, data.program);
+                break;
+                
+            case 'loadAudioFile':
+                this.loadAudioFile(data.bytes, data.name);
+                break;
+                
+            case 'loadMidiFile':
+                this.loadMidiFile(data.bytes);
+                break;
+                
+            case 'tuning':
+                this.setTuning(data.scl, data.kbm, data.referenceNote, data.referenceFrequency);
+                break;
+                
+            case 'resampleQuality':
+                if (this.initialized) {
+                    this.rustProcessor.set_resample_quality(data.quality);
+                }
+                break;
+                
+            case 'renderSnapshot':
+                this.renderSnapshot();
+                break;
+                
+            case 'exportPreset':
+                this.exportPreset(data.binary);
+                break;
+                
+            case 'importPreset':
+                this.importPreset(data.bytes);
+                break;
+                
+            case 'videoReference':
+                if (this.initialized) {
+                    this.rustProcessor.set_video_reference(data.path || '', data.offset || 0);
+                }
+                break;
+                
+            case 'markers':
+                if (this.initialized) {
+                    this.rustProcessor.clear_markers();
+                    for (const marker of data.markers || []) {
+                        this.rustProcessor.add_marker(marker.time, marker.name || '');
+                    }
+                }
+                break;
+                
+            case 'exportProject':
+                this.exportProject(data.embedAudio);
+                break;
+                
+            case 'importProject':
+                this.importProject(data.bytes, data.files);
+                break;
+                
+            case 'midi':
+                this.handleMidi(data.bytes, data.frameOffset);
+                break;
+                
+            case 'clockSync':
+                if (this.initialized) {
+                    this.rustProcessor.set_clock_follow(data.follow);
+                    this.rustProcessor.set_clock_output(data.output);
+                }
+                break;
+                
             default:
                 console.warn('Unknown message type:', data.type);
         }
@@ -76,6 +160,8 @@ class SamplerProcessor extends AudioWorkletProcessor {
             
             // Create the Rust processor
             this.rustProcessor = this.wasmModule.create_processor();
+            this.rustProcessor.set_output_sample_rate(sampleRate);
+            this.rustProcessor.set_current_frame(currentFrame);
             
             this.initialized = true;
             console.log('Wasm processor initialized');
@@ -119,6 +205,122 @@ class SamplerProcessor extends AudioWorkletProcessor {
         }
     }
     
+    // Replace the instrument with multisample zones. Each zone is
+    // { data, sampleRate, name, rootKey, keyRange, velocityRange, tune, gain, pan, loop, roundRobin }
+    // where loop is { mode, start, end } and roundRobin is { group, length, position }.
+    // `name` is the sample's file name, which projects can reference it by.
+    loadZones(zones) {
+        if (!this.initialized) {
+            console.warn('Cannot load zones: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            this.rustProcessor.clear_zones();
+            const groups = new Map();
+            
+            for (const zone of zones) {
+                const index = this.rustProcessor.add_zone(new Float32Array(zone.data), zone.sampleRate, zone.rootKey ?? 60);
+                const [lowKey, highKey] = zone.keyRange ?? [0, 127];
+                const [lowVelocity, highVelocity] = zone.velocityRange ?? [1, 127];
+                this.rustProcessor.set_zone_range(index, lowKey, highKey, lowVelocity, highVelocity);
+                this.rustProcessor.set_zone_tune(index, zone.tune ?? 0);
+                this.rustProcessor.set_zone_gain(index, zone.gain ?? 0);
+                this.rustProcessor.set_zone_pan(index, zone.pan ?? 0);
+                if (zone.name) {
+                    this.rustProcessor.set_zone_sample_name(index, zone.name);
+                }
+                
+                if (zone.loop) {
+                    this.rustProcessor.set_zone_loop(index, zone.loop.mode, zone.loop.start, zone.loop.end);
+                }
+                
+                if (zone.roundRobin) {
+                    if (!groups.has(zone.roundRobin.group)) {
+                        groups.set(zone.roundRobin.group, this.rustProcessor.add_alternation_group(false, zone.roundRobin.length));
+                    }
+                    this.rustProcessor.set_zone_alternation(index, groups.get(zone.roundRobin.group), zone.roundRobin.position);
+                }
+            }
+            
+            this.port.postMessage({ type: 'sampleLoaded' });
+        } catch (error) {
+            console.error('Failed to load zones:', error);
+            this.port.postMessage({ 
+                type: 'error', 
+                message: 'Failed to load zones' 
+            });
+        }
+    }
+    
+    // Load an SFZ instrument. `samples` maps each path from the file (relative to it,
+    // with forward slashes) to { data: Float32Array, sampleRate } decoded on the main thread.
+    loadSfz(text, samples) {
+        if (!this.initialized) {
+            console.warn('Cannot load SFZ: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            const missing = this.rustProcessor.load_sfz(text, (path) => samples[path] ?? null);
+            this.port.postMessage({ type: 'sampleLoaded', missing });
+        } catch (error) {
+            console.error('Failed to load SFZ:', error);
+            this.port.postMessage({ 
+                type: 'error', 
+                message: 'Failed to load SFZ instrument' 
+            });
+        }
+    }
+    
+    // Load a WAV, AIFF or FLAC file as the sample. Its loops, markers and tempo are
+    // posted back; `name` is the file name projects refer to it by.
+    loadAudioFile(bytes, name) {
+        if (!this.initialized) {
+            console.warn('Cannot load audio file: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            this.rustProcessor.load_audio_file(new Uint8Array(bytes));
+            if (name) {
+                this.rustProcessor.set_sample_name(name);
+            }
+            this.port.postMessage({
+                type: 'sampleLoaded',
+                loops: this.rustProcessor.sample_loops(),
+                markers: this.rustProcessor.sample_markers(),
+                tempo: this.rustProcessor.sample_tempo()
+            });
+        } catch (error) {
+            console.error('Failed to load audio file:', error);
+            this.port.postMessage({ 
+                type: 'error', 
+                message: 'Failed to load audio file' 
+            });
+        }
+    }
+    
+    // Load a SoundFont preset; without bank/program the first preset is used.
+    // The preset list is posted back for a preset picker.
+    loadSf2(bytes, bank, program) {
+        if (!this.initialized) {
+            console.warn('Cannot load SoundFont: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            const presets = this.rustProcessor.load_sf2(new Uint8Array(bytes), bank, program);
+            this.port.postMessage({ type: 'sampleLoaded', presets });
+        } catch (error) {
+            console.error('Failed to load SoundFont:', error);
+            this.port.postMessage({ 
+                type: 'error', 
+                message: 'Failed to load SoundFont' 
+            });
+        }
+    }
+    
     // Set playback state (play/pause/stop)
     setPlaybackState(isPlaying, resetPosition = false) {
         if (!this.initialized) {
@@ -147,7 +349,7 @@ class SamplerProcessor extends AudioWorkletProcessor {
         }
         
         try {
-            // Implementation will be added when the Rust processor supports seeking
+            this.rustProcessor.seek(time);
             console.log('Seeking to time:', time);
         } catch (error) {
             console.error('Failed to seek:', error);
@@ -162,8 +364,7 @@ class SamplerProcessor extends AudioWorkletProcessor {
         }
         
         try {
-            // Implementation will be added when the Rust processor supports looping
-            console.log('Loop points set:', startTime, 'to', endTime, 'enabled:', enabled);
+            this.rustProcessor.set_loop_region(startTime, endTime, enabled);
         } catch (error) {
             console.error('Failed to set loop points:', error);
         }
@@ -229,6 +430,206 @@ class SamplerProcessor extends AudioWorkletProcessor {
         }
     }
     
+    // Schedule a sample-accurate parameter change ('step', 'linear' or 'exponential')
+    scheduleParameter(effectId, paramName, value, frameTime, ramp = 'step') {
+        if (!this.initialized) {
+            console.warn('Cannot schedule parameter: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            switch (ramp) {
+                case 'linear':
+                    this.rustProcessor.schedule_linear_ramp(effectId, paramName, value, frameTime);
+                    break;
+                case 'exponential':
+                    this.rustProcessor.schedule_exponential_ramp(effectId, paramName, value, frameTime);
+                    break;
+                default:
+                    this.rustProcessor.schedule_parameter(effectId, paramName, value, frameTime);
+            }
+        } catch (error) {
+            console.error('Failed to schedule parameter:', error);
+        }
+    }
+    
+    // Cancel scheduled parameter changes at or after a frame
+    cancelScheduledParameters(effectId, paramName, frameTime) {
+        if (!this.initialized) {
+            console.warn('Cannot cancel scheduled parameters: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            this.rustProcessor.cancel_scheduled_parameters(effectId, paramName, frameTime);
+        } catch (error) {
+            console.error('Failed to cancel scheduled parameters:', error);
+        }
+    }
+    
+    // Load a Standard MIDI File that plays along with the transport
+    loadMidiFile(bytes) {
+        if (!this.initialized) {
+            console.warn('Cannot load MIDI file: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            this.rustProcessor.load_midi_file(new Uint8Array(bytes));
+            console.log('MIDI file loaded:', this.rustProcessor.midi_file_duration(), 'seconds');
+        } catch (error) {
+            console.error('Failed to load MIDI file:', error);
+        }
+    }
+    
+    // Apply Scala scale/keyboard mapping text and an optional reference pitch;
+    // with neither file the tuning resets to 12-TET
+    setTuning(scl, kbm, referenceNote, referenceFrequency) {
+        if (!this.initialized) {
+            console.warn('Cannot set tuning: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            if (!scl && !kbm) {
+                this.rustProcessor.reset_tuning();
+            }
+            if (scl) {
+                this.rustProcessor.load_scala_scale(scl);
+            }
+            if (kbm) {
+                this.rustProcessor.load_keyboard_mapping(kbm);
+            }
+            if (referenceNote !== undefined && referenceFrequency) {
+                this.rustProcessor.set_reference_pitch(referenceNote, referenceFrequency);
+            }
+        } catch (error) {
+            console.error('Failed to set tuning:', error);
+        }
+    }
+    
+    // Send the session, with its samples embedded, for a bounce on the render worker.
+    // Rendering here would hold up the audio thread for the length of the bounce.
+    renderSnapshot() {
+        if (!this.initialized) {
+            console.warn('Cannot render: Wasm processor not initialized');
+            this.port.postMessage({ type: 'renderSnapshot', error: 'Wasm processor not initialized' });
+            return;
+        }
+        
+        try {
+            const bytes = this.rustProcessor.export_project(true);
+            this.port.postMessage({ type: 'renderSnapshot', bytes }, [bytes.buffer]);
+        } catch (error) {
+            console.error('Failed to snapshot session for rendering:', error);
+            this.port.postMessage({ type: 'renderSnapshot', error: 'Failed to snapshot session' });
+        }
+    }
+    
+    // Send the current patch back as JSON text, or as a Uint8Array in the binary format
+    exportPreset(binary = false) {
+        if (!this.initialized) {
+            console.warn('Cannot export preset: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            if (binary) {
+                const bytes = this.rustProcessor.export_preset_binary();
+                this.port.postMessage({ type: 'preset', bytes }, [bytes.buffer]);
+            } else {
+                this.port.postMessage({ type: 'preset', json: this.rustProcessor.export_preset() });
+            }
+        } catch (error) {
+            console.error('Failed to export preset:', error);
+        }
+    }
+    
+    // Load a preset file's bytes, in either the JSON or the binary format
+    importPreset(bytes) {
+        if (!this.initialized) {
+            console.warn('Cannot import preset: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            this.rustProcessor.import_preset(new Uint8Array(bytes));
+            this.port.postMessage({ type: 'presetLoaded' });
+        } catch (error) {
+            console.error('Failed to import preset:', error);
+            this.port.postMessage({ 
+                type: 'error', 
+                message: 'Failed to import preset' 
+            });
+        }
+    }
+    
+    // Send the whole session back as a project file; without `embedAudio` samples
+    // are stored by name only, and a sample without a name fails the export
+    exportProject(embedAudio = true) {
+        if (!this.initialized) {
+            console.warn('Cannot export project: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            const bytes = this.rustProcessor.export_project(embedAudio);
+            this.port.postMessage({ type: 'project', bytes }, [bytes.buffer]);
+        } catch (error) {
+            console.error('Failed to export project:', error);
+            this.port.postMessage({ 
+                type: 'error', 
+                message: 'Failed to export project' 
+            });
+        }
+    }
+    
+    // Open a project file. `files` maps sample file names to their bytes, for
+    // projects that reference their sample rather than embedding it.
+    importProject(bytes, files = {}) {
+        if (!this.initialized) {
+            console.warn('Cannot import project: Wasm processor not initialized');
+            return;
+        }
+        
+        try {
+            const missing = this.rustProcessor.import_project(new Uint8Array(bytes), (name) => {
+                return files[name] ? new Uint8Array(files[name]) : null;
+            });
+            this.port.postMessage({
+                type: 'projectLoaded',
+                missing,
+                sampleName: this.rustProcessor.sample_name(),
+                sessionMarkers: this.rustProcessor.markers(),
+                video: this.rustProcessor.video_reference(),
+                loopRegion: this.rustProcessor.loop_region(),
+                position: this.rustProcessor.transport_position(),
+                loops: this.rustProcessor.sample_loops(),
+                markers: this.rustProcessor.sample_markers(),
+                tempo: this.rustProcessor.sample_tempo()
+            });
+        } catch (error) {
+            console.error('Failed to import project:', error);
+            this.port.postMessage({ 
+                type: 'error', 
+                message: 'Failed to import project' 
+            });
+        }
+    }
+    
+    // Pass raw MIDI bytes to the Wasm processor, played `frameOffset` frames into the next block
+    handleMidi(bytes, frameOffset = 0) {
+        if (!this.initialized) {
+            return;
+        }
+        
+        try {
+            this.rustProcessor.handle_midi(new Uint8Array(bytes), frameOffset);
+        } catch (error) {
+            console.error('Failed to handle MIDI:', error);
+        }
+    }
+    
     // Process audio
     process(inputs, outputs, parameters) {
         // Skip processing if not initialized
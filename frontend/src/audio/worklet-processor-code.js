@@ -64,6 +64,10 @@ class SamplerProcessor extends AudioWorkletProcessor {
                 this.setEffectParameter(data.effectType, data.paramName, data.value);
                 break;
                 
+            case 'renderSnapshot':
+                this.renderSnapshot();
+                break;
+                
             default:
                 console.warn('Unknown message type:', data.type);
         }
@@ -332,6 +336,22 @@ class SamplerProcessor extends AudioWorkletProcessor {
         }
     }
     
+    // Send the loaded sample for a bounce on the render worker.
+    // Rendering here would hold up the audio thread for the length of the bounce.
+    renderSnapshot() {
+        if (!this.initialized) {
+            console.warn('Cannot render: Wasm processor not initialized');
+            this.port.postMessage({ type: 'renderSnapshot', error: 'Wasm processor not initialized' });
+            return;
+        }
+        
+        const sample = this.sampleBuffer ? this.sampleBuffer.slice() : null;
+        this.port.postMessage(
+            { type: 'renderSnapshot', sample, sampleRate: this.sampleRate },
+            sample ? [sample.buffer] : []
+        );
+    }
+    
     // Process audio
     process(inputs, outputs, parameters) {
         // Skip processing if not initialized
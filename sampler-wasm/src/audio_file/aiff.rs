@@ -0,0 +1,307 @@
+use super::{DecodedAudio, Marker, SampleLoop, SampleMetadata};
+
+// AIFF-C compression types that hold uncompressed samples
+const COMPRESSION_NONE: &[u8] = b"NONE";
+const COMPRESSION_TWOS: &[u8] = b"twos";
+const COMPRESSION_RAW: &[u8] = b"raw ";
+const COMPRESSION_SOWT: &[u8] = b"sowt";
+const COMPRESSION_FLOAT32: [&[u8]; 2] = [b"fl32", b"FL32"];
+const COMPRESSION_FLOAT64: [&[u8]; 2] = [b"fl64", b"FL64"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    BigEndian,
+    LittleEndian,
+    Unsigned,
+    Float,
+}
+
+struct Common {
+    channels: usize,
+    frames: usize,
+    bits: u16,
+    sample_rate: f64,
+    encoding: Encoding,
+}
+
+// INST loop: play mode and the marker ids bounding it
+struct InstrumentLoop {
+    mode: u16,
+    begin: u16,
+    end: u16,
+}
+
+// Decode an AIFF or uncompressed AIFF-C file: integer PCM of 8 to 32 bits (either
+// byte order) or 32/64-bit float, with `MARK` markers and the `INST` root key,
+// detune and sustain/release loops
+pub fn decode(data: &[u8]) -> Result<DecodedAudio, String> {
+    if data.len() < 12 || &data[0..4] != b"FORM" || !matches!(&data[8..12], b"AIFF" | b"AIFC") {
+        return Err("Not an AIFF file".to_string());
+    }
+    let aifc = &data[8..12] == b"AIFC";
+    
+    let mut common = None;
+    let mut samples: &[u8] = &[];
+    let mut markers: Vec<(u16, Marker)> = Vec::new();
+    let mut metadata = SampleMetadata::default();
+    let mut loops = Vec::new();
+    
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = u32_at(data, offset + 4) as usize;
+        let body_start = offset + 8;
+        let body = &data[body_start..body_start.saturating_add(size).min(data.len())];
+        
+        match id {
+            b"COMM" => common = Some(read_common(body, aifc)?),
+            b"SSND" if body.len() >= 8 => {
+                // Sample data starts `offset` bytes into the chunk, after the block size
+                let start = 8usize.saturating_add(u32_at(body, 0) as usize).min(body.len());
+                samples = &body[start..];
+            },
+            b"MARK" => markers = read_markers(body),
+            b"INST" if body.len() >= 20 => {
+                let base_note = body[0] as i8;
+                if (0..=127).contains(&base_note) {
+                    metadata.root_key = Some(base_note as u8);
+                }
+                metadata.fine_tune = body[1] as i8 as f32;
+                loops = vec![read_loop(&body[8..14]), read_loop(&body[14..20])];
+            },
+            _ => {},
+        }
+        
+        offset = body_start.saturating_add(size).saturating_add(size & 1);
+    }
+    
+    let common = common.ok_or_else(|| "AIFF file has no 'COMM' chunk".to_string())?;
+    let channels = decode_samples(samples, &common)?;
+    
+    // Sustain loop first, then release loop; marker positions fall between frames,
+    // so the end marker is exclusive
+    let position = |id: u16| markers.iter().find(|(marker, _)| *marker == id).map(|(_, marker)| marker.position);
+    metadata.loops = loops.iter()
+        .filter(|instrument_loop| instrument_loop.mode != 0)
+        .filter_map(|instrument_loop| {
+            let start = position(instrument_loop.begin)?;
+            let end = position(instrument_loop.end)?;
+            (end > start).then_some(SampleLoop { start, end, ping_pong: instrument_loop.mode == 2 })
+        })
+        .collect();
+    metadata.markers = markers.into_iter().map(|(_, marker)| marker).collect();
+    
+    Ok(DecodedAudio { sample_rate: common.sample_rate as f32, channels, metadata })
+}
+
+fn read_common(body: &[u8], aifc: bool) -> Result<Common, String> {
+    if body.len() < 18 {
+        return Err("AIFF 'COMM' chunk is too short".to_string());
+    }
+    
+    let bits = u16_at(body, 6);
+    let compression = if aifc && body.len() >= 22 { &body[18..22] } else { COMPRESSION_NONE };
+    let encoding = match compression {
+        COMPRESSION_NONE | COMPRESSION_TWOS => Encoding::BigEndian,
+        COMPRESSION_SOWT => Encoding::LittleEndian,
+        COMPRESSION_RAW if bits == 8 => Encoding::Unsigned,
+        _ if COMPRESSION_FLOAT32.contains(&compression) || COMPRESSION_FLOAT64.contains(&compression) => Encoding::Float,
+        _ => return Err(format!("Unsupported AIFF-C compression '{}'", String::from_utf8_lossy(compression))),
+    };
+    
+    let common = Common {
+        channels: u16_at(body, 0) as usize,
+        frames: u32_at(body, 2) as usize,
+        bits: match compression {
+            _ if COMPRESSION_FLOAT32.contains(&compression) => 32,
+            _ if COMPRESSION_FLOAT64.contains(&compression) => 64,
+            _ => bits,
+        },
+        sample_rate: extended_at(body, 8),
+        encoding,
+    };
+    
+    if common.channels == 0 || common.sample_rate.is_nan() || common.sample_rate <= 0.0 {
+        return Err("AIFF file has no channels or sample rate".to_string());
+    }
+    if common.encoding != Encoding::Float && !(1..=32).contains(&common.bits) {
+        return Err(format!("Unsupported AIFF sample size ({} bits)", common.bits));
+    }
+    Ok(common)
+}
+
+fn decode_samples(data: &[u8], common: &Common) -> Result<Vec<Vec<f32>>, String> {
+    // Samples narrower than a byte multiple are left-justified in whole bytes
+    let bytes = (common.bits as usize).div_ceil(8);
+    let block_align = bytes * common.channels;
+    let frames = common.frames.min(data.len() / block_align);
+    
+    let decode_one: fn(&[u8]) -> f32 = match (common.encoding, bytes) {
+        (Encoding::Unsigned, 1) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (Encoding::BigEndian, 1) | (Encoding::LittleEndian, 1) => |b| b[0] as i8 as f32 / 128.0,
+        (Encoding::BigEndian, 2) => |b| i16::from_be_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (Encoding::LittleEndian, 2) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (Encoding::BigEndian, 3) => |b| (i32::from_be_bytes([b[0], b[1], b[2], 0]) >> 8) as f32 / 8_388_608.0,
+        (Encoding::LittleEndian, 3) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (Encoding::BigEndian, 4) => |b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (Encoding::LittleEndian, 4) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (Encoding::Float, 4) => |b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+        (Encoding::Float, 8) => |b| f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        _ => return Err("Unsupported AIFF encoding".to_string()),
+    };
+    
+    let mut channels = vec![Vec::with_capacity(frames); common.channels];
+    for frame in data.chunks_exact(block_align).take(frames) {
+        for (channel, buffer) in channels.iter_mut().enumerate() {
+            buffer.push(decode_one(&frame[channel * bytes..(channel + 1) * bytes]));
+        }
+    }
+    Ok(channels)
+}
+
+// `MARK`: (marker id, marker) with Pascal-string names padded to an even length
+fn read_markers(body: &[u8]) -> Vec<(u16, Marker)> {
+    let mut markers = Vec::new();
+    if body.len() < 2 {
+        return markers;
+    }
+    
+    let count = u16_at(body, 0);
+    let mut offset = 2;
+    for _ in 0..count {
+        if offset + 7 > body.len() {
+            break;
+        }
+        let id = u16_at(body, offset);
+        let position = u32_at(body, offset + 2) as usize;
+        let length = body[offset + 6] as usize;
+        let name_end = (offset + 7 + length).min(body.len());
+        let name = String::from_utf8_lossy(&body[offset + 7..name_end]).into_owned();
+        markers.push((id, Marker { position, name }));
+        
+        // Count byte plus text is padded to an even length
+        offset += 6 + ((2 + length) & !1);
+    }
+    markers
+}
+
+fn read_loop(body: &[u8]) -> InstrumentLoop {
+    InstrumentLoop {
+        mode: u16_at(body, 0),
+        begin: u16_at(body, 2),
+        end: u16_at(body, 4),
+    }
+}
+
+// 80-bit IEEE 754 extended precision, as used for the AIFF sample rate
+fn extended_at(data: &[u8], offset: usize) -> f64 {
+    let exponent = (u16_at(data, offset) & 0x7FFF) as i32;
+    let mut mantissa = [0; 8];
+    mantissa.copy_from_slice(&data[offset + 2..offset + 10]);
+    let mantissa = u64::from_be_bytes(mantissa);
+    
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+    if data[offset] & 0x80 != 0 { -value } else { value }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // 44100 Hz as an 80-bit extended float
+    const RATE_44100: [u8; 10] = [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0];
+    
+    const LEFT: [i16; 3] = [16384, -32768, 1];
+    const RIGHT: [i16; 3] = [-16384, 32767, 0];
+    
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(body);
+        if body.len() & 1 == 1 {
+            out.push(0);
+        }
+        out
+    }
+    
+    // Stereo 16-bit file of LEFT/RIGHT with two markers and a sustain loop between them.
+    // AIFF-C files use little-endian `sowt` samples.
+    fn file(aifc: bool) -> Vec<u8> {
+        let mut common = vec![0, 2, 0, 0, 0, 3, 0, 16];
+        common.extend_from_slice(&RATE_44100);
+        if aifc {
+            // Compression type, then an empty Pascal-string name
+            common.extend_from_slice(b"sowt\x00\x00");
+        }
+        let bytes = |sample: i16| if aifc { sample.to_le_bytes() } else { sample.to_be_bytes() };
+        let mut sound = vec![0; 8];
+        for (&left, &right) in LEFT.iter().zip(&RIGHT) {
+            sound.extend_from_slice(&bytes(left));
+            sound.extend_from_slice(&bytes(right));
+        }
+        
+        let mut markers = vec![0, 2];
+        markers.extend_from_slice(&[0, 1, 0, 0, 0, 1, 1, b'A']);
+        markers.extend_from_slice(&[0, 2, 0, 0, 0, 3, 3, b'E', b'n', b'd']);
+        
+        // Root key 60 detuned -10 cents, forward sustain loop from marker 1 to 2
+        let mut instrument = vec![60, 0xF6, 0, 127, 1, 127, 0, 0];
+        instrument.extend_from_slice(&[0, 1, 0, 1, 0, 2]);
+        instrument.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        
+        let mut body = if aifc { b"AIFC".to_vec() } else { b"AIFF".to_vec() };
+        body.extend(chunk(b"COMM", &common));
+        body.extend(chunk(b"SSND", &sound));
+        body.extend(chunk(b"MARK", &markers));
+        body.extend(chunk(b"INST", &instrument));
+        
+        let mut out = b"FORM".to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend(body);
+        out
+    }
+    
+    #[test]
+    fn decodes_known_frames() {
+        let expected = |samples: &[i16]| samples.iter().map(|&sample| sample as f32 / 32768.0).collect::<Vec<_>>();
+        for aifc in [false, true] {
+            let decoded = decode(&file(aifc)).unwrap();
+            assert_eq!(decoded.sample_rate, 44100.0);
+            assert_eq!(decoded.channels, vec![expected(&LEFT), expected(&RIGHT)]);
+            
+            let metadata = decoded.metadata;
+            assert_eq!(metadata.root_key, Some(60));
+            assert_eq!(metadata.fine_tune, -10.0);
+            assert_eq!(metadata.loops, vec![SampleLoop { start: 1, end: 3, ping_pong: false }]);
+            assert_eq!(metadata.markers, vec![
+                Marker { position: 1, name: "A".to_string() },
+                Marker { position: 3, name: "End".to_string() },
+            ]);
+        }
+    }
+    
+    #[test]
+    fn truncated_input_is_rejected() {
+        // The 'COMM' chunk ends 38 bytes in; shorter prefixes lack the format
+        let file = file(false);
+        for len in 0..file.len() {
+            match decode(&file[..len]) {
+                Ok(decoded) => {
+                    assert!(len >= 38);
+                    assert!(decoded.frames() <= 3);
+                },
+                Err(_) => assert!(len < 38),
+            }
+        }
+    }
+}
\ No newline at end of file
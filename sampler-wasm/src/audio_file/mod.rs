@@ -0,0 +1,74 @@
+// Audio file decoders that keep the loop and marker metadata `decodeAudioData` drops,
+// and the WAV encoder used for offline renders
+pub mod wav;
+pub mod aiff;
+pub mod flac;
+
+pub use wav::WavEncoding;
+
+use serde::{Deserialize, Serialize};
+
+// A loop region in sample frames
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SampleLoop {
+    pub start: usize,
+    pub end: usize,  // Exclusive
+    pub ping_pong: bool,
+}
+
+// A named position in sample frames
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub position: usize,
+    pub name: String,
+}
+
+// What a file says about its sample beyond the audio itself
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampleMetadata {
+    pub loops: Vec<SampleLoop>,
+    pub markers: Vec<Marker>,
+    pub root_key: Option<u8>,
+    pub fine_tune: f32,          // Cents
+    pub tempo: Option<f32>,      // BPM
+    pub beats: Option<u32>,      // Length in beats
+}
+
+impl SampleMetadata {
+    // Rescale loop and marker positions after a sample-rate conversion
+    pub fn scale_positions(&mut self, ratio: f64) {
+        let scale = |position: usize| (position as f64 * ratio).round() as usize;
+        for sample_loop in &mut self.loops {
+            sample_loop.start = scale(sample_loop.start);
+            sample_loop.end = scale(sample_loop.end);
+        }
+        for marker in &mut self.markers {
+            marker.position = scale(marker.position);
+        }
+    }
+}
+
+// Decoded audio as one buffer per channel
+#[derive(Debug, Clone, Default)]
+pub struct DecodedAudio {
+    pub sample_rate: f32,
+    pub channels: Vec<Vec<f32>>,
+    pub metadata: SampleMetadata,
+}
+
+impl DecodedAudio {
+    pub fn frames(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+}
+
+// Decode a file, choosing the format from its header
+pub fn decode(data: &[u8]) -> Result<DecodedAudio, String> {
+    match data.get(0..4) {
+        Some(b"RIFF") | Some(b"RF64") => wav::decode(data),
+        Some(b"FORM") => aiff::decode(data),
+        Some(b"fLaC") => flac::decode(data),
+        Some(tag) if tag.starts_with(b"ID3") => flac::decode(data),
+        _ => Err("Unsupported audio file format".to_string()),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,392 @@
+use super::{DecodedAudio, Marker, SampleLoop, SampleMetadata};
+use crate::utils::XorShiftRng;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// RF64 chunk sizes of 0xFFFFFFFF defer to the 64-bit sizes in `ds64`
+const RF64_SIZE_PLACEHOLDER: u32 = 0xFFFF_FFFF;
+
+// Sample encodings written by `encode`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WavEncoding {
+    Pcm16,
+    Pcm24,
+    Float32,
+}
+
+impl WavEncoding {
+    pub fn from_index(index: u32) -> Self {
+        match index {
+            1 => WavEncoding::Pcm24,
+            2 => WavEncoding::Float32,
+            _ => WavEncoding::Pcm16,
+        }
+    }
+    
+    fn bits(self) -> u16 {
+        match self {
+            WavEncoding::Pcm16 => 16,
+            WavEncoding::Pcm24 => 24,
+            WavEncoding::Float32 => 32,
+        }
+    }
+}
+
+struct Format {
+    format: u16,
+    channels: usize,
+    sample_rate: u32,
+    bits: u16,
+    block_align: usize,
+}
+
+// Decode a RIFF/WAVE or RF64 file: integer PCM of 8, 16, 24 or 32 bits or 32/64-bit
+// float, with `smpl` loops and root key, `cue` points (named by `LIST`/`adtl`
+// labels) and `acid` tempo
+pub fn decode(data: &[u8]) -> Result<DecodedAudio, String> {
+    if data.len() < 12 || !matches!(&data[0..4], b"RIFF" | b"RF64") || &data[8..12] != b"WAVE" {
+        return Err("Not a WAV file".to_string());
+    }
+    let rf64 = &data[0..4] == b"RF64";
+    
+    let mut format = None;
+    let mut samples: &[u8] = &[];
+    let mut data_size_64 = None;
+    let mut metadata = RiffMetadata::default();
+    
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let declared = u32_at(data, offset + 4);
+        let body_start = offset + 8;
+        // Streaming writers leave the placeholder size in plain RIFF too, meaning "to the end of the file"
+        let size = match (id, declared) {
+            (b"data", RF64_SIZE_PLACEHOLDER) => data_size_64.filter(|_| rf64).unwrap_or(data.len() - body_start),
+            _ => declared as usize,
+        };
+        let body = &data[body_start..body_start.saturating_add(size).min(data.len())];
+        
+        match id {
+            b"ds64" if body.len() >= 16 => data_size_64 = usize::try_from(u64_at(body, 8)).ok(),
+            b"fmt " => format = Some(read_format(body)?),
+            b"data" => samples = body,
+            _ => metadata.read_chunk(id, body),
+        }
+        
+        offset = body_start.saturating_add(size).saturating_add(size & 1);
+    }
+    
+    let format = format.ok_or_else(|| "WAV file has no 'fmt ' chunk".to_string())?;
+    let channels = decode_samples(samples, &format)?;
+    
+    Ok(DecodedAudio { sample_rate: format.sample_rate as f32, channels, metadata: metadata.finish() })
+}
+
+// Sample metadata gathered from RIFF chunks. FLAC files keep the same chunks in
+// `riff` application blocks, so they share this reader.
+#[derive(Default)]
+pub(super) struct RiffMetadata {
+    metadata: SampleMetadata,
+    cues: Vec<(u32, usize)>,
+    labels: Vec<(u32, String)>,
+}
+
+impl RiffMetadata {
+    pub(super) fn read_chunk(&mut self, id: &[u8], body: &[u8]) {
+        match id {
+            b"smpl" => read_smpl(body, &mut self.metadata),
+            b"cue " => self.cues = read_cues(body),
+            b"LIST" if body.get(0..4) == Some(b"adtl") => self.labels = read_labels(&body[4..]),
+            b"acid" if body.len() >= 24 => {
+                self.metadata.beats = Some(u32_at(body, 12)).filter(|&beats| beats > 0);
+                self.metadata.tempo = Some(f32::from_le_bytes([body[20], body[21], body[22], body[23]]))
+                    .filter(|tempo| tempo.is_finite() && *tempo > 0.0);
+            },
+            _ => {},
+        }
+    }
+    
+    // Read a run of chunks, as found after the RIFF header
+    pub(super) fn read_chunks(&mut self, mut data: &[u8]) {
+        while data.len() >= 8 {
+            let size = (u32_at(data, 4) as usize).min(data.len() - 8);
+            self.read_chunk(&data[0..4], &data[8..8 + size]);
+            data = &data[(8 + size + (size & 1)).min(data.len())..];
+        }
+    }
+    
+    // Name the cue points from their labels
+    pub(super) fn finish(mut self) -> SampleMetadata {
+        let labels = self.labels;
+        self.metadata.markers = self.cues.into_iter()
+            .map(|(id, position)| Marker {
+                position,
+                name: labels.iter().find(|(label, _)| *label == id).map(|(_, name)| name.clone()).unwrap_or_default(),
+            })
+            .collect();
+        self.metadata
+    }
+}
+
+// Encode planar channels of equal length as a RIFF/WAVE file. Integer encodings clip
+// at full scale; `dither` adds triangular (TPDF) noise of +/-1 LSB before rounding.
+pub fn encode(channels: &[&[f32]], sample_rate: u32, encoding: WavEncoding, dither: bool) -> Vec<u8> {
+    let channel_count = channels.len().max(1);
+    let frames = channels.iter().map(|channel| channel.len()).min().unwrap_or(0);
+    let bytes = encoding.bits() as usize / 8;
+    let block_align = channel_count * bytes;
+    let data_size = frames * block_align;
+    let format_tag = if encoding == WavEncoding::Float32 { FORMAT_FLOAT } else { FORMAT_PCM };
+    
+    // Float files carry a `fact` chunk with the frame count
+    let fact_size = if encoding == WavEncoding::Float32 { 12 } else { 0 };
+    let mut out = Vec::with_capacity(44 + fact_size + data_size);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&((36 + fact_size + data_size) as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&format_tag.to_le_bytes());
+    out.extend_from_slice(&(channel_count as u16).to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    out.extend_from_slice(&(block_align as u16).to_le_bytes());
+    out.extend_from_slice(&encoding.bits().to_le_bytes());
+    
+    if encoding == WavEncoding::Float32 {
+        out.extend_from_slice(b"fact");
+        out.extend_from_slice(&4u32.to_le_bytes());
+        out.extend_from_slice(&(frames as u32).to_le_bytes());
+    }
+    
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(data_size as u32).to_le_bytes());
+    
+    let mut rng = XorShiftRng::new(0xD17E);
+    let full_scale = match encoding {
+        WavEncoding::Pcm16 => 32768.0,
+        WavEncoding::Pcm24 => 8_388_608.0,
+        WavEncoding::Float32 => 1.0,
+    };
+    for frame in 0..frames {
+        for channel in 0..channel_count {
+            let sample = channels.get(channel).map_or(0.0, |samples| samples[frame]);
+            if encoding == WavEncoding::Float32 {
+                out.extend_from_slice(&sample.to_le_bytes());
+                continue;
+            }
+            
+            let noise = if dither { 0.5 * (rng.next_bipolar() + rng.next_bipolar()) } else { 0.0 };
+            let value = (sample as f64 * full_scale + noise as f64).round().clamp(-full_scale, full_scale - 1.0) as i32;
+            out.extend_from_slice(&value.to_le_bytes()[..bytes]);
+        }
+    }
+    out
+}
+
+fn read_format(body: &[u8]) -> Result<Format, String> {
+    if body.len() < 16 {
+        return Err("WAV 'fmt ' chunk is too short".to_string());
+    }
+    
+    let mut format = u16_at(body, 0);
+    if format == FORMAT_EXTENSIBLE && body.len() >= 26 {
+        // The sub-format GUID starts with the plain format tag
+        format = u16_at(body, 24);
+    }
+    
+    let format = Format {
+        format,
+        channels: u16_at(body, 2) as usize,
+        sample_rate: u32_at(body, 4),
+        block_align: u16_at(body, 12) as usize,
+        bits: u16_at(body, 14),
+    };
+    
+    let supported = match format.format {
+        FORMAT_PCM => matches!(format.bits, 8 | 16 | 24 | 32),
+        FORMAT_FLOAT => matches!(format.bits, 32 | 64),
+        _ => false,
+    };
+    if !supported {
+        return Err(format!("Unsupported WAV encoding (format {}, {} bits)", format.format, format.bits));
+    }
+    if format.channels == 0 || format.sample_rate == 0 {
+        return Err("WAV file has no channels or sample rate".to_string());
+    }
+    Ok(format)
+}
+
+fn decode_samples(data: &[u8], format: &Format) -> Result<Vec<Vec<f32>>, String> {
+    let bytes = format.bits as usize / 8;
+    let block_align = format.block_align.max(bytes * format.channels);
+    let frames = data.len() / block_align;
+    
+    let decode_one: fn(&[u8]) -> f32 = match (format.format, format.bits) {
+        (FORMAT_PCM, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (FORMAT_PCM, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (FORMAT_PCM, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (FORMAT_PCM, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (FORMAT_FLOAT, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (FORMAT_FLOAT, 64) => |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        _ => return Err("Unsupported WAV encoding".to_string()),
+    };
+    
+    let mut channels = vec![Vec::with_capacity(frames); format.channels];
+    for frame in data.chunks_exact(block_align) {
+        for (channel, buffer) in channels.iter_mut().enumerate() {
+            buffer.push(decode_one(&frame[channel * bytes..(channel + 1) * bytes]));
+        }
+    }
+    Ok(channels)
+}
+
+// `smpl`: MIDI unity note and pitch fraction, then 24-byte loop records whose
+// end is the last frame played
+fn read_smpl(body: &[u8], metadata: &mut SampleMetadata) {
+    if body.len() < 36 {
+        return;
+    }
+    
+    let unity_note = u32_at(body, 12);
+    if unity_note <= 127 {
+        metadata.root_key = Some(unity_note as u8);
+    }
+    metadata.fine_tune = u32_at(body, 16) as f32 / 4_294_967_296.0 * 100.0;
+    
+    let count = u32_at(body, 28) as usize;
+    metadata.loops = body[36..].chunks_exact(24)
+        .take(count)
+        .filter_map(|record| {
+            let kind = u32_at(record, 4);
+            let start = u32_at(record, 8) as usize;
+            let end = (u32_at(record, 12) as usize).checked_add(1)?;
+            (end > start).then_some(SampleLoop { start, end, ping_pong: kind == 1 })
+        })
+        .collect();
+}
+
+// `cue `: (cue id, sample offset) pairs
+fn read_cues(body: &[u8]) -> Vec<(u32, usize)> {
+    if body.len() < 4 {
+        return Vec::new();
+    }
+    body[4..].chunks_exact(24)
+        .take(u32_at(body, 0) as usize)
+        .map(|record| (u32_at(record, 0), u32_at(record, 20) as usize))
+        .collect()
+}
+
+// `labl` sub-chunks of an `adtl` list: (cue id, name)
+fn read_labels(mut body: &[u8]) -> Vec<(u32, String)> {
+    let mut labels = Vec::new();
+    while body.len() >= 8 {
+        let size = (u32_at(body, 4) as usize).min(body.len() - 8);
+        let chunk = &body[8..8 + size];
+        if &body[0..4] == b"labl" && chunk.len() >= 4 {
+            let text = &chunk[4..];
+            let end = text.iter().position(|&byte| byte == 0).unwrap_or(text.len());
+            labels.push((u32_at(chunk, 0), String::from_utf8_lossy(&text[..end]).into_owned()));
+        }
+        body = &body[(8 + size + (size & 1)).min(body.len())..];
+    }
+    labels
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        if body.len() & 1 == 1 {
+            out.push(0);
+        }
+        out
+    }
+    
+    fn words(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+    
+    #[test]
+    fn encode_decode_round_trip() {
+        let left = [0.0, 0.5, -0.5, -1.0, 0.25];
+        let right = [0.125, -0.25, 0.75, 0.5, 0.0];
+        for encoding in [WavEncoding::Pcm16, WavEncoding::Pcm24, WavEncoding::Float32] {
+            let decoded = decode(&encode(&[&left, &right], 44100, encoding, false)).unwrap();
+            assert_eq!(decoded.sample_rate, 44100.0);
+            assert_eq!(decoded.channels, vec![left.to_vec(), right.to_vec()]);
+        }
+    }
+    
+    #[test]
+    fn decodes_smpl_and_cue_chunks() {
+        let mut file = encode(&[&[0.0; 64]], 48000, WavEncoding::Pcm16, false);
+        
+        // Unity note 60 plus half a semitone, one forward loop over frames 8..=31 and
+        // one ping-pong loop over 40..=47
+        let mut smpl = words(&[0, 0, 20833, 60, 0x8000_0000, 0, 0, 2, 0]);
+        smpl.extend(words(&[1, 0, 8, 31, 0, 0]));
+        smpl.extend(words(&[2, 1, 40, 47, 0, 0]));
+        file.extend(chunk(b"smpl", &smpl));
+        
+        let mut cue = words(&[2]);
+        cue.extend(words(&[1, 0, u32::from_le_bytes(*b"data"), 0, 0, 16]));
+        cue.extend(words(&[2, 0, u32::from_le_bytes(*b"data"), 0, 0, 48]));
+        file.extend(chunk(b"cue ", &cue));
+        
+        let mut label = words(&[2]);
+        label.extend_from_slice(b"Tail\0");
+        let mut list = b"adtl".to_vec();
+        list.extend(chunk(b"labl", &label));
+        file.extend(chunk(b"LIST", &list));
+        
+        let metadata = decode(&file).unwrap().metadata;
+        assert_eq!(metadata.root_key, Some(60));
+        assert_eq!(metadata.fine_tune, 50.0);
+        assert_eq!(metadata.loops, vec![
+            SampleLoop { start: 8, end: 32, ping_pong: false },
+            SampleLoop { start: 40, end: 48, ping_pong: true },
+        ]);
+        assert_eq!(metadata.markers, vec![
+            Marker { position: 16, name: String::new() },
+            Marker { position: 48, name: "Tail".to_string() },
+        ]);
+    }
+    
+    #[test]
+    fn truncated_input_is_rejected() {
+        let file = encode(&[&[0.5; 16], &[-0.5; 16]], 48000, WavEncoding::Pcm24, false);
+        
+        // Anything cut before the end of the 'fmt ' chunk is an error; past it the
+        // file decodes to however many whole frames are left
+        for len in 0..file.len() {
+            match decode(&file[..len]) {
+                Ok(decoded) => {
+                    assert!(len >= 36);
+                    assert!(decoded.frames() <= 16);
+                },
+                Err(_) => assert!(len < 36),
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+
+use crate::effects::EffectsChain;
+
+// Anything automation can read and write effect parameters through
+pub trait ParameterTarget {
+    fn get_parameter(&self, effect_id: usize, param: &str) -> Option<f32>;
+    fn set_parameter(&mut self, effect_id: usize, param: &str, value: f32);
+}
+
+// Plain effect chains (one per output channel) are written in lockstep
+impl ParameterTarget for [EffectsChain] {
+    fn get_parameter(&self, effect_id: usize, param: &str) -> Option<f32> {
+        self.first().and_then(|chain| chain.get_effect_parameter(effect_id, param))
+    }
+    
+    fn set_parameter(&mut self, effect_id: usize, param: &str, value: f32) {
+        for chain in self.iter_mut() {
+            chain.set_effect_parameter(effect_id, param, value);
+        }
+    }
+}
+
+// How a scheduled value is reached, mirroring the Web Audio AudioParam methods
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RampType {
+    Step,        // setValueAtTime
+    Linear,      // linearRampToValueAtTime
+    Exponential, // exponentialRampToValueAtTime
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutomationEvent {
+    pub frame: u64,
+    pub value: f32,
+    pub ramp: RampType,
+}
+
+// All scheduled events for a single effect parameter
+pub struct AutomationLane {
+    effect_id: usize,
+    param: String,
+    events: VecDeque<AutomationEvent>,
+    
+    // Where the ramp towards the next event starts from
+    start_frame: u64,
+    start_value: Option<f32>,
+}
+
+impl AutomationLane {
+    pub fn new(effect_id: usize, param: &str) -> Self {
+        AutomationLane {
+            effect_id,
+            param: param.to_string(),
+            events: VecDeque::new(),
+            start_frame: 0,
+            start_value: None,
+        }
+    }
+    
+    pub fn matches(&self, effect_id: usize, param: &str) -> bool {
+        self.effect_id == effect_id && self.param == param
+    }
+    
+    pub fn insert(&mut self, event: AutomationEvent) {
+        // Keep events sorted by frame; events at the same frame keep their insertion order
+        let position = self.events.iter()
+            .position(|existing| existing.frame > event.frame)
+            .unwrap_or(self.events.len());
+        self.events.insert(position, event);
+    }
+    
+    // Remove every event scheduled at or after the given frame
+    pub fn cancel_from(&mut self, frame: u64) {
+        self.events.retain(|event| event.frame < frame);
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+    
+    // Compute the parameter value at `frame`, or None if nothing changes on this frame.
+    // `current` is the parameter's present value, used when a ramp has no preceding event.
+    pub fn value_at(&mut self, frame: u64, current: f32) -> Option<f32> {
+        let mut value = None;
+        
+        // Consume every event that has been reached
+        while let Some(event) = self.events.front().copied() {
+            if event.frame > frame {
+                break;
+            }
+            
+            self.events.pop_front();
+            self.start_frame = event.frame;
+            self.start_value = Some(event.value);
+            value = Some(event.value);
+        }
+        
+        if value.is_some() {
+            return value;
+        }
+        
+        let next = self.events.front().copied()?;
+        if next.ramp == RampType::Step {
+            return None;
+        }
+        
+        // A ramp with no preceding event starts from wherever the parameter is now
+        let start_value = match self.start_value {
+            Some(start_value) => start_value,
+            None => {
+                self.start_frame = frame;
+                self.start_value = Some(current);
+                current
+            }
+        };
+        
+        let duration = next.frame.saturating_sub(self.start_frame).max(1) as f32;
+        let t = (frame.saturating_sub(self.start_frame) as f32 / duration).clamp(0.0, 1.0);
+        
+        match next.ramp {
+            RampType::Linear => Some(start_value + (next.value - start_value) * t),
+            RampType::Exponential => {
+                // Like AudioParam, an exponential ramp between values of different sign
+                // (or involving zero) holds the start value until the end time
+                if start_value == 0.0 || next.value == 0.0 || (start_value < 0.0) != (next.value < 0.0) {
+                    Some(start_value)
+                } else {
+                    Some(start_value * (next.value / start_value).powf(t))
+                }
+            },
+            RampType::Step => None,
+        }
+    }
+}
+
+// Timestamped parameter changes, consumed frame by frame inside the render loop
+pub struct AutomationQueue {
+    lanes: Vec<AutomationLane>,
+}
+
+impl AutomationQueue {
+    pub fn new() -> Self {
+        AutomationQueue {
+            lanes: Vec::new(),
+        }
+    }
+    
+    pub fn schedule(&mut self, effect_id: usize, param: &str, value: f32, frame: u64, ramp: RampType) {
+        let event = AutomationEvent { frame, value, ramp };
+        
+        match self.lanes.iter_mut().find(|lane| lane.matches(effect_id, param)) {
+            Some(lane) => lane.insert(event),
+            None => {
+                let mut lane = AutomationLane::new(effect_id, param);
+                lane.insert(event);
+                self.lanes.push(lane);
+            }
+        }
+    }
+    
+    pub fn cancel(&mut self, effect_id: usize, param: &str, from_frame: u64) {
+        for lane in self.lanes.iter_mut().filter(|lane| lane.matches(effect_id, param)) {
+            lane.cancel_from(from_frame);
+        }
+    }
+    
+    pub fn clear(&mut self) {
+        self.lanes.clear();
+    }
+    
+    pub fn has_pending(&self) -> bool {
+        self.lanes.iter().any(|lane| !lane.is_empty())
+    }
+    
+    // Apply every value due at `frame` to the target
+    pub fn apply<T: ParameterTarget + ?Sized>(&mut self, frame: u64, target: &mut T) {
+        for lane in &mut self.lanes {
+            if lane.is_empty() {
+                continue;
+            }
+            
+            let current = target.get_parameter(lane.effect_id, &lane.param).unwrap_or(0.0);
+            
+            if let Some(value) = lane.value_at(frame, current) {
+                target.set_parameter(lane.effect_id, &lane.param, value);
+            }
+        }
+    }
+}
+
+impl Default for AutomationQueue {
+    fn default() -> Self {
+        AutomationQueue::new()
+    }
+}
\ No newline at end of file
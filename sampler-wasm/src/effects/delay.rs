@@ -1,5 +1,9 @@
 use super::Effect;
 
+// Longest delay time. The line is allocated for it up front, so changing the time,
+// which modulation does from the audio thread, never allocates.
+const MAX_DELAY_SECONDS: f32 = 4.0;
+
 pub struct Delay {
     buffer: Vec<f32>,
     write_pos: usize,
@@ -11,45 +15,22 @@ pub struct Delay {
 
 impl Delay {
     pub fn new(delay_time_seconds: f32, feedback: f32, mix: f32, sample_rate: f32) -> Self {
-        // Calculate delay in samples
-        let delay_samples = (delay_time_seconds * sample_rate) as usize;
-        
-        // Create a buffer with some extra room
-        let buffer_size = delay_samples + 1;
-        let mut buffer = Vec::with_capacity(buffer_size);
-        buffer.resize(buffer_size, 0.0);
-        
-        Delay {
-            buffer,
+        let mut delay = Delay {
+            buffer: vec![0.0; max_delay_samples(sample_rate) + 1],
             write_pos: 0,
-            delay_samples,
+            delay_samples: 0,
             feedback: feedback.clamp(0.0, 0.99), // Prevent unstable feedback
             mix: mix.clamp(0.0, 1.0),
             sample_rate,
-        }
+        };
+        delay.set_delay_time(delay_time_seconds);
+        delay
     }
     
+    // Only moves the read position within the preallocated line
     pub fn set_delay_time(&mut self, delay_time_seconds: f32) {
-        // Calculate new delay in samples
-        let new_delay_samples = (delay_time_seconds * self.sample_rate) as usize;
-        
-        // If we need a larger buffer, resize it
-        if new_delay_samples >= self.buffer.len() {
-            let new_buffer_size = new_delay_samples + 1;
-            let mut new_buffer = Vec::with_capacity(new_buffer_size);
-            new_buffer.resize(new_buffer_size, 0.0);
-            
-            // Copy old buffer contents to new buffer
-            for i in 0..self.buffer.len() {
-                let read_pos = (self.write_pos + i) % self.buffer.len();
-                new_buffer[i] = self.buffer[read_pos];
-            }
-            
-            self.buffer = new_buffer;
-            self.write_pos = 0;
-        }
-        
-        self.delay_samples = new_delay_samples;
+        let delay_samples = (delay_time_seconds.max(0.0) * self.sample_rate) as usize;
+        self.delay_samples = delay_samples.clamp(1, self.buffer.len() - 1);
     }
     
     pub fn set_feedback(&mut self, feedback: f32) {
@@ -87,6 +68,15 @@ impl Effect for Delay {
         self.write_pos = 0;
     }
     
+    // Reallocates the line for the new rate, dropping what it held
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        let delay_time = self.delay_samples as f32 / self.sample_rate;
+        self.sample_rate = sample_rate;
+        self.buffer = vec![0.0; max_delay_samples(sample_rate) + 1];
+        self.write_pos = 0;
+        self.set_delay_time(delay_time);
+    }
+    
     fn set_parameter(&mut self, name: &str, value: f32) -> bool {
         match name {
             "time" => {
@@ -105,7 +95,24 @@ impl Effect for Delay {
         }
     }
     
+    fn get_parameter(&self, name: &str) -> Option<f32> {
+        match name {
+            "time" => Some(self.delay_samples as f32 / self.sample_rate),
+            "feedback" => Some(self.feedback),
+            "mix" => Some(self.mix),
+            _ => None,
+        }
+    }
+    
+    fn parameter_names(&self) -> &[&'static str] {
+        &["time", "feedback", "mix"]
+    }
+    
     fn name(&self) -> &str {
         "Delay"
     }
+}
+
+fn max_delay_samples(sample_rate: f32) -> usize {
+    ((MAX_DELAY_SECONDS * sample_rate) as usize).max(1)
 }
\ No newline at end of file
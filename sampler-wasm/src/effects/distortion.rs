@@ -102,6 +102,10 @@ impl Effect for Distortion {
         // Distortion has no state to reset
     }
     
+    fn set_sample_rate(&mut self, _sample_rate: f32) {
+        // Distortion doesn't depend on the sample rate
+    }
+    
     fn set_parameter(&mut self, name: &str, value: f32) -> bool {
         match name {
             "drive" => {
@@ -137,6 +141,27 @@ impl Effect for Distortion {
         }
     }
     
+    fn get_parameter(&self, name: &str) -> Option<f32> {
+        match name {
+            "drive" => Some(self.drive),
+            "mix" => Some(self.mix),
+            "output_gain" => Some(self.output_gain),
+            "type" => Some(match self.distortion_type {
+                DistortionType::Soft => 0.0,
+                DistortionType::Hard => 1.0,
+                DistortionType::Foldback => 2.0,
+                DistortionType::Sine => 3.0,
+                DistortionType::Bitcrush => 4.0,
+            }),
+            "bit_depth" => Some(self.bit_depth as f32),
+            _ => None,
+        }
+    }
+    
+    fn parameter_names(&self) -> &[&'static str] {
+        &["drive", "mix", "output_gain", "type", "bit_depth"]
+    }
+    
     fn name(&self) -> &str {
         "Distortion"
     }
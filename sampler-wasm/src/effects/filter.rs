@@ -1,6 +1,7 @@
 use super::Effect;
 use std::f32::consts::PI;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FilterType {
     LowPass,
     HighPass,
@@ -8,6 +9,27 @@ pub enum FilterType {
     Notch,
 }
 
+impl FilterType {
+    // 0: LowPass, 1: HighPass, 2: BandPass, 3: Notch
+    pub fn from_index(index: u32) -> FilterType {
+        match index {
+            1 => FilterType::HighPass,
+            2 => FilterType::BandPass,
+            3 => FilterType::Notch,
+            _ => FilterType::LowPass,
+        }
+    }
+    
+    pub fn index(&self) -> u32 {
+        match self {
+            FilterType::LowPass => 0,
+            FilterType::HighPass => 1,
+            FilterType::BandPass => 2,
+            FilterType::Notch => 3,
+        }
+    }
+}
+
 pub struct Filter {
     filter_type: FilterType,
     cutoff: f32,
@@ -164,6 +186,12 @@ impl Effect for Filter {
         self.y2 = 0.0;
     }
     
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.cutoff = self.cutoff.clamp(20.0, sample_rate * 0.49);
+        self.calculate_coefficients();
+    }
+    
     fn set_parameter(&mut self, name: &str, value: f32) -> bool {
         match name {
             "cutoff" => {
@@ -175,21 +203,26 @@ impl Effect for Filter {
                 true
             },
             "type" => {
-                // 0: LowPass, 1: HighPass, 2: BandPass, 3: Notch
-                let filter_type = match value as i32 {
-                    0 => FilterType::LowPass,
-                    1 => FilterType::HighPass,
-                    2 => FilterType::BandPass,
-                    3 => FilterType::Notch,
-                    _ => FilterType::LowPass,
-                };
-                self.set_filter_type(filter_type);
+                self.set_filter_type(FilterType::from_index(value.max(0.0) as u32));
                 true
             },
             _ => false,
         }
     }
     
+    fn get_parameter(&self, name: &str) -> Option<f32> {
+        match name {
+            "cutoff" => Some(self.cutoff),
+            "resonance" => Some(self.resonance),
+            "type" => Some(self.filter_type.index() as f32),
+            _ => None,
+        }
+    }
+    
+    fn parameter_names(&self) -> &[&'static str] {
+        &["cutoff", "resonance", "type"]
+    }
+    
     fn name(&self) -> &str {
         "Filter"
     }
@@ -11,9 +11,18 @@ pub trait Effect {
     // Reset the effect state
     fn reset(&mut self);
     
+    // Follow a new sample rate, keeping parameter values
+    fn set_sample_rate(&mut self, sample_rate: f32);
+    
     // Set a parameter by name and value
     fn set_parameter(&mut self, name: &str, value: f32) -> bool;
     
+    // Get the current value of a parameter by name
+    fn get_parameter(&self, name: &str) -> Option<f32>;
+    
+    // Names of every parameter accepted by `set_parameter`
+    fn parameter_names(&self) -> &[&'static str];
+    
     // Get the effect name
     fn name(&self) -> &str;
 }
@@ -59,6 +68,12 @@ impl EffectsChain {
         }
     }
     
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        for effect in &mut self.effects {
+            effect.set_sample_rate(sample_rate);
+        }
+    }
+    
     pub fn set_effect_parameter(&mut self, effect_index: usize, param_name: &str, value: f32) -> bool {
         if effect_index < self.effects.len() {
             self.effects[effect_index].set_parameter(param_name, value)
@@ -67,7 +82,25 @@ impl EffectsChain {
         }
     }
     
+    pub fn get_effect_parameter(&self, effect_index: usize, param_name: &str) -> Option<f32> {
+        self.effects.get(effect_index).and_then(|effect| effect.get_parameter(param_name))
+    }
+    
+    pub fn effect_name(&self, effect_index: usize) -> Option<&str> {
+        self.effects.get(effect_index).map(|effect| effect.name())
+    }
+    
+    pub fn effect_parameter_names(&self, effect_index: usize) -> &[&'static str] {
+        self.effects.get(effect_index).map_or(&[], |effect| effect.parameter_names())
+    }
+    
     pub fn count(&self) -> usize {
         self.effects.len()
     }
+}
+
+impl Default for EffectsChain {
+    fn default() -> Self {
+        EffectsChain::new()
+    }
 }
\ No newline at end of file
@@ -1,3 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EnvelopeCurve {
+    Linear,
+    Exponential,    // Fast start, slow finish (like an analog RC envelope)
+    Logarithmic,    // Slow start, fast finish
+    Tension(f32),   // -1 (slow start) to 1 (fast start), 0 is linear
+}
+
+impl EnvelopeCurve {
+    pub fn tension(&self) -> f32 {
+        match self {
+            EnvelopeCurve::Linear => 0.0,
+            EnvelopeCurve::Exponential => 0.5,
+            EnvelopeCurve::Logarithmic => -0.5,
+            EnvelopeCurve::Tension(tension) => tension.clamp(-1.0, 1.0),
+        }
+    }
+    
+    // Map linear progress through a stage (0 to 1) onto curved progress (0 to 1)
+    pub fn shape(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        let k = self.tension() * 10.0;
+        
+        if k.abs() < 0.001 {
+            t
+        } else {
+            (1.0 - (-k * t).exp()) / (1.0 - (-k).exp())
+        }
+    }
+}
+
+// What happens when the envelope is triggered while it is still running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetriggerMode {
+    Reset,   // Restart the attack from zero
+    Legato,  // Restart the attack from the current level
+    Mono,    // Ignore triggers while the gate is held; only retrigger once released
+}
+
+impl RetriggerMode {
+    // 0: Reset, 1: Legato, 2: Mono
+    pub fn from_index(index: u32) -> RetriggerMode {
+        match index {
+            0 => RetriggerMode::Reset,
+            2 => RetriggerMode::Mono,
+            _ => RetriggerMode::Legato,
+        }
+    }
+    
+    pub fn index(&self) -> u32 {
+        match self {
+            RetriggerMode::Reset => 0,
+            RetriggerMode::Legato => 1,
+            RetriggerMode::Mono => 2,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EnvelopeStage {
     Idle,
@@ -16,10 +76,23 @@ pub struct Envelope {
     sustain_level: f32,
     release_time: f32,
     
+    // Per-stage curves
+    attack_curve: EnvelopeCurve,
+    decay_curve: EnvelopeCurve,
+    release_curve: EnvelopeCurve,
+    
+    // Velocity response (0 = ignore velocity, 1 = full range)
+    velocity_to_level: f32,
+    velocity_to_attack: f32,
+    retrigger_mode: RetriggerMode,
+    
     // Current state
     stage: EnvelopeStage,
     current_level: f32,
     stage_time: f32,
+    stage_start_level: f32,  // Level the current stage started from
+    peak_level: f32,         // Attack target for the current note
+    note_attack_time: f32,   // Attack time for the current note
     
     // Sample rate for time calculations
     sample_rate: f32,
@@ -34,9 +107,20 @@ impl Envelope {
             sustain_level: 0.7,   // 70% default sustain
             release_time: 0.3,    // 300ms default release
             
+            attack_curve: EnvelopeCurve::Linear,
+            decay_curve: EnvelopeCurve::Exponential,
+            release_curve: EnvelopeCurve::Exponential,
+            
+            velocity_to_level: 1.0,
+            velocity_to_attack: 0.0,
+            retrigger_mode: RetriggerMode::Legato,
+            
             stage: EnvelopeStage::Idle,
             current_level: 0.0,
             stage_time: 0.0,
+            stage_start_level: 0.0,
+            peak_level: 1.0,
+            note_attack_time: 0.01,
             
             sample_rate,
         }
@@ -50,19 +134,83 @@ impl Envelope {
         self.release_time = release.max(0.001); // Minimum 1ms
     }
     
+    // (attack, decay, hold, sustain, release) as passed to `set_parameters`
+    pub fn parameters(&self) -> (f32, f32, f32, f32, f32) {
+        (self.attack_time, self.decay_time, self.hold_time, self.sustain_level, self.release_time)
+    }
+    
+    pub fn set_curves(&mut self, attack: EnvelopeCurve, decay: EnvelopeCurve, release: EnvelopeCurve) {
+        self.attack_curve = attack;
+        self.decay_curve = decay;
+        self.release_curve = release;
+    }
+    
+    // (attack, decay, release) curve tensions
+    pub fn curves(&self) -> (f32, f32, f32) {
+        (self.attack_curve.tension(), self.decay_curve.tension(), self.release_curve.tension())
+    }
+    
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+    
+    // Scale the peak level and the attack time by velocity. With full attack
+    // sensitivity the hardest notes attack almost instantly.
+    pub fn set_velocity_sensitivity(&mut self, to_level: f32, to_attack: f32) {
+        self.velocity_to_level = to_level.clamp(0.0, 1.0);
+        self.velocity_to_attack = to_attack.clamp(0.0, 1.0);
+    }
+    
+    // (to_level, to_attack) as passed to `set_velocity_sensitivity`
+    pub fn velocity_sensitivity(&self) -> (f32, f32) {
+        (self.velocity_to_level, self.velocity_to_attack)
+    }
+    
+    pub fn set_retrigger_mode(&mut self, mode: RetriggerMode) {
+        self.retrigger_mode = mode;
+    }
+    
+    pub fn retrigger_mode(&self) -> RetriggerMode {
+        self.retrigger_mode
+    }
+    
     pub fn trigger(&mut self) {
-        self.stage = EnvelopeStage::Attack;
-        self.stage_time = 0.0;
-        // Don't reset current_level to allow for legato playing
+        self.trigger_with_velocity(1.0);
+    }
+    
+    // Start the envelope for a note (velocity 0 to 1). Returns false if the
+    // trigger was ignored because a mono envelope's gate is still held.
+    pub fn trigger_with_velocity(&mut self, velocity: f32) -> bool {
+        if self.retrigger_mode == RetriggerMode::Mono && self.is_active() && !self.is_releasing() {
+            return false;
+        }
+        
+        let velocity = velocity.clamp(0.0, 1.0);
+        self.peak_level = 1.0 - self.velocity_to_level * (1.0 - velocity);
+        self.note_attack_time = (self.attack_time * (1.0 - self.velocity_to_attack * velocity)).max(0.001);
+        
+        if self.retrigger_mode == RetriggerMode::Reset {
+            self.current_level = 0.0;
+        }
+        
+        // Other modes keep current_level to allow for legato playing
+        self.enter_stage(EnvelopeStage::Attack);
+        true
     }
     
     pub fn release(&mut self) {
         if self.stage != EnvelopeStage::Idle {
-            self.stage = EnvelopeStage::Release;
-            self.stage_time = 0.0;
+            // Release from wherever the envelope is right now
+            self.enter_stage(EnvelopeStage::Release);
         }
     }
     
+    fn enter_stage(&mut self, stage: EnvelopeStage) {
+        self.stage = stage;
+        self.stage_time = 0.0;
+        self.stage_start_level = self.current_level;
+    }
+    
     pub fn process(&mut self) -> f32 {
         let sample_time = 1.0 / self.sample_rate;
         self.stage_time += sample_time;
@@ -72,25 +220,24 @@ impl Envelope {
                 self.current_level = 0.0;
             },
             EnvelopeStage::Attack => {
-                // Linear attack from current level to 1.0
-                if self.stage_time >= self.attack_time {
-                    self.current_level = 1.0;
-                    self.stage = EnvelopeStage::Decay;
-                    self.stage_time = 0.0;
+                // Curved attack from the level at trigger time to the velocity-scaled peak
+                if self.stage_time >= self.note_attack_time {
+                    self.current_level = self.peak_level;
+                    self.enter_stage(EnvelopeStage::Decay);
                 } else {
-                    let attack_rate = (1.0 - self.current_level) / self.attack_time;
-                    self.current_level += attack_rate * sample_time;
+                    let progress = self.attack_curve.shape(self.stage_time / self.note_attack_time);
+                    self.current_level = self.stage_start_level + (self.peak_level - self.stage_start_level) * progress;
                 }
             },
             EnvelopeStage::Decay => {
-                // Exponential decay from 1.0 to sustain level
+                // Curved decay from the peak to sustain level (scaled like the peak)
+                let sustain_level = self.sustain_level * self.peak_level;
                 if self.stage_time >= self.decay_time {
-                    self.current_level = self.sustain_level;
-                    self.stage = EnvelopeStage::Hold;
-                    self.stage_time = 0.0;
+                    self.current_level = sustain_level;
+                    self.enter_stage(EnvelopeStage::Hold);
                 } else {
-                    let decay_factor = (-5.0 * self.stage_time / self.decay_time).exp();
-                    self.current_level = self.sustain_level + (1.0 - self.sustain_level) * decay_factor;
+                    let progress = self.decay_curve.shape(self.stage_time / self.decay_time);
+                    self.current_level = self.stage_start_level + (sustain_level - self.stage_start_level) * progress;
                 }
             },
             EnvelopeStage::Hold => {
@@ -102,16 +249,16 @@ impl Envelope {
             },
             EnvelopeStage::Sustain => {
                 // Sustain at sustain_level until release
-                self.current_level = self.sustain_level;
+                self.current_level = self.sustain_level * self.peak_level;
             },
             EnvelopeStage::Release => {
-                // Exponential release from current level to 0
+                // Curved release from the level at release time to 0
                 if self.stage_time >= self.release_time {
                     self.current_level = 0.0;
                     self.stage = EnvelopeStage::Idle;
                 } else {
-                    let release_factor = (-5.0 * self.stage_time / self.release_time).exp();
-                    self.current_level = self.current_level * release_factor;
+                    let progress = self.release_curve.shape(self.stage_time / self.release_time);
+                    self.current_level = self.stage_start_level * (1.0 - progress);
                 }
             },
         }
@@ -122,4 +269,20 @@ impl Envelope {
     pub fn is_active(&self) -> bool {
         self.stage != EnvelopeStage::Idle
     }
+    
+    pub fn is_releasing(&self) -> bool {
+        self.stage == EnvelopeStage::Release
+    }
+    
+    // Current output level without advancing
+    pub fn level(&self) -> f32 {
+        self.current_level
+    }
+    
+    // Return to idle immediately
+    pub fn reset(&mut self) {
+        self.stage = EnvelopeStage::Idle;
+        self.current_level = 0.0;
+        self.stage_time = 0.0;
+    }
 }
\ No newline at end of file
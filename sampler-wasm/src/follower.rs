@@ -0,0 +1,200 @@
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DetectionMode {
+    Peak,
+    Rms,
+}
+
+// Which signal audio-derived modulation listens to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FollowerInput {
+    Voices,    // The sampler's own voice output (before effects)
+    External,  // The worklet's first input
+}
+
+impl FollowerInput {
+    // 0: Voices, 1: External input
+    pub fn from_index(index: u32) -> FollowerInput {
+        match index {
+            1 => FollowerInput::External,
+            _ => FollowerInput::Voices,
+        }
+    }
+    
+    pub fn index(&self) -> u32 {
+        match self {
+            FollowerInput::Voices => 0,
+            FollowerInput::External => 1,
+        }
+    }
+}
+
+// One-pole smoothing coefficient for a block of `frames` frames
+fn block_coefficient(time_seconds: f32, frames: usize, sample_rate: f32) -> f32 {
+    if time_seconds <= 0.0 {
+        0.0
+    } else {
+        (-(frames as f32) / (time_seconds * sample_rate)).exp()
+    }
+}
+
+// Tracks the level of a signal with separate attack and release times
+pub struct EnvelopeFollower {
+    attack_time: f32,
+    release_time: f32,
+    mode: DetectionMode,
+    level: f32,
+    sample_rate: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(sample_rate: f32) -> Self {
+        EnvelopeFollower {
+            attack_time: 0.01,   // 10ms default attack
+            release_time: 0.2,   // 200ms default release
+            mode: DetectionMode::Rms,
+            level: 0.0,
+            sample_rate,
+        }
+    }
+    
+    pub fn set_parameters(&mut self, attack: f32, release: f32, mode: DetectionMode) {
+        self.attack_time = attack.max(0.0);
+        self.release_time = release.max(0.0);
+        self.mode = mode;
+    }
+    
+    // (attack, release, mode) as passed to `set_parameters`
+    pub fn parameters(&self) -> (f32, f32, DetectionMode) {
+        (self.attack_time, self.release_time, self.mode)
+    }
+    
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+    
+    // Feed a block of audio and return the smoothed level (0 to 1 for normalized audio)
+    pub fn process_block(&mut self, block: &[f32]) -> f32 {
+        if block.is_empty() {
+            return self.level;
+        }
+        
+        let input = match self.mode {
+            DetectionMode::Peak => utils::calculate_peak(block),
+            DetectionMode::Rms => utils::calculate_rms(block),
+        };
+        
+        let time = if input > self.level { self.attack_time } else { self.release_time };
+        let coefficient = block_coefficient(time, block.len(), self.sample_rate);
+        self.level = input + (self.level - input) * coefficient;
+        self.level
+    }
+    
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+    
+    pub fn reset(&mut self) {
+        self.level = 0.0;
+    }
+}
+
+// Detects onsets by comparing a fast and a slow envelope, emitting a short pulse per hit
+pub struct TransientDetector {
+    threshold: f32,       // Ratio of fast to slow envelope that counts as a hit
+    floor: f32,           // Ignore anything quieter than this
+    retrigger_time: f32,  // Minimum seconds between hits
+    pulse_time: f32,      // How long the output stays high
+    
+    fast_level: f32,
+    slow_level: f32,
+    time_since_trigger: f32,
+    pulse_remaining: f32,
+    trigger_count: u32,
+    
+    sample_rate: f32,
+}
+
+impl TransientDetector {
+    pub fn new(sample_rate: f32) -> Self {
+        TransientDetector {
+            threshold: 2.0,        // Fast envelope at least 6dB above the slow one
+            floor: 0.01,           // -40dB
+            retrigger_time: 0.05,  // 50ms
+            pulse_time: 0.01,      // 10ms
+            
+            fast_level: 0.0,
+            slow_level: 0.0,
+            time_since_trigger: f32::MAX,
+            pulse_remaining: 0.0,
+            trigger_count: 0,
+            
+            sample_rate,
+        }
+    }
+    
+    // Sensitivity as the jump in dB over the recent average that counts as a hit
+    pub fn set_parameters(&mut self, threshold_db: f32, floor_db: f32, retrigger_time: f32) {
+        self.threshold = utils::db_to_linear(threshold_db.max(0.1));
+        self.floor = utils::db_to_linear(floor_db);
+        self.retrigger_time = retrigger_time.max(0.0);
+    }
+    
+    // (threshold_db, floor_db, retrigger_time) as passed to `set_parameters`
+    pub fn parameters(&self) -> (f32, f32, f32) {
+        (utils::linear_to_db(self.threshold), utils::linear_to_db(self.floor), self.retrigger_time)
+    }
+    
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+    
+    // Feed a block of audio and return the pulse output (1 while a hit is active, else 0)
+    pub fn process_block(&mut self, block: &[f32]) -> f32 {
+        if block.is_empty() {
+            return self.output();
+        }
+        
+        let block_time = block.len() as f32 / self.sample_rate;
+        let peak = utils::calculate_peak(block);
+        
+        // Fast envelope: instant attack, 5ms release. Slow envelope: 100ms average.
+        let fast = block_coefficient(0.005, block.len(), self.sample_rate);
+        self.fast_level = if peak > self.fast_level { peak } else { peak + (self.fast_level - peak) * fast };
+        
+        let is_hit = self.fast_level > self.floor
+            && self.fast_level > self.slow_level * self.threshold
+            && self.time_since_trigger >= self.retrigger_time;
+        
+        let slow = block_coefficient(0.1, block.len(), self.sample_rate);
+        self.slow_level = self.fast_level + (self.slow_level - self.fast_level) * slow;
+        
+        self.time_since_trigger = (self.time_since_trigger + block_time).min(f32::MAX / 2.0);
+        self.pulse_remaining = (self.pulse_remaining - block_time).max(0.0);
+        
+        if is_hit {
+            self.time_since_trigger = 0.0;
+            self.pulse_remaining = self.pulse_time;
+            self.trigger_count = self.trigger_count.wrapping_add(1);
+        }
+        
+        self.output()
+    }
+    
+    pub fn output(&self) -> f32 {
+        if self.pulse_remaining > 0.0 { 1.0 } else { 0.0 }
+    }
+    
+    // Total hits so far; pollers can compare against the last value they saw
+    pub fn trigger_count(&self) -> u32 {
+        self.trigger_count
+    }
+    
+    pub fn reset(&mut self) {
+        self.fast_level = 0.0;
+        self.slow_level = 0.0;
+        self.time_since_trigger = f32::MAX;
+        self.pulse_remaining = 0.0;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,43 @@
+use std::rc::Rc;
+
+// Importers for multisampled instrument formats, building keymap zones
+pub mod sfz;
+pub mod sf2;
+
+pub use sfz::SfzInstrument;
+pub use sf2::Sf2File;
+
+// Decoded sample data and its sample rate
+pub type LoadedSample = (Rc<[f32]>, f32);
+
+// Parse a note number or name ("c4", "f#3", "eb-1"); middle C is c4 = 60
+pub fn parse_note(text: &str) -> Option<u8> {
+    if let Ok(number) = text.parse::<i32>() {
+        return (0..=127).contains(&number).then_some(number as u8);
+    }
+    
+    let text = text.to_ascii_lowercase();
+    let mut chars = text.chars().peekable();
+    let semitone = match chars.next()? {
+        'c' => 0,
+        'd' => 2,
+        'e' => 4,
+        'f' => 5,
+        'g' => 7,
+        'a' => 9,
+        'b' => 11,
+        _ => return None,
+    };
+    let accidental = match chars.peek() {
+        Some('#') => 1,
+        Some('b') => -1,
+        _ => 0,
+    };
+    if accidental != 0 {
+        chars.next();
+    }
+    
+    let octave: i32 = chars.collect::<String>().parse().ok()?;
+    let note = (octave + 1) * 12 + semitone + accidental;
+    (0..=127).contains(&note).then_some(note as u8)
+}
\ No newline at end of file
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::effects::filter::FilterType;
+use crate::keymap::{Keymap, LoopMode, Zone, ZoneFilter};
+
+// Generator operators used when building zones
+const START_OFFSET: usize = 0;
+const END_OFFSET: usize = 1;
+const LOOP_START_OFFSET: usize = 2;
+const LOOP_END_OFFSET: usize = 3;
+const START_COARSE_OFFSET: usize = 4;
+const FILTER_CUTOFF: usize = 8;
+const FILTER_Q: usize = 9;
+const END_COARSE_OFFSET: usize = 12;
+const PAN: usize = 17;
+const ATTACK_VOL_ENV: usize = 34;
+const HOLD_VOL_ENV: usize = 35;
+const DECAY_VOL_ENV: usize = 36;
+const SUSTAIN_VOL_ENV: usize = 37;
+const RELEASE_VOL_ENV: usize = 38;
+const INSTRUMENT: usize = 41;
+const KEY_RANGE: usize = 43;
+const VELOCITY_RANGE: usize = 44;
+const LOOP_START_COARSE_OFFSET: usize = 45;
+const INITIAL_ATTENUATION: usize = 48;
+const LOOP_END_COARSE_OFFSET: usize = 50;
+const COARSE_TUNE: usize = 51;
+const FINE_TUNE: usize = 52;
+const SAMPLE_ID: usize = 53;
+const SAMPLE_MODES: usize = 54;
+const OVERRIDING_ROOT_KEY: usize = 58;
+const GENERATOR_COUNT: usize = 61;
+
+// Timecents for "instant" envelope stages, the SF2 default
+const INSTANT_TIMECENTS: i32 = -12000;
+
+// Cutoff in absolute cents at or above which the filter is left open
+const OPEN_FILTER_CENTS: i32 = 13500;
+
+// Generator amounts of one zone, as stored (signed words or lo/hi byte ranges)
+#[derive(Debug, Clone, Copy)]
+pub struct Generators {
+    values: [Option<u16>; GENERATOR_COUNT],
+}
+
+impl Generators {
+    fn new() -> Self {
+        Generators { values: [None; GENERATOR_COUNT] }
+    }
+    
+    fn set(&mut self, operator: u16, amount: u16) {
+        if let Some(value) = self.values.get_mut(operator as usize) {
+            *value = Some(amount);
+        }
+    }
+    
+    fn has(&self, operator: usize) -> bool {
+        self.values[operator].is_some()
+    }
+    
+    fn signed(&self, operator: usize) -> Option<i32> {
+        self.values[operator].map(|value| value as i16 as i32)
+    }
+    
+    fn unsigned(&self, operator: usize) -> Option<usize> {
+        self.values[operator].map(|value| value as usize)
+    }
+    
+    fn range(&self, operator: usize) -> Option<(u8, u8)> {
+        self.values[operator].map(|value| ((value & 0xFF) as u8, (value >> 8) as u8))
+    }
+    
+    // `local` values over `self` (a global zone's)
+    fn merged(&self, local: &Generators) -> Generators {
+        let mut merged = *self;
+        for (value, local) in merged.values.iter_mut().zip(local.values.iter()) {
+            if local.is_some() {
+                *value = *local;
+            }
+        }
+        merged
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sf2Preset {
+    pub name: String,
+    pub bank: u16,
+    pub program: u16,
+    zones: Vec<Generators>,
+}
+
+#[derive(Debug, Clone)]
+struct Sf2Instrument {
+    zones: Vec<Generators>,
+}
+
+#[derive(Debug, Clone)]
+struct SampleHeader {
+    start: usize,
+    end: usize,
+    loop_start: usize,
+    loop_end: usize,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i8,
+    rom: bool,
+}
+
+// A SoundFont 2 bank: its sample pool and preset/instrument hierarchy. The pool is
+// borrowed from the file, and only the ranges a preset's zones play are decoded.
+#[derive(Debug, Clone)]
+pub struct Sf2File<'a> {
+    pub presets: Vec<Sf2Preset>,
+    instruments: Vec<Sf2Instrument>,
+    samples: Vec<SampleHeader>,
+    smpl: &'a [u8],  // 16-bit sample words
+    sm24: &'a [u8],  // Optional low bytes extending `smpl` to 24 bits
+}
+
+impl<'a> Sf2File<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"sfbk" {
+            return Err("Not a SoundFont 2 file".to_string());
+        }
+        
+        let mut smpl: &'a [u8] = &[];
+        let mut sm24: &'a [u8] = &[];
+        let mut pdta: HashMap<[u8; 4], &[u8]> = HashMap::new();
+        
+        for (id, body) in chunks(&data[12..]) {
+            if &id != b"LIST" || body.len() < 4 {
+                continue;
+            }
+            let list_type = &body[0..4];
+            for (id, sub) in chunks(&body[4..]) {
+                match (list_type, &id) {
+                    (b"sdta", b"smpl") => smpl = sub,
+                    (b"sdta", b"sm24") => sm24 = sub,
+                    (b"pdta", _) => {
+                        pdta.insert(id, sub);
+                    },
+                    _ => {},
+                }
+            }
+        }
+        
+        let chunk = |id: &[u8; 4]| pdta.get(id).copied().ok_or_else(|| format!("SoundFont is missing its '{}' chunk", String::from_utf8_lossy(id)));
+        
+        let generators = |bags: &[u8], gens: &[u8]| -> Vec<Generators> {
+            let gen_starts: Vec<usize> = records(bags, 4).map(|bag| u16_at(bag, 0) as usize).collect();
+            let all: Vec<(u16, u16)> = records(gens, 4).map(|gen| (u16_at(gen, 0), u16_at(gen, 2))).collect();
+            gen_starts.windows(2)
+                .map(|window| {
+                    let mut zone = Generators::new();
+                    for &(operator, amount) in all.get(window[0]..window[1].min(all.len())).unwrap_or(&[]) {
+                        zone.set(operator, amount);
+                    }
+                    zone
+                })
+                .collect()
+        };
+        
+        // Headers index their first bag; each header's bags run to the next header's
+        let split_zones = |first_bags: &[usize], zones: &[Generators]| -> Vec<Vec<Generators>> {
+            first_bags.windows(2)
+                .map(|window| zones.get(window[0]..window[1].min(zones.len())).unwrap_or(&[]).to_vec())
+                .collect()
+        };
+        
+        let preset_zones = generators(chunk(b"pbag")?, chunk(b"pgen")?);
+        let preset_headers: Vec<&[u8]> = records(chunk(b"phdr")?, 38).collect();
+        let first_bags: Vec<usize> = preset_headers.iter().map(|record| u16_at(record, 24) as usize).collect();
+        let presets = split_zones(&first_bags, &preset_zones).into_iter().zip(preset_headers)
+            .map(|(zones, record)| Sf2Preset {
+                name: name(&record[0..20]),
+                program: u16_at(record, 20),
+                bank: u16_at(record, 22),
+                zones,
+            })
+            .collect();
+        
+        let instrument_zones = generators(chunk(b"ibag")?, chunk(b"igen")?);
+        let first_bags: Vec<usize> = records(chunk(b"inst")?, 22).map(|record| u16_at(record, 20) as usize).collect();
+        let instruments = split_zones(&first_bags, &instrument_zones).into_iter()
+            .map(|zones| Sf2Instrument { zones })
+            .collect();
+        
+        let samples = records(chunk(b"shdr")?, 46)
+            .map(|record| SampleHeader {
+                start: u32_at(record, 20) as usize,
+                end: u32_at(record, 24) as usize,
+                loop_start: u32_at(record, 28) as usize,
+                loop_end: u32_at(record, 32) as usize,
+                sample_rate: u32_at(record, 36),
+                original_pitch: record[40],
+                pitch_correction: record[41] as i8,
+                rom: u16_at(record, 44) & 0x8000 != 0,
+            })
+            .collect();
+        
+        // `sm24` only applies when it covers every sample word
+        let sm24 = if sm24.len() >= smpl.len() / 2 { sm24 } else { &[] };
+        
+        Ok(Sf2File { presets, instruments, samples, smpl, sm24 })
+    }
+    
+    fn frames(&self) -> usize {
+        self.smpl.len() / 2
+    }
+    
+    // Sample pool frames as floats
+    fn read_pool(&self, start: usize, end: usize) -> Vec<f32> {
+        (start..end)
+            .map(|i| {
+                let word = i16::from_le_bytes([self.smpl[2 * i], self.smpl[2 * i + 1]]) as f32;
+                match self.sm24.get(i) {
+                    Some(&low) => (word * 256.0 + low as f32) / 8_388_608.0,
+                    None => word / 32768.0,
+                }
+            })
+            .collect()
+    }
+    
+    pub fn find_preset(&self, bank: u16, program: u16) -> Option<usize> {
+        self.presets.iter().position(|preset| preset.bank == bank && preset.program == program)
+    }
+    
+    // Build the zones of a preset. Preset generators offset the instrument's
+    // (ranges narrow them); a leading zone without an instrument or sample is
+    // the global zone its siblings inherit from.
+    pub fn preset_keymap(&self, index: usize) -> Result<Keymap, String> {
+        let preset = self.presets.get(index).ok_or_else(|| format!("No preset {}", index))?;
+        let mut keymap = Keymap::new();
+        let mut cache: HashMap<(usize, usize, usize), Rc<[f32]>> = HashMap::new();
+        
+        let (preset_global, preset_zones) = split_global(&preset.zones, INSTRUMENT);
+        for preset_zone in preset_zones {
+            let preset_zone = preset_global.merged(preset_zone);
+            let Some(instrument) = preset_zone.unsigned(INSTRUMENT).and_then(|index| self.instruments.get(index)) else {
+                continue;
+            };
+            
+            let (instrument_global, instrument_zones) = split_global(&instrument.zones, SAMPLE_ID);
+            for instrument_zone in instrument_zones {
+                let zone = instrument_global.merged(instrument_zone);
+                if let Some(zone) = self.build_zone(&zone, &preset_zone, &mut cache) {
+                    keymap.add_zone(zone);
+                }
+            }
+        }
+        
+        if keymap.is_empty() {
+            return Err(format!("Preset '{}' has no playable zones", preset.name));
+        }
+        Ok(keymap)
+    }
+    
+    fn build_zone(&self, zone: &Generators, preset: &Generators, cache: &mut HashMap<(usize, usize, usize), Rc<[f32]>>) -> Option<Zone> {
+        let header = self.samples.get(zone.unsigned(SAMPLE_ID)?)?;
+        if header.rom || header.sample_rate == 0 {
+            return None;
+        }
+        
+        let intersect = |operator: usize| {
+            let (low, high) = zone.range(operator).unwrap_or((0, 127));
+            let (preset_low, preset_high) = preset.range(operator).unwrap_or((0, 127));
+            let range = (low.max(preset_low), high.min(preset_high).min(127));
+            (range.0 <= range.1).then_some(range)
+        };
+        let key_range = intersect(KEY_RANGE)?;
+        let velocity_range = intersect(VELOCITY_RANGE)?;
+        
+        // Instrument value (or the default) plus the preset's offset
+        let value = |operator: usize, default: i32| zone.signed(operator).unwrap_or(default) + preset.signed(operator).unwrap_or(0);
+        let offset = |fine: usize, coarse: usize| zone.signed(fine).unwrap_or(0) as i64 + 32768 * zone.signed(coarse).unwrap_or(0) as i64;
+        let address = |base: usize, delta: i64| (base as i64 + delta).clamp(0, self.frames() as i64) as usize;
+        
+        let start = address(header.start, offset(START_OFFSET, START_COARSE_OFFSET));
+        let end = address(header.end, offset(END_OFFSET, END_COARSE_OFFSET));
+        if end <= start {
+            return None;
+        }
+        let loop_start = address(header.loop_start, offset(LOOP_START_OFFSET, LOOP_START_COARSE_OFFSET));
+        let loop_end = address(header.loop_end, offset(LOOP_END_OFFSET, LOOP_END_COARSE_OFFSET));
+        
+        let sample = cache.entry((start, end, header.sample_rate as usize))
+            .or_insert_with(|| Rc::from(self.read_pool(start, end)))
+            .clone();
+        let mut result = Zone::new(sample, header.sample_rate as f32);
+        result.key_range = key_range;
+        result.velocity_range = velocity_range;
+        
+        result.root_key = match zone.signed(OVERRIDING_ROOT_KEY) {
+            Some(key) if (0..=127).contains(&key) => key as u8,
+            _ => header.original_pitch.min(127),
+        };
+        result.tune = (value(COARSE_TUNE, 0) * 100 + value(FINE_TUNE, 0) + header.pitch_correction as i32) as f32;
+        
+        // Attenuation and sustain are in centibels
+        result.gain = 10.0f32.powf(-value(INITIAL_ATTENUATION, 0).max(0) as f32 / 200.0);
+        result.pan = (value(PAN, 0) as f32 / 500.0).clamp(-1.0, 1.0);
+        
+        result.loop_mode = match zone.signed(SAMPLE_MODES).unwrap_or(0) & 3 {
+            1 => LoopMode::Continuous,
+            3 => LoopMode::Sustain,
+            _ => LoopMode::NoLoop,
+        };
+        result.loop_start = loop_start.saturating_sub(start);
+        result.loop_end = loop_end.saturating_sub(start);
+        
+        let seconds = |operator: usize| timecents_to_seconds(value(operator, INSTANT_TIMECENTS));
+        let sustain = 10.0f32.powf(-value(SUSTAIN_VOL_ENV, 0).clamp(0, 1440) as f32 / 200.0);
+        result.amp_envelope = Some((seconds(ATTACK_VOL_ENV), seconds(DECAY_VOL_ENV), seconds(HOLD_VOL_ENV), sustain, seconds(RELEASE_VOL_ENV)));
+        
+        let cutoff = value(FILTER_CUTOFF, OPEN_FILTER_CENTS);
+        if cutoff < OPEN_FILTER_CENTS {
+            let resonance = 0.707 * 10.0f32.powf(value(FILTER_Q, 0).clamp(0, 960) as f32 / 200.0);
+            result.filter = Some(ZoneFilter {
+                filter_type: FilterType::LowPass,
+                cutoff: 8.176 * 2.0f32.powf(cutoff as f32 / 1200.0),
+                resonance,
+            });
+        }
+        
+        Some(result)
+    }
+}
+
+// A first zone lacking the terminal generator (instrument or sample) is global
+fn split_global(zones: &[Generators], terminal: usize) -> (Generators, &[Generators]) {
+    match zones.first() {
+        Some(first) if !first.has(terminal) => (*first, &zones[1..]),
+        _ => (Generators::new(), zones),
+    }
+}
+
+fn timecents_to_seconds(timecents: i32) -> f32 {
+    2.0f32.powf(timecents.clamp(-12000, 8000) as f32 / 1200.0)
+}
+
+// RIFF chunks as (id, body), skipping the pad byte after odd-sized bodies
+fn chunks(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let id = [data[0], data[1], data[2], data[3]];
+        let size = (u32_at(data, 4) as usize).min(data.len() - 8);
+        let body = &data[8..8 + size];
+        data = &data[(8 + size + (size & 1)).min(data.len())..];
+        Some((id, body))
+    })
+}
+
+fn records(data: &[u8], size: usize) -> impl Iterator<Item = &[u8]> {
+    data.chunks_exact(size)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn name(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        if body.len() & 1 == 1 {
+            out.push(0);
+        }
+        out
+    }
+    
+    fn list(list_type: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = list_type.to_vec();
+        body.extend(chunks.concat());
+        chunk(b"LIST", &body)
+    }
+    
+    fn padded_name(name: &str, length: usize) -> Vec<u8> {
+        let mut out = name.as_bytes().to_vec();
+        out.resize(length, 0);
+        out
+    }
+    
+    fn words(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+    
+    fn generators(generators: &[(usize, i16)]) -> Vec<u8> {
+        generators.iter().flat_map(|&(operator, amount)| words(&[operator as u16, amount as u16])).collect()
+    }
+    
+    fn range(low: u8, high: u8) -> i16 {
+        i16::from_le_bytes([low, high])
+    }
+    
+    // One preset (bank 1, program 5) whose global zone detunes its single instrument
+    // zone. The instrument's global zone sets pan and looping for a low and a high
+    // zone over one sample, and the high zone overrides the pan.
+    fn file() -> Vec<u8> {
+        let pool: Vec<i16> = (0..8).map(|i| i * 1000).collect();
+        let pool: Vec<u8> = pool.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        
+        let mut phdr = padded_name("Piano", 20);
+        phdr.extend(words(&[5, 1, 0, 0, 0, 0, 0, 0, 0]));
+        phdr.extend(padded_name("EOP", 20));
+        phdr.extend(words(&[0, 0, 2, 0, 0, 0, 0, 0, 0]));
+        let pbag = words(&[0, 0, 1, 0, 3, 0]);
+        let pgen = generators(&[
+            (FINE_TUNE, 10),
+            (VELOCITY_RANGE, range(1, 100)), (INSTRUMENT, 0),
+            (0, 0),
+        ]);
+        
+        let mut inst = padded_name("Inst", 20);
+        inst.extend(words(&[0]));
+        inst.extend(padded_name("EOI", 20));
+        inst.extend(words(&[3]));
+        let ibag = words(&[0, 0, 2, 0, 4, 0, 7, 0]);
+        let igen = generators(&[
+            (PAN, 250), (SAMPLE_MODES, 1),
+            (KEY_RANGE, range(0, 59)), (SAMPLE_ID, 0),
+            (KEY_RANGE, range(60, 127)), (PAN, -500), (SAMPLE_ID, 0),
+            (0, 0),
+        ]);
+        
+        let mut shdr = padded_name("Sample", 20);
+        shdr.extend(words(&[0, 0, 8, 0, 2, 0, 6, 0, 22050, 0]));
+        shdr.extend_from_slice(&[60, 0, 0, 0, 1, 0]);
+        shdr.extend(padded_name("EOS", 46));
+        
+        let mut body = b"sfbk".to_vec();
+        body.extend(list(b"INFO", &[chunk(b"ifil", &words(&[2, 1]))]));
+        body.extend(list(b"sdta", &[chunk(b"smpl", &pool)]));
+        body.extend(list(b"pdta", &[
+            chunk(b"phdr", &phdr), chunk(b"pbag", &pbag), chunk(b"pgen", &pgen),
+            chunk(b"inst", &inst), chunk(b"ibag", &ibag), chunk(b"igen", &igen),
+            chunk(b"shdr", &shdr),
+        ]));
+        chunk(b"RIFF", &body)
+    }
+    
+    #[test]
+    fn global_zones() {
+        let data = file();
+        let file = Sf2File::parse(&data).unwrap();
+        let index = file.find_preset(1, 5).unwrap();
+        assert_eq!(file.presets[index].name, "Piano");
+        
+        let keymap = file.preset_keymap(index).unwrap();
+        let zones = keymap.zones();
+        assert_eq!(zones.len(), 2);
+        for zone in zones {
+            assert_eq!(zone.velocity_range, (1, 100));
+            assert_eq!(zone.tune, 10.0);
+            assert_eq!(zone.root_key, 60);
+            assert_eq!(zone.sample_rate, 22050.0);
+            assert_eq!(zone.loop_mode, LoopMode::Continuous);
+            assert_eq!((zone.loop_start, zone.loop_end), (2, 6));
+            assert_eq!(zone.sample.len(), 8);
+            assert_eq!(zone.sample[1], 1000.0 / 32768.0);
+        }
+        assert_eq!(zones[0].key_range, (0, 59));
+        assert_eq!(zones[0].pan, 0.5);
+        assert_eq!(zones[1].key_range, (60, 127));
+        assert_eq!(zones[1].pan, -1.0);
+    }
+    
+    #[test]
+    fn truncated_input_is_rejected() {
+        // Cut before the sample headers the hierarchy is incomplete; any later cut
+        // leaves a bank whose presets still build, or fail to, without panicking
+        let data = file();
+        let shdr = data.windows(4).position(|window| window == b"shdr").unwrap();
+        for len in 0..data.len() {
+            if let Ok(file) = Sf2File::parse(&data[..len]) {
+                assert!(len >= shdr + 8);
+                if let Some(index) = file.find_preset(1, 5) {
+                    let _ = file.preset_keymap(index);
+                }
+            }
+        }
+    }
+}
\ No newline at end of file
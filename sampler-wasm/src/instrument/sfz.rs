@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{parse_note, LoadedSample};
+use crate::effects::filter::FilterType;
+use crate::keymap::{self, AlternationGroup, AlternationMode, Keymap, LoopMode, Zone, ZoneFilter};
+
+// Header levels whose opcodes regions inherit, outermost first
+const LEVELS: usize = 3;  // <global>, <master>, <group>
+
+// One <region> with every opcode it inherits from the enclosing headers
+#[derive(Debug, Clone, Default)]
+pub struct SfzRegion {
+    pub opcodes: HashMap<String, String>,
+    pub group: usize,  // Index of the enclosing <group> header, for round robins
+}
+
+impl SfzRegion {
+    pub fn get(&self, opcode: &str) -> Option<&str> {
+        self.opcodes.get(opcode).map(String::as_str)
+    }
+    
+    fn number(&self, opcode: &str) -> Option<f32> {
+        self.get(opcode)?.parse::<f32>().ok().filter(|value| value.is_finite())
+    }
+    
+    fn note(&self, opcode: &str) -> Option<u8> {
+        self.get(opcode).and_then(parse_note)
+    }
+    
+    fn velocity(&self, opcode: &str) -> Option<u8> {
+        self.number(opcode).map(|value| value.clamp(0.0, 127.0) as u8)
+    }
+}
+
+// A parsed SFZ file: its regions and the sample directory from <control>
+#[derive(Debug, Clone, Default)]
+pub struct SfzInstrument {
+    pub default_path: String,
+    pub regions: Vec<SfzRegion>,
+}
+
+impl SfzInstrument {
+    // Parse SFZ text. Supports <control>, <global>, <master>, <group> and <region>
+    // headers, `//` and `/* */` comments and `#define` substitution; other headers
+    // (<curve>, <effect>, ...) and `#include` are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = strip_comments(text);
+        let mut defines: Vec<(String, String)> = Vec::new();
+        let mut instrument = SfzInstrument::default();
+        
+        // Opcodes of the headers currently in effect, and the header being filled
+        let mut levels: [HashMap<String, String>; LEVELS] = Default::default();
+        let mut region: Option<HashMap<String, String>> = None;
+        let mut section = Section::Other;
+        let mut group = 0;
+        
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(define) = line.strip_prefix("#define") {
+                let mut parts = define.split_whitespace();
+                if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                    defines.push((name.to_string(), value.to_string()));
+                    defines.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+                }
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            
+            let mut line = line.to_string();
+            for (name, value) in &defines {
+                line = line.replace(name.as_str(), value);
+            }
+            
+            for token in tokenize(&line)? {
+                match token {
+                    Token::Header(name) => {
+                        if let Some(opcodes) = region.take() {
+                            instrument.push_region(&levels, opcodes, group);
+                        }
+                        
+                        section = match name {
+                            "control" => Section::Control,
+                            "region" => {
+                                region = Some(HashMap::new());
+                                Section::Region
+                            },
+                            "global" | "master" | "group" => {
+                                let level = header_level(name);
+                                for inner in &mut levels[level..] {
+                                    inner.clear();
+                                }
+                                if name == "group" {
+                                    group += 1;
+                                }
+                                Section::Level(level)
+                            },
+                            _ => Section::Other,
+                        };
+                    },
+                    Token::Opcode(opcode, value) => match section {
+                        Section::Control if opcode == "default_path" => instrument.default_path = value.replace('\\', "/"),
+                        Section::Region => {
+                            if let Some(opcodes) = &mut region {
+                                opcodes.insert(opcode.to_string(), value.to_string());
+                            }
+                        },
+                        Section::Level(level) => {
+                            levels[level].insert(opcode.to_string(), value.to_string());
+                        },
+                        _ => {},
+                    },
+                }
+            }
+        }
+        
+        if let Some(opcodes) = region.take() {
+            instrument.push_region(&levels, opcodes, group);
+        }
+        
+        if instrument.regions.is_empty() {
+            return Err("SFZ file has no regions".to_string());
+        }
+        Ok(instrument)
+    }
+    
+    fn push_region(&mut self, levels: &[HashMap<String, String>; LEVELS], opcodes: HashMap<String, String>, group: usize) {
+        let mut merged = HashMap::new();
+        for level in levels {
+            merged.extend(level.iter().map(|(key, value)| (key.clone(), value.clone())));
+        }
+        merged.extend(opcodes);
+        
+        if merged.contains_key("sample") {
+            self.regions.push(SfzRegion { opcodes: merged, group });
+        }
+    }
+    
+    // Path of a region's sample, relative to the SFZ file
+    pub fn sample_path(&self, region: &SfzRegion) -> Option<String> {
+        let sample = region.get("sample")?.replace('\\', "/");
+        Some(format!("{}{}", self.default_path, sample))
+    }
+    
+    // Every distinct sample path, in the order regions use them
+    pub fn sample_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        for path in self.regions.iter().filter_map(|region| self.sample_path(region)) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+    
+    // Build a keymap, loading each sample once through `load_sample` (path to
+    // data and sample rate). Regions whose sample can't be loaded are skipped
+    // and their paths returned alongside the keymap.
+    pub fn to_keymap<F>(&self, mut load_sample: F) -> (Keymap, Vec<String>)
+    where
+        F: FnMut(&str) -> Option<LoadedSample>,
+    {
+        let mut keymap = Keymap::new();
+        let mut samples: HashMap<String, Option<LoadedSample>> = HashMap::new();
+        let mut missing = Vec::new();
+        let mut round_robins: HashMap<(usize, usize), usize> = HashMap::new();
+        
+        for region in &self.regions {
+            let Some(path) = self.sample_path(region) else {
+                continue;
+            };
+            let loaded = samples.entry(path.clone()).or_insert_with(|| load_sample(&path));
+            let Some((sample, sample_rate)) = loaded.clone() else {
+                if !missing.contains(&path) {
+                    missing.push(path);
+                }
+                continue;
+            };
+            
+            let mut zone = region_zone(region, sample, sample_rate);
+            zone.sample_name = path;
+            
+            // Regions of one <group> sharing a seq_length take turns
+            let length = region.number("seq_length").map_or(1, |length| length.max(1.0) as usize);
+            if length > 1 {
+                let position = region.number("seq_position").map_or(1, |position| position.max(1.0) as usize);
+                let group = *round_robins.entry((region.group, length))
+                    .or_insert_with(|| keymap.add_group(AlternationGroup::new(AlternationMode::RoundRobin, length)));
+                zone.alternation = Some((group, position - 1));
+            }
+            
+            keymap.add_zone(zone);
+        }
+        
+        (keymap, missing)
+    }
+}
+
+// The header whose opcodes are being read
+#[derive(Clone, Copy)]
+enum Section {
+    Control,
+    Level(usize),  // <global>, <master> or <group>
+    Region,
+    Other,         // Unsupported headers, whose opcodes are ignored
+}
+
+fn header_level(name: &str) -> usize {
+    match name {
+        "global" => 0,
+        "master" => 1,
+        _ => 2,
+    }
+}
+
+// Map a region's opcodes onto a zone
+fn region_zone(region: &SfzRegion, sample: Rc<[f32]>, sample_rate: f32) -> Zone {
+    let mut zone = Zone::new(sample, sample_rate);
+    
+    // `key` sets the range and the root together
+    let key = region.note("key");
+    let low_key = region.note("lokey").or(key).unwrap_or(0);
+    let high_key = region.note("hikey").or(key).unwrap_or(127);
+    zone.key_range = (low_key, high_key);
+    zone.root_key = region.note("pitch_keycenter").or(key).unwrap_or(60);
+    zone.velocity_range = (region.velocity("lovel").unwrap_or(1), region.velocity("hivel").unwrap_or(127));
+    
+    zone.tune = region.number("tune").unwrap_or(0.0) + 100.0 * region.number("transpose").unwrap_or(0.0);
+    zone.gain = keymap::zone_gain(region.number("volume").unwrap_or(0.0));
+    zone.pan = (region.number("pan").unwrap_or(0.0) / 100.0).clamp(-1.0, 1.0);
+    
+    zone.loop_mode = match region.get("loop_mode").or_else(|| region.get("loopmode")) {
+        Some("one_shot") => LoopMode::OneShot,
+        Some("loop_continuous") => LoopMode::Continuous,
+        Some("loop_sustain") => LoopMode::Sustain,
+        _ => LoopMode::NoLoop,
+    };
+    let loop_point = |names: [&str; 2]| names.iter().find_map(|name| region.number(name)).map(|value| value.max(0.0) as usize);
+    if let Some(start) = loop_point(["loop_start", "loopstart"]) {
+        zone.loop_start = start;
+    }
+    if let Some(end) = loop_point(["loop_end", "loopend"]) {
+        zone.loop_end = end + 1;  // SFZ loop ends are inclusive
+    }
+    
+    // Velocity crossfades
+    let fade = |low: &str, high: &str| Some((region.velocity(low)?, region.velocity(high)?));
+    zone.velocity_fade_in = fade("xfin_lovel", "xfin_hivel");
+    zone.velocity_fade_out = fade("xfout_lovel", "xfout_hivel");
+    
+    // Only regions that shape their envelope replace the instrument's
+    if region.opcodes.keys().any(|opcode| opcode.starts_with("ampeg_")) {
+        zone.amp_envelope = Some((
+            region.number("ampeg_attack").unwrap_or(0.0),
+            region.number("ampeg_decay").unwrap_or(0.0),
+            region.number("ampeg_hold").unwrap_or(0.0),
+            region.number("ampeg_sustain").unwrap_or(100.0) / 100.0,
+            region.number("ampeg_release").unwrap_or(0.001),
+        ));
+    }
+    
+    if let Some(cutoff) = region.number("cutoff") {
+        let filter_type = match region.get("fil_type").unwrap_or("lpf_2p") {
+            kind if kind.starts_with("hpf") => FilterType::HighPass,
+            kind if kind.starts_with("bpf") => FilterType::BandPass,
+            kind if kind.starts_with("brf") => FilterType::Notch,
+            _ => FilterType::LowPass,
+        };
+        // Resonance is a gain in dB above the 0.707 Q of a flat response
+        let resonance = 0.707 * 10.0f32.powf(region.number("resonance").unwrap_or(0.0) / 20.0);
+        zone.filter = Some(ZoneFilter { filter_type, cutoff, resonance });
+    }
+    
+    zone
+}
+
+enum Token<'a> {
+    Header(&'a str),
+    Opcode(&'a str, &'a str),
+}
+
+// Split a line into headers and opcodes. Values run until the next header or
+// opcode, so sample paths may contain spaces.
+fn tokenize(line: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    
+    while !rest.is_empty() {
+        if let Some(header) = rest.strip_prefix('<') {
+            let end = header.find('>').ok_or_else(|| format!("Unterminated SFZ header: '{}'", line))?;
+            tokens.push(Token::Header(&header[..end]));
+            rest = header[end + 1..].trim_start();
+            continue;
+        }
+        
+        let equals = rest.find('=').ok_or_else(|| format!("Invalid SFZ opcode: '{}'", rest))?;
+        let opcode = rest[..equals].trim();
+        if opcode.is_empty() || opcode.contains(char::is_whitespace) {
+            return Err(format!("Invalid SFZ opcode: '{}'", rest));
+        }
+        
+        let value_text = &rest[equals + 1..];
+        let end = value_end(value_text);
+        tokens.push(Token::Opcode(opcode, value_text[..end].trim()));
+        rest = value_text[end..].trim_start();
+    }
+    
+    Ok(tokens)
+}
+
+// Where a value ends: at a header, or at the whitespace before the next `name=`
+fn value_end(text: &str) -> usize {
+    let mut end = text.find('<').unwrap_or(text.len());
+    for (index, _) in text[..end].match_indices(char::is_whitespace) {
+        let word = text[index..end].trim_start();
+        let name_length = word.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(word.len());
+        if name_length > 0 && word[name_length..].starts_with('=') {
+            end = index;
+            break;
+        }
+    }
+    end
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    
+    while !rest.is_empty() {
+        match (rest.find("//"), rest.find("/*")) {
+            (Some(line), block) if block.is_none_or(|block| line < block) => {
+                result.push_str(&rest[..line]);
+                rest = &rest[line..];
+                rest = rest.find('\n').map_or("", |end| &rest[end..]);
+            },
+            (_, Some(block)) => {
+                result.push_str(&rest[..block]);
+                // Keep line breaks inside the comment so lines stay apart
+                let after = &rest[block + 2..];
+                let end = after.find("*/").map_or(after.len(), |end| end + 2);
+                result.extend(after[..end.min(after.len())].chars().filter(|&c| c == '\n'));
+                rest = after.get(end..).unwrap_or("");
+            },
+            _ => {
+                result.push_str(rest);
+                break;
+            },
+        }
+    }
+    result
+}
\ No newline at end of file
@@ -0,0 +1,292 @@
+use std::rc::Rc;
+
+use crate::effects::filter::FilterType;
+use crate::resample::{self, ResampleQuality};
+use crate::utils::{self, XorShiftRng};
+use crate::voice::ROOT_KEY;
+
+// How a zone's sample loops
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopMode {
+    NoLoop,      // Play to the end of the sample or until the envelope finishes
+    OneShot,     // Play to the end of the sample, ignoring note-off
+    Continuous,  // Loop for as long as the voice sounds
+    Sustain,     // Loop until note-off, then play on past the loop end
+}
+
+impl LoopMode {
+    pub fn from_index(index: u32) -> Self {
+        match index {
+            1 => LoopMode::OneShot,
+            2 => LoopMode::Continuous,
+            3 => LoopMode::Sustain,
+            _ => LoopMode::NoLoop,
+        }
+    }
+    
+    pub fn index(&self) -> u32 {
+        match self {
+            LoopMode::NoLoop => 0,
+            LoopMode::OneShot => 1,
+            LoopMode::Continuous => 2,
+            LoopMode::Sustain => 3,
+        }
+    }
+}
+
+// How zones sharing an alternation group take turns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlternationMode {
+    RoundRobin,
+    Random,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlternationGroup {
+    pub mode: AlternationMode,
+    pub length: usize,  // Number of positions zones of the group are spread over
+    counter: usize,
+    triggered: bool,  // A zone of this group matched the current note-on
+    selected: usize,
+}
+
+impl AlternationGroup {
+    pub fn new(mode: AlternationMode, length: usize) -> Self {
+        AlternationGroup {
+            mode,
+            length: length.max(1),
+            counter: 0,
+            triggered: false,
+            selected: 0,
+        }
+    }
+}
+
+// Per-zone filter settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneFilter {
+    pub filter_type: FilterType,
+    pub cutoff: f32,     // Hz
+    pub resonance: f32,  // Q
+}
+
+// Original sample and rate, and the converted copy
+type ConvertedSample = (Rc<[f32]>, f32, Rc<[f32]>);
+
+// One sample mapped onto a key and velocity range
+#[derive(Debug, Clone)]
+pub struct Zone {
+    pub sample: Rc<[f32]>,  // Shared between zones that play the same sample
+    pub sample_rate: f32,
+    pub source_rate: f32,  // Rate the sample was loaded at, before any conversion
+    pub sample_name: String,  // File the sample came from, if it has one
+    pub root_key: u8,  // Key that plays the sample at its original pitch
+    pub key_range: (u8, u8),
+    pub velocity_range: (u8, u8),
+    pub tune: f32,  // Cents
+    pub gain: f32,  // Linear
+    pub pan: f32,   // -1 (left) to 1 (right)
+    pub loop_mode: LoopMode,
+    pub loop_start: usize,
+    pub loop_end: usize,  // Exclusive
+    
+    // Velocity crossfades: gain rises across `fade_in` and falls across `fade_out`
+    pub velocity_fade_in: Option<(u8, u8)>,
+    pub velocity_fade_out: Option<(u8, u8)>,
+    
+    // Alternation group index and this zone's position in it
+    pub alternation: Option<(usize, usize)>,
+    
+    // Amplitude envelope (attack, decay, hold, sustain, release) replacing the
+    // instrument's, and a filter for this zone alone
+    pub amp_envelope: Option<(f32, f32, f32, f32, f32)>,
+    pub filter: Option<ZoneFilter>,
+}
+
+impl Zone {
+    // A zone playing `sample` across the whole keyboard at every velocity
+    pub fn new(sample: Rc<[f32]>, sample_rate: f32) -> Self {
+        let loop_end = sample.len();
+        Zone {
+            sample,
+            sample_rate,
+            source_rate: sample_rate,
+            sample_name: String::new(),
+            root_key: ROOT_KEY,
+            key_range: (0, 127),
+            velocity_range: (1, 127),
+            tune: 0.0,
+            gain: 1.0,
+            pan: 0.0,
+            loop_mode: LoopMode::NoLoop,
+            loop_start: 0,
+            loop_end,
+            velocity_fade_in: None,
+            velocity_fade_out: None,
+            alternation: None,
+            amp_envelope: None,
+            filter: None,
+        }
+    }
+    
+    pub fn contains(&self, note: u8, velocity: u8) -> bool {
+        (self.key_range.0..=self.key_range.1).contains(&note)
+            && (self.velocity_range.0..=self.velocity_range.1).contains(&velocity)
+    }
+    
+    // Equal-power gain of the velocity crossfades at `velocity`
+    pub fn velocity_gain(&self, velocity: u8) -> f32 {
+        let ramp = |(low, high): (u8, u8)| {
+            if high <= low {
+                return if velocity >= high { 1.0 } else { 0.0 };
+            }
+            ((velocity as f32 - low as f32) / (high - low) as f32).clamp(0.0, 1.0)
+        };
+        
+        let fade_in = self.velocity_fade_in.map_or(1.0, ramp);
+        let fade_out = self.velocity_fade_out.map_or(1.0, |range| 1.0 - ramp(range));
+        (fade_in * fade_out).sqrt()
+    }
+    
+    // Pitch offset in semitones for playing this zone, relative to a sample rooted at ROOT_KEY
+    pub fn pitch_offset(&self) -> f32 {
+        ROOT_KEY as f32 - self.root_key as f32 + self.tune / 100.0
+    }
+    
+    // Loop region clamped to the sample, or None if the zone doesn't loop
+    pub fn loop_region(&self) -> Option<(usize, usize)> {
+        let end = self.loop_end.min(self.sample.len());
+        match self.loop_mode {
+            LoopMode::Continuous | LoopMode::Sustain if self.loop_start + 1 < end => Some((self.loop_start, end)),
+            _ => None,
+        }
+    }
+}
+
+// The zones of a multisampled instrument
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    zones: Vec<Zone>,
+    groups: Vec<AlternationGroup>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Keymap::default()
+    }
+    
+    // A single sample across the whole keyboard
+    pub fn single(sample: Rc<[f32]>, sample_rate: f32) -> Self {
+        let mut keymap = Keymap::new();
+        keymap.add_zone(Zone::new(sample, sample_rate));
+        keymap
+    }
+    
+    pub fn clear(&mut self) {
+        self.zones.clear();
+        self.groups.clear();
+    }
+    
+    pub fn add_zone(&mut self, zone: Zone) -> usize {
+        self.zones.push(zone);
+        self.zones.len() - 1
+    }
+    
+    pub fn add_group(&mut self, group: AlternationGroup) -> usize {
+        self.groups.push(group);
+        self.groups.len() - 1
+    }
+    
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+    
+    pub fn zone(&self, index: usize) -> Option<&Zone> {
+        self.zones.get(index)
+    }
+    
+    pub fn zone_mut(&mut self, index: usize) -> Option<&mut Zone> {
+        self.zones.get_mut(index)
+    }
+    
+    // Convert every zone's sample to `sample_rate`, scaling loop points to match.
+    // Zones sharing a sample keep sharing the converted copy.
+    pub fn resample(&mut self, sample_rate: f32, quality: ResampleQuality) {
+        let mut converted: Vec<ConvertedSample> = Vec::new();
+        for zone in &mut self.zones {
+            if zone.sample_rate == sample_rate || zone.sample_rate <= 0.0 {
+                continue;
+            }
+            
+            let cached = converted.iter()
+                .find(|(original, rate, _)| Rc::ptr_eq(original, &zone.sample) && *rate == zone.sample_rate)
+                .map(|(_, _, sample)| sample.clone());
+            let sample = cached.unwrap_or_else(|| {
+                let sample: Rc<[f32]> = Rc::from(resample::resample(&zone.sample, zone.sample_rate, sample_rate, quality));
+                converted.push((zone.sample.clone(), zone.sample_rate, sample.clone()));
+                sample
+            });
+            
+            let ratio = sample_rate as f64 / zone.sample_rate as f64;
+            zone.loop_start = (zone.loop_start as f64 * ratio).round() as usize;
+            zone.loop_end = (zone.loop_end as f64 * ratio).round() as usize;
+            zone.sample = sample;
+            zone.sample_rate = sample_rate;
+        }
+    }
+    
+    pub fn groups(&self) -> &[AlternationGroup] {
+        &self.groups
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+    
+    // Sample data of a zone, empty if the zone no longer exists
+    pub fn sample(&self, index: usize) -> &[f32] {
+        self.zones.get(index).map_or(&[], |zone| &zone.sample)
+    }
+    
+    // Collect the zones to voice for a note-on as (zone index, gain). Each alternation
+    // group with a matching zone moves on one step, so repeated notes cycle through it.
+    pub fn select(&mut self, note: u8, velocity: u8, rng: &mut XorShiftRng, selection: &mut Vec<(usize, f32)>) {
+        for group in &mut self.groups {
+            group.triggered = false;
+        }
+        for zone in self.zones.iter().filter(|zone| zone.contains(note, velocity)) {
+            if let Some(group) = zone.alternation.and_then(|(group, _)| self.groups.get_mut(group)) {
+                group.triggered = true;
+            }
+        }
+        for group in self.groups.iter_mut().filter(|group| group.triggered) {
+            group.selected = match group.mode {
+                AlternationMode::RoundRobin => {
+                    let selected = group.counter % group.length;
+                    group.counter = group.counter.wrapping_add(1);
+                    selected
+                },
+                AlternationMode::Random => (rng.next_f32() * group.length as f32) as usize % group.length,
+            };
+        }
+        
+        for (index, zone) in self.zones.iter().enumerate() {
+            if !zone.contains(note, velocity) {
+                continue;
+            }
+            let chosen = match zone.alternation {
+                Some((group, position)) => self.groups.get(group).is_none_or(|group| group.selected == position),
+                None => true,
+            };
+            let gain = zone.gain * zone.velocity_gain(velocity);
+            if chosen && gain > 0.0 {
+                selection.push((index, gain));
+            }
+        }
+    }
+}
+
+// Convert zone gain in dB, as instrument formats store it, to linear
+pub fn zone_gain(db: f32) -> f32 {
+    utils::db_to_linear(db.clamp(-144.0, 24.0))
+}
\ No newline at end of file
@@ -0,0 +1,235 @@
+use std::f32::consts::PI;
+
+use crate::utils::XorShiftRng;
+
+pub const NUM_LFOS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    SampleAndHold,
+    SmoothRandom,
+}
+
+impl LfoShape {
+    // 0: Sine, 1: Triangle, 2: Saw, 3: Square, 4: Sample & Hold, 5: Smoothed Random
+    pub fn from_index(index: u32) -> LfoShape {
+        match index {
+            0 => LfoShape::Sine,
+            1 => LfoShape::Triangle,
+            2 => LfoShape::Saw,
+            3 => LfoShape::Square,
+            4 => LfoShape::SampleAndHold,
+            5 => LfoShape::SmoothRandom,
+            _ => LfoShape::Sine,
+        }
+    }
+    
+    pub fn index(&self) -> u32 {
+        match self {
+            LfoShape::Sine => 0,
+            LfoShape::Triangle => 1,
+            LfoShape::Saw => 2,
+            LfoShape::Square => 3,
+            LfoShape::SampleAndHold => 4,
+            LfoShape::SmoothRandom => 5,
+        }
+    }
+}
+
+pub struct Lfo {
+    // LFO parameters
+    shape: LfoShape,
+    rate_hz: f32,
+    sync_beats: Option<f32>,  // Cycle length in beats when tempo-synced
+    tempo_bpm: f32,
+    phase_offset: f32,        // 0 to 1, fraction of a cycle
+    retrigger: bool,
+    fade_in_time: f32,        // Seconds
+    
+    // Current state
+    phase: f32,
+    fade_level: f32,
+    current_value: f32,
+    
+    // Random shapes
+    held_value: f32,
+    previous_random: f32,
+    next_random: f32,
+    rng: XorShiftRng,
+    
+    // Sample rate for time calculations
+    sample_rate: f32,
+}
+
+impl Lfo {
+    pub fn new(sample_rate: f32) -> Self {
+        Lfo::with_seed(sample_rate, 1)
+    }
+    
+    pub fn with_seed(sample_rate: f32, seed: u32) -> Self {
+        let mut rng = XorShiftRng::new(seed);
+        let previous_random = rng.next_bipolar();
+        let next_random = rng.next_bipolar();
+        
+        Lfo {
+            shape: LfoShape::Sine,
+            rate_hz: 1.0,          // 1Hz default rate
+            sync_beats: None,
+            tempo_bpm: 120.0,
+            phase_offset: 0.0,
+            retrigger: false,
+            fade_in_time: 0.0,
+            
+            phase: 0.0,
+            fade_level: 1.0,
+            current_value: 0.0,
+            
+            held_value: previous_random,
+            previous_random,
+            next_random,
+            rng,
+            
+            sample_rate,
+        }
+    }
+    
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+    
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+    
+    pub fn shape(&self) -> LfoShape {
+        self.shape
+    }
+    
+    // Free-running rate in Hz; disables tempo sync
+    pub fn set_rate(&mut self, rate_hz: f32) {
+        self.rate_hz = rate_hz.clamp(0.001, 100.0);
+        self.sync_beats = None;
+    }
+    
+    // Tempo-synced rate as the length of one cycle in beats (e.g. 0.25 for sixteenths)
+    pub fn set_sync(&mut self, beats: f32) {
+        self.sync_beats = if beats > 0.0 { Some(beats) } else { None };
+    }
+    
+    // Free-running rate in Hz, kept while tempo-synced
+    pub fn rate(&self) -> f32 {
+        self.rate_hz
+    }
+    
+    // Cycle length in beats, None when free-running
+    pub fn sync(&self) -> Option<f32> {
+        self.sync_beats
+    }
+    
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.tempo_bpm = bpm.clamp(1.0, 999.0);
+    }
+    
+    pub fn set_phase_offset(&mut self, offset: f32) {
+        self.phase_offset = offset.rem_euclid(1.0);
+    }
+    
+    pub fn phase_offset(&self) -> f32 {
+        self.phase_offset
+    }
+    
+    pub fn set_retrigger(&mut self, retrigger: bool) {
+        self.retrigger = retrigger;
+    }
+    
+    pub fn retrigger(&self) -> bool {
+        self.retrigger
+    }
+    
+    pub fn set_fade_in(&mut self, fade_in_seconds: f32) {
+        self.fade_in_time = fade_in_seconds.max(0.0);
+        if self.fade_in_time == 0.0 {
+            self.fade_level = 1.0;
+        }
+    }
+    
+    pub fn fade_in(&self) -> f32 {
+        self.fade_in_time
+    }
+    
+    // Current rate in Hz, taking tempo sync into account
+    pub fn frequency(&self) -> f32 {
+        match self.sync_beats {
+            Some(beats) => self.tempo_bpm / 60.0 / beats,
+            None => self.rate_hz,
+        }
+    }
+    
+    // Called on note-on: restarts the cycle when retriggering and always restarts the fade-in
+    pub fn trigger(&mut self) {
+        if self.retrigger {
+            self.phase = 0.0;
+        }
+        
+        if self.fade_in_time > 0.0 {
+            self.fade_level = 0.0;
+        }
+    }
+    
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.fade_level = if self.fade_in_time > 0.0 { 0.0 } else { 1.0 };
+        self.current_value = 0.0;
+    }
+    
+    // Advance by one sample and return the bipolar output (-1 to 1)
+    pub fn process(&mut self) -> f32 {
+        let p = (self.phase + self.phase_offset).fract();
+        
+        let value = match self.shape {
+            LfoShape::Sine => (2.0 * PI * p).sin(),
+            LfoShape::Triangle => {
+                if p < 0.25 {
+                    4.0 * p
+                } else if p < 0.75 {
+                    2.0 - 4.0 * p
+                } else {
+                    4.0 * p - 4.0
+                }
+            },
+            LfoShape::Saw => 2.0 * p - 1.0,
+            LfoShape::Square => if p < 0.5 { 1.0 } else { -1.0 },
+            LfoShape::SampleAndHold => self.held_value,
+            LfoShape::SmoothRandom => {
+                // Smoothstep between consecutive random values, which change on cycle boundaries
+                let t = p * p * (3.0 - 2.0 * p);
+                self.previous_random + (self.next_random - self.previous_random) * t
+            },
+        };
+        
+        // Advance the phase, drawing new random values each time the offset phase
+        // wraps, so the random shapes follow the phase offset like the others
+        self.phase = (self.phase + self.frequency() / self.sample_rate).fract();
+        if (self.phase + self.phase_offset).fract() < p {
+            self.previous_random = self.next_random;
+            self.next_random = self.rng.next_bipolar();
+            self.held_value = self.rng.next_bipolar();
+        }
+        
+        if self.fade_level < 1.0 {
+            self.fade_level = (self.fade_level + 1.0 / (self.fade_in_time * self.sample_rate)).min(1.0);
+        }
+        
+        self.current_value = value * self.fade_level;
+        self.current_value
+    }
+    
+    // Last output without advancing
+    pub fn value(&self) -> f32 {
+        self.current_value
+    }
+}
\ No newline at end of file
@@ -1,9 +1,24 @@
 use wasm_bindgen::prelude::*;
 
-mod processor;
-mod envelope;
-mod effects;
-mod utils;
+pub mod processor;
+pub mod envelope;
+pub mod mseg;
+pub mod lfo;
+pub mod follower;
+pub mod midi;
+pub mod voice;
+pub mod keymap;
+pub mod instrument;
+pub mod audio_file;
+pub mod resample;
+pub mod preset;
+pub mod project;
+pub mod tuning;
+pub mod mod_matrix;
+pub mod macro_controls;
+pub mod effects;
+pub mod automation;
+pub mod utils;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator.
 #[cfg(feature = "wee_alloc")]
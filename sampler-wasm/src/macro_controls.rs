@@ -0,0 +1,150 @@
+use crate::automation::ParameterTarget;
+use crate::mod_matrix::ModCurve;
+
+pub const NUM_MACROS: usize = 8;
+
+// One parameter moved by a macro, mapped from the macro's 0-1 range onto min..max.
+// A max below min inverts the direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroTarget {
+    pub effect_id: usize,
+    pub param: String,
+    pub min: f32,
+    pub max: f32,
+    pub curve: ModCurve,
+}
+
+impl MacroTarget {
+    pub fn value_for(&self, macro_value: f32) -> f32 {
+        self.min + (self.max - self.min) * self.curve.apply(macro_value.clamp(0.0, 1.0))
+    }
+}
+
+pub struct MacroControl {
+    name: String,
+    value: f32,
+    cc: Option<u8>,
+    targets: Vec<MacroTarget>,
+}
+
+impl MacroControl {
+    pub fn new(name: &str) -> Self {
+        MacroControl {
+            name: name.to_string(),
+            value: 0.0,
+            cc: None,
+            targets: Vec::new(),
+        }
+    }
+    
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+    
+    pub fn cc(&self) -> Option<u8> {
+        self.cc
+    }
+    
+    pub fn targets(&self) -> &[MacroTarget] {
+        &self.targets
+    }
+}
+
+// The bank of macro knobs available for live performance
+pub struct MacroBank {
+    macros: Vec<MacroControl>,
+}
+
+impl MacroBank {
+    pub fn new() -> Self {
+        MacroBank {
+            macros: (0..NUM_MACROS).map(|i| MacroControl::new(&format!("Macro {}", i + 1))).collect(),
+        }
+    }
+    
+    pub fn get(&self, index: usize) -> Option<&MacroControl> {
+        self.macros.get(index)
+    }
+    
+    pub fn set_name(&mut self, index: usize, name: &str) {
+        if let Some(control) = self.macros.get_mut(index) {
+            control.name = name.to_string();
+        }
+    }
+    
+    // Add a target, replacing any existing mapping of the same parameter
+    pub fn add_target(&mut self, index: usize, target: MacroTarget) -> bool {
+        let control = match self.macros.get_mut(index) {
+            Some(control) => control,
+            None => return false,
+        };
+        
+        control.targets.retain(|t| !(t.effect_id == target.effect_id && t.param == target.param));
+        control.targets.push(target);
+        true
+    }
+    
+    pub fn remove_target(&mut self, index: usize, effect_id: usize, param: &str) {
+        if let Some(control) = self.macros.get_mut(index) {
+            control.targets.retain(|t| !(t.effect_id == effect_id && t.param == param));
+        }
+    }
+    
+    pub fn clear_targets(&mut self, index: usize) {
+        if let Some(control) = self.macros.get_mut(index) {
+            control.targets.clear();
+        }
+    }
+    
+    // Assign a MIDI CC to a macro, or None to unassign
+    pub fn assign_cc(&mut self, index: usize, cc: Option<u8>) {
+        if index >= self.macros.len() {
+            return;
+        }
+        
+        // A controller drives at most one macro
+        if cc.is_some() {
+            for control in &mut self.macros {
+                if control.cc == cc {
+                    control.cc = None;
+                }
+            }
+        }
+        
+        self.macros[index].cc = cc.filter(|&cc| cc < 128);
+    }
+    
+    // Move a macro to a normalized value and push it to every target
+    pub fn set_value<T: ParameterTarget + ?Sized>(&mut self, index: usize, value: f32, target: &mut T) {
+        let control = match self.macros.get_mut(index) {
+            Some(control) => control,
+            None => return,
+        };
+        
+        control.value = value.clamp(0.0, 1.0);
+        for macro_target in &control.targets {
+            target.set_parameter(macro_target.effect_id, &macro_target.param, macro_target.value_for(control.value));
+        }
+    }
+    
+    // Route an incoming controller value (0 to 1) to its macro. Returns true if a macro is assigned.
+    pub fn handle_cc<T: ParameterTarget + ?Sized>(&mut self, cc: u8, value: f32, target: &mut T) -> bool {
+        match self.macros.iter().position(|control| control.cc == Some(cc)) {
+            Some(index) => {
+                self.set_value(index, value, target);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl Default for MacroBank {
+    fn default() -> Self {
+        MacroBank::new()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,235 @@
+use super::{MidiMessage, CLOCKS_PER_QUARTER, CLOCKS_PER_SONG_POSITION};
+
+// Loop gains: how much of each tick's timing error corrects the phase and the period
+const PHASE_GAIN: f64 = 0.2;
+const PERIOD_GAIN: f64 = 0.02;
+
+// Tempo range accepted from an external clock
+const MIN_CLOCK_BPM: f64 = 20.0;
+const MAX_CLOCK_BPM: f64 = 400.0;
+
+// Follows an incoming MIDI clock. A phase-locked loop predicts when each tick is due,
+// and the prediction error trims both the predicted phase and the tick period, which
+// smooths out the jitter of USB/Web MIDI timestamps into a steady tempo.
+pub struct ClockFollower {
+    sample_rate: f64,
+    period: f64,                   // Estimated frames per clock tick
+    predicted_frame: Option<f64>,  // When the loop expects the most recent tick
+    last_frame: Option<u64>,
+    locked: bool,
+    
+    // Song position from start/continue/stop and song position pointer
+    running: bool,
+    position_ticks: u64,
+}
+
+impl ClockFollower {
+    pub fn new(sample_rate: f32) -> Self {
+        ClockFollower {
+            sample_rate: sample_rate as f64,
+            period: 0.0,
+            predicted_frame: None,
+            last_frame: None,
+            locked: false,
+            running: false,
+            position_ticks: 0,
+        }
+    }
+    
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate as f64;
+        self.unlock();
+    }
+    
+    fn period_range(&self) -> (f64, f64) {
+        let ticks_per_second = |bpm: f64| bpm / 60.0 * CLOCKS_PER_QUARTER as f64;
+        (self.sample_rate / ticks_per_second(MAX_CLOCK_BPM), self.sample_rate / ticks_per_second(MIN_CLOCK_BPM))
+    }
+    
+    fn unlock(&mut self) {
+        self.predicted_frame = None;
+        self.last_frame = None;
+        self.locked = false;
+    }
+    
+    // A clock tick arrived at `frame`
+    pub fn tick(&mut self, frame: u64) {
+        if self.running {
+            self.position_ticks += 1;
+        }
+        
+        let (min_period, max_period) = self.period_range();
+        let last_frame = self.last_frame.replace(frame);
+        
+        let predicted = match (self.predicted_frame, last_frame) {
+            (Some(predicted), _) => predicted + self.period,
+            (None, Some(last)) => {
+                // Second tick: seed the loop with the measured interval
+                let measured = frame.saturating_sub(last) as f64;
+                if measured >= min_period && measured <= max_period {
+                    self.period = measured;
+                    self.predicted_frame = Some(frame as f64);
+                    self.locked = true;
+                }
+                return;
+            },
+            (None, None) => return,
+        };
+        
+        let error = frame as f64 - predicted;
+        
+        // Way off (tempo jump or dropped ticks): start over from this tick
+        if error.abs() > self.period * 4.0 {
+            self.unlock();
+            self.last_frame = Some(frame);
+            return;
+        }
+        
+        self.predicted_frame = Some(predicted + PHASE_GAIN * error);
+        self.period = (self.period + PERIOD_GAIN * error).clamp(min_period, max_period);
+    }
+    
+    // Drop lock if the clock has gone quiet for more than a few ticks
+    pub fn check_timeout(&mut self, frame: u64) {
+        if let Some(last) = self.last_frame {
+            let (_, max_period) = self.period_range();
+            if (frame.saturating_sub(last)) as f64 > max_period * 4.0 {
+                self.unlock();
+            }
+        }
+    }
+    
+    // Apply start/continue/stop/song position. Returns true if the message was one of those.
+    pub fn handle(&mut self, message: &MidiMessage) -> bool {
+        match *message {
+            MidiMessage::Start => {
+                self.position_ticks = 0;
+                self.running = true;
+            },
+            MidiMessage::Continue => self.running = true,
+            MidiMessage::Stop => self.running = false,
+            MidiMessage::SongPosition(position) => {
+                self.position_ticks = position as u64 * CLOCKS_PER_SONG_POSITION as u64;
+            },
+            _ => return false,
+        }
+        true
+    }
+    
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+    
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+    
+    // Estimated tempo, or None until the loop has locked
+    pub fn tempo(&self) -> Option<f32> {
+        if !self.locked {
+            return None;
+        }
+        Some((60.0 * self.sample_rate / (self.period * CLOCKS_PER_QUARTER as f64)) as f32)
+    }
+    
+    // Song position in quarter notes
+    pub fn beat_position(&self) -> f64 {
+        self.position_ticks as f64 / CLOCKS_PER_QUARTER as f64
+    }
+}
+
+// Produces MIDI clock, transport and song position bytes that follow the processor's transport
+pub struct ClockGenerator {
+    enabled: bool,
+    running: bool,
+    next_tick: u64,  // Index of the next clock tick to send, counted from the song start
+    output: Vec<u8>,
+}
+
+impl ClockGenerator {
+    pub fn new() -> Self {
+        ClockGenerator {
+            enabled: false,
+            running: false,
+            next_tick: 0,
+            output: Vec::new(),
+        }
+    }
+    
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled && self.enabled && self.running {
+            self.output.extend_from_slice(&MidiMessage::Stop.to_bytes());
+        }
+        self.enabled = enabled;
+        self.running = false;
+    }
+    
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    
+    // The transport started (or resumed) at `beats` quarter notes
+    pub fn start(&mut self, beats: f64) {
+        if !self.enabled {
+            return;
+        }
+        
+        let message = if beats <= 0.0 { MidiMessage::Start } else { MidiMessage::Continue };
+        self.locate(beats);
+        self.output.extend_from_slice(&message.to_bytes());
+        self.running = true;
+    }
+    
+    pub fn stop(&mut self) {
+        if self.enabled && self.running {
+            self.output.extend_from_slice(&MidiMessage::Stop.to_bytes());
+        }
+        self.running = false;
+    }
+    
+    // Send a song position pointer for `beats`, rounded down to a sixteenth
+    pub fn locate(&mut self, beats: f64) {
+        if !self.enabled {
+            return;
+        }
+        
+        let sixteenths = (beats.max(0.0) * 4.0).floor().min(16383.0) as u16;
+        self.output.extend_from_slice(&MidiMessage::SongPosition(sixteenths).to_bytes());
+        self.next_tick = sixteenths as u64 * CLOCKS_PER_SONG_POSITION as u64;
+    }
+    
+    // Emit any clock ticks due up to `beats` quarter notes
+    pub fn advance(&mut self, beats: f64) {
+        if !self.enabled || !self.running {
+            return;
+        }
+        
+        let due = (beats * CLOCKS_PER_QUARTER as f64).floor() as u64;
+        
+        // A jump of more than a bar without `locate` resynchronizes instead of flooding ticks
+        if due > self.next_tick + 4 * CLOCKS_PER_QUARTER as u64 {
+            self.locate(beats);
+            return;
+        }
+        
+        while self.next_tick <= due {
+            self.output.push(super::TIMING_CLOCK);
+            self.next_tick += 1;
+        }
+    }
+    
+    pub fn output_len(&self) -> usize {
+        self.output.len()
+    }
+    
+    // Bytes generated since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl Default for ClockGenerator {
+    fn default() -> Self {
+        ClockGenerator::new()
+    }
+}
\ No newline at end of file
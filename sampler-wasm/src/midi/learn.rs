@@ -0,0 +1,368 @@
+use serde::{Deserialize, Serialize};
+
+use crate::macro_controls::NUM_MACROS;
+use crate::mod_matrix::ModCurve;
+
+// Controllers used to select and enter NRPN / RPN values
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_NRPN_LSB: u8 = 98;
+const CC_NRPN_MSB: u8 = 99;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+
+// How close (as a fraction of the mapped range) a controller must come to the
+// parameter's current value before soft takeover lets it through
+const TAKEOVER_TOLERANCE: f32 = 0.02;
+
+// A hardware control a mapping listens to, saved by its identifier
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum ControllerId {
+    Cc(u8),
+    Nrpn(u16),  // 14-bit parameter number
+}
+
+impl ControllerId {
+    // Parse an identifier such as "cc:74" or "nrpn:1024"
+    pub fn parse(id: &str) -> Option<ControllerId> {
+        let (kind, number) = id.split_once(':')?;
+        let number = number.parse::<u16>().ok()?;
+        match kind {
+            "cc" if number < 128 => Some(ControllerId::Cc(number as u8)),
+            "nrpn" if number < 16384 => Some(ControllerId::Nrpn(number)),
+            _ => None,
+        }
+    }
+    
+    pub fn id(&self) -> String {
+        match self {
+            ControllerId::Cc(cc) => format!("cc:{}", cc),
+            ControllerId::Nrpn(number) => format!("nrpn:{}", number),
+        }
+    }
+}
+
+impl From<ControllerId> for String {
+    fn from(controller: ControllerId) -> String {
+        controller.id()
+    }
+}
+
+impl TryFrom<String> for ControllerId {
+    type Error = String;
+    
+    fn try_from(id: String) -> Result<Self, String> {
+        ControllerId::parse(&id).ok_or_else(|| format!("Unknown MIDI controller '{}'", id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnvelopeParam {
+    Attack,
+    Decay,
+    Hold,
+    Sustain,
+    Release,
+}
+
+impl EnvelopeParam {
+    fn parse(name: &str) -> Option<EnvelopeParam> {
+        match name {
+            "attack" => Some(EnvelopeParam::Attack),
+            "decay" => Some(EnvelopeParam::Decay),
+            "hold" => Some(EnvelopeParam::Hold),
+            "sustain" => Some(EnvelopeParam::Sustain),
+            "release" => Some(EnvelopeParam::Release),
+            _ => None,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            EnvelopeParam::Attack => "attack",
+            EnvelopeParam::Decay => "decay",
+            EnvelopeParam::Hold => "hold",
+            EnvelopeParam::Sustain => "sustain",
+            EnvelopeParam::Release => "release",
+        }
+    }
+}
+
+// A parameter a controller can be learned onto, saved by its identifier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum LearnTarget {
+    EffectParameter { effect_id: usize, param: String },
+    Envelope { index: usize, param: EnvelopeParam },  // 0: amplitude envelope, 1: modulation envelope
+    Macro(usize),
+}
+
+impl LearnTarget {
+    // Parse an identifier such as "effect:0:cutoff", "env:0:attack" or "macro:2"
+    pub fn parse(id: &str) -> Option<LearnTarget> {
+        let mut parts = id.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("effect"), Some(effect_id), Some(param)) if !param.is_empty() => Some(LearnTarget::EffectParameter {
+                effect_id: effect_id.parse().ok()?,
+                param: param.to_string(),
+            }),
+            (Some("env"), Some(index), Some(param)) => {
+                let index = index.parse::<usize>().ok().filter(|&index| index < 2)?;
+                Some(LearnTarget::Envelope { index, param: EnvelopeParam::parse(param)? })
+            },
+            (Some("macro"), Some(index), None) => {
+                index.parse::<usize>().ok().filter(|&index| index < NUM_MACROS).map(LearnTarget::Macro)
+            },
+            _ => None,
+        }
+    }
+    
+    pub fn id(&self) -> String {
+        match self {
+            LearnTarget::EffectParameter { effect_id, param } => format!("effect:{}:{}", effect_id, param),
+            LearnTarget::Envelope { index, param } => format!("env:{}:{}", index, param.name()),
+            LearnTarget::Macro(index) => format!("macro:{}", index),
+        }
+    }
+}
+
+impl From<LearnTarget> for String {
+    fn from(target: LearnTarget) -> String {
+        target.id()
+    }
+}
+
+impl TryFrom<String> for LearnTarget {
+    type Error = String;
+    
+    fn try_from(id: String) -> Result<Self, String> {
+        LearnTarget::parse(&id).ok_or_else(|| format!("Unknown MIDI learn target '{}'", id))
+    }
+}
+
+// A learned controller-to-parameter assignment. The controller's 0-1 range maps
+// onto min..max through the curve; a max below min inverts the direction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub controller: ControllerId,
+    pub target: LearnTarget,
+    pub min: f32,
+    pub max: f32,
+    pub curve: ModCurve,
+    pub soft_takeover: bool,
+    
+    // Soft takeover state
+    #[serde(skip)]
+    picked_up: bool,
+    #[serde(skip)]
+    last_output: Option<f32>,   // Value the controller last asked for
+    #[serde(skip)]
+    last_written: Option<f32>,  // Parameter value right after we last wrote it
+}
+
+impl MidiMapping {
+    pub fn new(controller: ControllerId, target: LearnTarget, min: f32, max: f32, curve: ModCurve, soft_takeover: bool) -> Self {
+        MidiMapping {
+            controller,
+            target,
+            min,
+            max,
+            curve,
+            soft_takeover,
+            picked_up: false,
+            last_output: None,
+            last_written: None,
+        }
+    }
+    
+    // Check a mapping read from a file; its controller and target were checked as they were read
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.min.is_finite() || !self.max.is_finite() {
+            return Err(format!("MIDI mapping of '{}' has an invalid range", self.target.id()));
+        }
+        Ok(())
+    }
+    
+    pub fn value_for(&self, controller_value: f32) -> f32 {
+        self.min + (self.max - self.min) * self.curve.apply(controller_value.clamp(0.0, 1.0))
+    }
+    
+    // Decide whether a controller move reaches the parameter. `current` is the
+    // parameter's present value; with soft takeover the controller only takes
+    // over once it reaches or passes that value.
+    fn take_over(&mut self, controller_value: f32, current: Option<f32>) -> Option<f32> {
+        let output = self.value_for(controller_value);
+        let previous = self.last_output.replace(output);
+        
+        let current = match current {
+            Some(current) if self.soft_takeover => current,
+            _ => return Some(output),
+        };
+        
+        // Something else (automation, a preset, the UI) moved the parameter since our last write
+        if let Some(written) = self.last_written {
+            if (current - written).abs() > f32::EPSILON * current.abs().max(1.0) {
+                self.picked_up = false;
+            }
+        }
+        
+        if !self.picked_up {
+            let tolerance = (self.max - self.min).abs() * TAKEOVER_TOLERANCE;
+            let crossed = previous.is_some_and(|previous| (previous - current) * (output - current) <= 0.0);
+            if (output - current).abs() > tolerance && !crossed {
+                return None;
+            }
+            self.picked_up = true;
+        }
+        Some(output)
+    }
+}
+
+// NRPN selection and data entry state for one channel
+#[derive(Debug, Clone, Copy, Default)]
+struct NrpnState {
+    parameter_msb: Option<u8>,
+    parameter_lsb: Option<u8>,
+    rpn_selected: bool,
+    data_msb: Option<u8>,
+}
+
+impl NrpnState {
+    fn parameter(&self) -> Option<u16> {
+        if self.rpn_selected {
+            return None;
+        }
+        Some((self.parameter_msb? as u16) << 7 | self.parameter_lsb? as u16)
+    }
+}
+
+// MIDI learn: capture the next controller for an armed target and route
+// controllers to their mapped parameters
+pub struct MidiLearn {
+    mappings: Vec<MidiMapping>,
+    armed: Option<MidiMapping>,  // Template whose controller is filled in when learned
+    nrpn: [NrpnState; 16],
+}
+
+impl MidiLearn {
+    pub fn new() -> Self {
+        MidiLearn {
+            mappings: Vec::new(),
+            armed: None,
+            nrpn: [NrpnState::default(); 16],
+        }
+    }
+    
+    // Wait for the next controller and map it onto `target`
+    pub fn arm(&mut self, target: LearnTarget, min: f32, max: f32, curve: ModCurve, soft_takeover: bool) {
+        self.armed = Some(MidiMapping::new(ControllerId::Cc(0), target, min, max, curve, soft_takeover));
+    }
+    
+    pub fn disarm(&mut self) {
+        self.armed = None;
+    }
+    
+    pub fn is_armed(&self) -> bool {
+        self.armed.is_some()
+    }
+    
+    pub fn mappings(&self) -> &[MidiMapping] {
+        &self.mappings
+    }
+    
+    // Add a mapping, replacing any existing mapping of the same target
+    pub fn add_mapping(&mut self, mapping: MidiMapping) {
+        self.mappings.retain(|existing| existing.target != mapping.target);
+        self.mappings.push(mapping);
+    }
+    
+    pub fn remove_mapping(&mut self, target: &LearnTarget) {
+        self.mappings.retain(|mapping| mapping.target != *target);
+    }
+    
+    pub fn clear(&mut self) {
+        self.mappings.clear();
+        self.armed = None;
+    }
+    
+    // Decode a control change (value 0-127), following NRPN sequences. Returns the
+    // controller and its normalized value, or None if the message was only part of
+    // an NRPN/RPN selection or was captured by an armed learn.
+    pub fn handle_cc(&mut self, channel: u8, cc: u8, value: u8) -> Option<(ControllerId, f32)> {
+        let state = &mut self.nrpn[(channel & 0x0F) as usize];
+        
+        let event = match cc {
+            CC_NRPN_MSB => {
+                state.parameter_msb = Some(value);
+                state.rpn_selected = false;
+                state.data_msb = None;
+                None
+            },
+            CC_NRPN_LSB => {
+                state.parameter_lsb = Some(value);
+                state.rpn_selected = false;
+                state.data_msb = None;
+                None
+            },
+            CC_RPN_MSB | CC_RPN_LSB => {
+                state.rpn_selected = true;
+                None
+            },
+            CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB if state.rpn_selected => None,
+            CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB if state.parameter().is_some() => {
+                let parameter = state.parameter()?;
+                if cc == CC_DATA_ENTRY_MSB {
+                    state.data_msb = Some(value);
+                    Some((ControllerId::Nrpn(parameter), value as f32 / 127.0))
+                } else {
+                    let msb = state.data_msb?;
+                    let combined = (msb as u16) << 7 | value as u16;
+                    Some((ControllerId::Nrpn(parameter), combined as f32 / 16383.0))
+                }
+            },
+            _ => Some((ControllerId::Cc(cc), value as f32 / 127.0)),
+        };
+        
+        let (controller, value) = event?;
+        if let Some(mut mapping) = self.armed.take() {
+            mapping.controller = controller;
+            self.add_mapping(mapping);
+            return None;
+        }
+        Some((controller, value))
+    }
+    
+    // Apply soft takeover for mapping `index`; returns the value to write, if any
+    pub fn take_over(&mut self, index: usize, controller_value: f32, current: Option<f32>) -> Option<f32> {
+        self.mappings.get_mut(index)?.take_over(controller_value, current)
+    }
+    
+    // Record the parameter's value right after a write, so later outside changes can be spotted
+    pub fn note_written(&mut self, index: usize, value: Option<f32>) {
+        if let Some(mapping) = self.mappings.get_mut(index) {
+            mapping.last_written = value;
+        }
+    }
+    
+    // Replace all mappings, e.g. with those saved in a preset
+    pub fn set_mappings(&mut self, mappings: &[MidiMapping]) {
+        self.mappings.clear();
+        for mapping in mappings {
+            self.add_mapping(MidiMapping::new(
+                mapping.controller,
+                mapping.target.clone(),
+                mapping.min,
+                mapping.max,
+                mapping.curve,
+                mapping.soft_takeover,
+            ));
+        }
+    }
+}
+
+impl Default for MidiLearn {
+    fn default() -> Self {
+        MidiLearn::new()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,98 @@
+// Export the MIDI modules
+pub mod parser;
+pub mod smf;
+pub mod sequencer;
+pub mod recorder;
+pub mod learn;
+pub mod mpe;
+pub mod clock;
+
+pub use learn::MidiLearn;
+pub use parser::MidiParser;
+pub use recorder::Recorder;
+pub use sequencer::Sequencer;
+pub use smf::MidiFile;
+
+// Status bytes (upper nibble for channel messages)
+pub const NOTE_OFF: u8 = 0x80;
+pub const NOTE_ON: u8 = 0x90;
+pub const POLY_AFTERTOUCH: u8 = 0xA0;
+pub const CONTROL_CHANGE: u8 = 0xB0;
+pub const PROGRAM_CHANGE: u8 = 0xC0;
+pub const CHANNEL_AFTERTOUCH: u8 = 0xD0;
+pub const PITCH_BEND: u8 = 0xE0;
+pub const SYSEX_START: u8 = 0xF0;
+pub const SYSEX_END: u8 = 0xF7;
+pub const SONG_POSITION: u8 = 0xF2;
+
+// System real-time bytes
+pub const TIMING_CLOCK: u8 = 0xF8;
+pub const START: u8 = 0xFA;
+pub const CONTINUE: u8 = 0xFB;
+pub const STOP: u8 = 0xFC;
+
+// MIDI clock ticks per quarter note, and per song position pointer unit (a sixteenth)
+pub const CLOCKS_PER_QUARTER: u32 = 24;
+pub const CLOCKS_PER_SONG_POSITION: u32 = 6;
+
+// Sustain (damper) pedal
+pub const CC_SUSTAIN: u8 = 64;
+
+// Channel mode controllers
+pub const CC_ALL_SOUND_OFF: u8 = 120;
+pub const CC_ALL_NOTES_OFF: u8 = 123;
+
+// Centre value of the 14-bit pitch bend range
+pub const PITCH_BEND_CENTER: u16 = 8192;
+
+// A decoded MIDI 1.0 message. Channels are 0-15, data values 0-127.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    PolyAftertouch { channel: u8, note: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelAftertouch { channel: u8, pressure: u8 },
+    PitchBend { channel: u8, value: u16 },  // 0 to 16383, centre 8192
+    SysEx(Vec<u8>),                         // Complete message including F0 and F7
+    SongPosition(u16),                      // Sixteenth notes since the start of the song
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl MidiMessage {
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyAftertouch { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelAftertouch { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+    
+    // Encode back to raw bytes (always with an explicit status byte)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            MidiMessage::NoteOff { channel, note, velocity } => vec![NOTE_OFF | channel, note, velocity],
+            MidiMessage::NoteOn { channel, note, velocity } => vec![NOTE_ON | channel, note, velocity],
+            MidiMessage::PolyAftertouch { channel, note, pressure } => vec![POLY_AFTERTOUCH | channel, note, pressure],
+            MidiMessage::ControlChange { channel, controller, value } => vec![CONTROL_CHANGE | channel, controller, value],
+            MidiMessage::ProgramChange { channel, program } => vec![PROGRAM_CHANGE | channel, program],
+            MidiMessage::ChannelAftertouch { channel, pressure } => vec![CHANNEL_AFTERTOUCH | channel, pressure],
+            MidiMessage::PitchBend { channel, value } => vec![PITCH_BEND | channel, (value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8],
+            MidiMessage::SysEx(ref data) => data.clone(),
+            MidiMessage::SongPosition(position) => vec![SONG_POSITION, (position & 0x7F) as u8, ((position >> 7) & 0x7F) as u8],
+            MidiMessage::Clock => vec![TIMING_CLOCK],
+            MidiMessage::Start => vec![START],
+            MidiMessage::Continue => vec![CONTINUE],
+            MidiMessage::Stop => vec![STOP],
+        }
+    }
+}
\ No newline at end of file
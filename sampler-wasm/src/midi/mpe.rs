@@ -0,0 +1,106 @@
+// MIDI Polyphonic Expression: each sounding note gets its own member channel so
+// pitch bend, pressure and timbre (CC 74) apply per note
+
+pub const DEFAULT_MEMBER_BEND_RANGE: f32 = 48.0;
+pub const DEFAULT_MANAGER_BEND_RANGE: f32 = 2.0;
+
+// Controller carrying per-note timbre (the "Y" axis)
+pub const CC_TIMBRE: u8 = 74;
+
+const LOWER_MANAGER: u8 = 0;
+const UPPER_MANAGER: u8 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MpeZone {
+    pub manager: u8,   // Channel 0 (lower zone) or 15 (upper zone)
+    pub members: u8,   // Number of member channels, counted away from the manager
+    pub member_bend_range: f32,   // Semitones
+    pub manager_bend_range: f32,  // Semitones, applied to every note in the zone
+}
+
+impl MpeZone {
+    fn new(manager: u8, members: u8) -> Self {
+        MpeZone {
+            manager,
+            members,
+            member_bend_range: DEFAULT_MEMBER_BEND_RANGE,
+            manager_bend_range: DEFAULT_MANAGER_BEND_RANGE,
+        }
+    }
+    
+    pub fn has_member(&self, channel: u8) -> bool {
+        if self.manager == LOWER_MANAGER {
+            channel >= 1 && channel <= self.members
+        } else {
+            channel < UPPER_MANAGER && channel >= UPPER_MANAGER - self.members
+        }
+    }
+}
+
+// The lower and upper zones. With neither configured every channel behaves as plain MIDI.
+#[derive(Debug, Clone, Default)]
+pub struct MpeConfig {
+    lower: Option<MpeZone>,
+    upper: Option<MpeZone>,
+}
+
+impl MpeConfig {
+    pub fn new() -> Self {
+        MpeConfig::default()
+    }
+    
+    // Configure the lower zone (manager channel 1) with `members` member channels; 0 disables it.
+    // As in the MPE spec, a new zone shrinks the other zone if they would overlap.
+    pub fn set_lower_zone(&mut self, members: u8) {
+        let members = members.min(15);
+        self.lower = (members > 0).then(|| MpeZone::new(LOWER_MANAGER, members));
+        if let Some(upper) = &mut self.upper {
+            upper.members = upper.members.min(14u8.saturating_sub(members));
+            if upper.members == 0 {
+                self.upper = None;
+            }
+        }
+    }
+    
+    // Configure the upper zone (manager channel 16) with `members` member channels; 0 disables it
+    pub fn set_upper_zone(&mut self, members: u8) {
+        let members = members.min(15);
+        self.upper = (members > 0).then(|| MpeZone::new(UPPER_MANAGER, members));
+        if let Some(lower) = &mut self.lower {
+            lower.members = lower.members.min(14u8.saturating_sub(members));
+            if lower.members == 0 {
+                self.lower = None;
+            }
+        }
+    }
+    
+    pub fn set_bend_ranges(&mut self, upper_zone: bool, member_range: f32, manager_range: f32) {
+        let zone = if upper_zone { &mut self.upper } else { &mut self.lower };
+        if let Some(zone) = zone {
+            zone.member_bend_range = member_range.clamp(0.0, 96.0);
+            zone.manager_bend_range = manager_range.clamp(0.0, 96.0);
+        }
+    }
+    
+    pub fn zone(&self, upper: bool) -> Option<&MpeZone> {
+        if upper { self.upper.as_ref() } else { self.lower.as_ref() }
+    }
+    
+    pub fn is_enabled(&self) -> bool {
+        self.lower.is_some() || self.upper.is_some()
+    }
+    
+    // The zone a channel is a member channel of, if any
+    pub fn member_zone(&self, channel: u8) -> Option<&MpeZone> {
+        [&self.lower, &self.upper].into_iter()
+            .flatten()
+            .find(|zone| zone.has_member(channel))
+    }
+    
+    // The zone a channel manages, if any
+    pub fn managed_zone(&self, channel: u8) -> Option<&MpeZone> {
+        [&self.lower, &self.upper].into_iter()
+            .flatten()
+            .find(|zone| zone.manager == channel)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,164 @@
+use super::{
+    MidiMessage, CHANNEL_AFTERTOUCH, CONTINUE, CONTROL_CHANGE, NOTE_OFF, NOTE_ON, POLY_AFTERTOUCH, PROGRAM_CHANGE,
+    SONG_POSITION, START, STOP, SYSEX_END, SYSEX_START, TIMING_CLOCK,
+};
+
+// SysEx messages longer than this are dropped rather than buffered
+const MAX_SYSEX_LENGTH: usize = 65536;
+
+// Streaming parser for raw MIDI 1.0 bytes. State carries across calls, so a
+// message (or SysEx dump) may be split over several chunks.
+pub struct MidiParser {
+    running_status: Option<u8>,
+    song_position: bool,  // Collecting the two data bytes of a song position pointer
+    data: [u8; 2],
+    data_length: usize,
+    sysex: Vec<u8>,
+    in_sysex: bool,
+    sysex_overflow: bool,
+}
+
+// Number of data bytes following a channel status byte
+pub(crate) fn data_bytes(status: u8) -> usize {
+    match status & 0xF0 {
+        PROGRAM_CHANGE | CHANNEL_AFTERTOUCH => 1,
+        _ => 2,
+    }
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        MidiParser {
+            running_status: None,
+            song_position: false,
+            data: [0; 2],
+            data_length: 0,
+            sysex: Vec::new(),
+            in_sysex: false,
+            sysex_overflow: false,
+        }
+    }
+    
+    pub fn reset(&mut self) {
+        self.running_status = None;
+        self.song_position = false;
+        self.data_length = 0;
+        self.sysex.clear();
+        self.in_sysex = false;
+        self.sysex_overflow = false;
+    }
+    
+    // Parse a chunk of bytes, calling `handler` for every complete message
+    pub fn parse<F: FnMut(MidiMessage)>(&mut self, bytes: &[u8], mut handler: F) {
+        for &byte in bytes {
+            if let Some(message) = self.push(byte) {
+                handler(message);
+            }
+        }
+    }
+    
+    // Feed one byte, returning a message once one is complete
+    pub fn push(&mut self, byte: u8) -> Option<MidiMessage> {
+        // Real-time messages may appear anywhere, even inside SysEx, and leave all state alone
+        if byte >= TIMING_CLOCK {
+            return match byte {
+                TIMING_CLOCK => Some(MidiMessage::Clock),
+                START => Some(MidiMessage::Start),
+                CONTINUE => Some(MidiMessage::Continue),
+                STOP => Some(MidiMessage::Stop),
+                _ => None,  // Active sensing, reset
+            };
+        }
+        
+        if byte & 0x80 != 0 {
+            return self.push_status(byte);
+        }
+        
+        if self.in_sysex {
+            if self.sysex.len() < MAX_SYSEX_LENGTH {
+                self.sysex.push(byte);
+            } else {
+                self.sysex_overflow = true;
+            }
+            return None;
+        }
+        
+        if self.song_position {
+            self.data[self.data_length] = byte;
+            self.data_length += 1;
+            if self.data_length < 2 {
+                return None;
+            }
+            self.data_length = 0;
+            self.song_position = false;
+            return Some(MidiMessage::SongPosition(self.data[0] as u16 | (self.data[1] as u16) << 7));
+        }
+        
+        // Data byte: needs a status, either fresh or running
+        let status = self.running_status?;
+        self.data[self.data_length] = byte;
+        self.data_length += 1;
+        if self.data_length < data_bytes(status) {
+            return None;
+        }
+        
+        self.data_length = 0;
+        Some(Self::channel_message(status, self.data))
+    }
+    
+    fn push_status(&mut self, status: u8) -> Option<MidiMessage> {
+        // Any status byte ends a SysEx; only F7 completes it properly
+        let sysex = if self.in_sysex {
+            self.in_sysex = false;
+            let complete = status == SYSEX_END && !self.sysex_overflow;
+            let mut data = std::mem::take(&mut self.sysex);
+            if complete {
+                data.push(SYSEX_END);
+                Some(MidiMessage::SysEx(data))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        
+        self.data_length = 0;
+        self.song_position = status == SONG_POSITION;
+        
+        match status {
+            SYSEX_START => {
+                self.running_status = None;
+                self.in_sysex = true;
+                self.sysex_overflow = false;
+                self.sysex.clear();
+                self.sysex.push(SYSEX_START);
+            },
+            // Other system common messages cancel running status; their data is ignored
+            0xF1..=0xF7 => self.running_status = None,
+            _ => self.running_status = Some(status),
+        }
+        
+        sysex
+    }
+    
+    pub(crate) fn channel_message(status: u8, data: [u8; 2]) -> MidiMessage {
+        let channel = status & 0x0F;
+        match status & 0xF0 {
+            NOTE_OFF => MidiMessage::NoteOff { channel, note: data[0], velocity: data[1] },
+            // Note-on with velocity 0 is a note-off
+            NOTE_ON if data[1] == 0 => MidiMessage::NoteOff { channel, note: data[0], velocity: 0 },
+            NOTE_ON => MidiMessage::NoteOn { channel, note: data[0], velocity: data[1] },
+            POLY_AFTERTOUCH => MidiMessage::PolyAftertouch { channel, note: data[0], pressure: data[1] },
+            CONTROL_CHANGE => MidiMessage::ControlChange { channel, controller: data[0], value: data[1] },
+            PROGRAM_CHANGE => MidiMessage::ProgramChange { channel, program: data[0] },
+            CHANNEL_AFTERTOUCH => MidiMessage::ChannelAftertouch { channel, pressure: data[0] },
+            _ => MidiMessage::PitchBend { channel, value: data[0] as u16 | (data[1] as u16) << 7 },
+        }
+    }
+}
+
+impl Default for MidiParser {
+    fn default() -> Self {
+        MidiParser::new()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,169 @@
+use super::smf::{write_variable_length, MAX_VARIABLE_LENGTH};
+use super::MidiMessage;
+
+// Ticks per quarter note in exported files
+pub const RECORD_PPQ: u16 = 480;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedEvent {
+    pub time: f64,  // Transport position in seconds
+    pub message: MidiMessage,
+}
+
+// Captures live note, controller and pitch bend events against the transport
+pub struct Recorder {
+    events: Vec<RecordedEvent>,
+    recording: bool,
+    tempo_bpm: f32,
+    quantize_beats: f32,  // Grid for note starts, 0 for none
+    
+    // Notes held in the current pass and how far their start was moved by quantizing
+    held: Vec<(u8, u8, f64)>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            events: Vec::new(),
+            recording: false,
+            tempo_bpm: 120.0,
+            quantize_beats: 0.0,
+            held: Vec::with_capacity(128),
+        }
+    }
+    
+    // Begin a pass. Without overdub the previous take is discarded.
+    pub fn start(&mut self, overdub: bool, tempo_bpm: f32) {
+        if !overdub {
+            self.events.clear();
+        }
+        self.held.clear();
+        self.tempo_bpm = tempo_bpm.clamp(1.0, 999.0);
+        self.recording = true;
+    }
+    
+    // End the pass, closing any notes still held at `position`
+    pub fn stop(&mut self, position: f64) {
+        if !self.recording {
+            return;
+        }
+        
+        for (channel, note, shift) in self.held.drain(..) {
+            self.events.push(RecordedEvent {
+                time: (position + shift).max(0.0),
+                message: MidiMessage::NoteOff { channel, note, velocity: 0 },
+            });
+        }
+        self.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self.recording = false;
+    }
+    
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.held.clear();
+    }
+    
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+    
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+    
+    // Snap note starts to a grid in beats (e.g. 0.25 for sixteenths); 0 turns quantizing off
+    pub fn set_quantize(&mut self, grid_beats: f32) {
+        self.quantize_beats = grid_beats.max(0.0);
+    }
+    
+    fn quantize(&self, time: f64) -> f64 {
+        if self.quantize_beats <= 0.0 {
+            return time;
+        }
+        let grid = self.quantize_beats as f64 * 60.0 / self.tempo_bpm as f64;
+        (time / grid).round() * grid
+    }
+    
+    // Record a message received at transport position `time`
+    pub fn record(&mut self, time: f64, message: &MidiMessage) {
+        if !self.recording {
+            return;
+        }
+        
+        // Note-offs move with their note-on so quantizing keeps the played length
+        let time = match *message {
+            MidiMessage::NoteOn { channel, note, .. } => {
+                let quantized = self.quantize(time);
+                self.held.retain(|&(c, n, _)| (c, n) != (channel, note));
+                self.held.push((channel, note, quantized - time));
+                quantized
+            },
+            MidiMessage::NoteOff { channel, note, .. } => {
+                match self.held.iter().position(|&(c, n, _)| (c, n) == (channel, note)) {
+                    Some(index) => time + self.held.remove(index).2,
+                    None => return,  // Note started before recording
+                }
+            },
+            MidiMessage::ControlChange { .. } | MidiMessage::PitchBend { .. } => time,
+            _ => return,
+        };
+        
+        self.events.push(RecordedEvent { time: time.max(0.0), message: message.clone() });
+    }
+    
+    // Serialize the take as a format 1 Standard MIDI File: a tempo track followed by the performance
+    pub fn to_midi_file(&self) -> Result<Vec<u8>, String> {
+        let microseconds_per_quarter = (60_000_000.0 / self.tempo_bpm as f64).round() as u32;
+        
+        let mut tempo_track = Vec::new();
+        write_variable_length(&mut tempo_track, 0)?;
+        tempo_track.extend_from_slice(&[0xFF, 0x51, 0x03]);
+        tempo_track.extend_from_slice(&microseconds_per_quarter.to_be_bytes()[1..]);
+        write_variable_length(&mut tempo_track, 0)?;
+        tempo_track.extend_from_slice(&[0xFF, 0x58, 0x04, 4, 2, 24, 8]);  // 4/4
+        write_variable_length(&mut tempo_track, 0)?;
+        tempo_track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+        
+        let mut events: Vec<&RecordedEvent> = self.events.iter().collect();
+        events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        
+        let ticks_per_second = RECORD_PPQ as f64 * self.tempo_bpm as f64 / 60.0;
+        let mut performance_track = Vec::new();
+        let mut last_tick: u64 = 0;
+        for event in events {
+            let tick = ((event.time * ticks_per_second).round() as u64).max(last_tick);
+            
+            // A gap longer than one delta can hold is bridged with empty text events
+            let mut delta = tick - last_tick;
+            while delta > MAX_VARIABLE_LENGTH as u64 {
+                write_variable_length(&mut performance_track, MAX_VARIABLE_LENGTH)?;
+                performance_track.extend_from_slice(&[0xFF, 0x01, 0x00]);
+                delta -= MAX_VARIABLE_LENGTH as u64;
+            }
+            write_variable_length(&mut performance_track, delta as u32)?;
+            performance_track.extend_from_slice(&event.message.to_bytes());
+            last_tick = tick;
+        }
+        write_variable_length(&mut performance_track, 0)?;
+        performance_track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+        
+        let mut file = Vec::new();
+        file.extend_from_slice(b"MThd");
+        file.extend_from_slice(&6u32.to_be_bytes());
+        file.extend_from_slice(&1u16.to_be_bytes());  // Format 1
+        file.extend_from_slice(&2u16.to_be_bytes());  // Two tracks
+        file.extend_from_slice(&RECORD_PPQ.to_be_bytes());
+        for track in [tempo_track, performance_track] {
+            file.extend_from_slice(b"MTrk");
+            file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            file.extend_from_slice(&track);
+        }
+        Ok(file)
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
\ No newline at end of file
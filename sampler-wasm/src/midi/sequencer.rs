@@ -0,0 +1,106 @@
+use super::smf::MidiFile;
+use super::MidiMessage;
+
+// Plays a MIDI file against the processor's transport position (in seconds).
+// The caller asks for every event due before the end of the current frame.
+pub struct Sequencer {
+    events: Vec<(f64, MidiMessage)>,
+    next_index: usize,
+    duration: f64,
+    enabled: bool,
+    
+    // Notes started by the sequence and not yet ended, as (channel, note)
+    sounding: Vec<(u8, u8)>,
+    pending_offs: Vec<MidiMessage>,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Sequencer {
+            events: Vec::new(),
+            next_index: 0,
+            duration: 0.0,
+            enabled: true,
+            sounding: Vec::with_capacity(128),
+            pending_offs: Vec::new(),
+        }
+    }
+    
+    // Replace the sequence, positioned at `position` seconds
+    pub fn load(&mut self, file: &MidiFile, position: f64) {
+        self.stop_notes();
+        self.events = file.timed_messages();
+        self.duration = self.events.last().map_or(0.0, |&(time, _)| time);
+        self.seek(position);
+    }
+    
+    pub fn clear(&mut self) {
+        self.stop_notes();
+        self.events.clear();
+        self.next_index = 0;
+        self.duration = 0.0;
+    }
+    
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.stop_notes();
+        }
+        self.enabled = enabled;
+    }
+    
+    pub fn is_loaded(&self) -> bool {
+        !self.events.is_empty()
+    }
+    
+    // Time of the last event in seconds
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+    
+    // Jump to a transport position; notes left hanging are ended
+    pub fn seek(&mut self, position: f64) {
+        self.stop_notes();
+        self.next_index = self.events.partition_point(|&(time, _)| time < position);
+    }
+    
+    // Queue note-offs for everything the sequence is holding
+    pub fn stop_notes(&mut self) {
+        for (channel, note) in self.sounding.drain(..) {
+            self.pending_offs.push(MidiMessage::NoteOff { channel, note, velocity: 0 });
+        }
+    }
+    
+    // Next message due before `until` seconds, advancing past it. Note-offs from
+    // seeking or stopping come first.
+    pub fn next_due(&mut self, until: f64) -> Option<MidiMessage> {
+        if let Some(message) = self.pending_offs.pop() {
+            return Some(message);
+        }
+        
+        if !self.enabled {
+            return None;
+        }
+        
+        let (time, message) = self.events.get(self.next_index)?;
+        if *time >= until {
+            return None;
+        }
+        self.next_index += 1;
+        
+        match *message {
+            MidiMessage::NoteOn { channel, note, .. } if !self.sounding.contains(&(channel, note)) => {
+                self.sounding.push((channel, note));
+            },
+            MidiMessage::NoteOff { channel, note, .. } => self.sounding.retain(|&held| held != (channel, note)),
+            _ => {},
+        }
+        
+        Some(message.clone())
+    }
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Sequencer::new()
+    }
+}
\ No newline at end of file
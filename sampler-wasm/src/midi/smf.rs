@@ -0,0 +1,417 @@
+use super::parser::{data_bytes, MidiParser};
+use super::{MidiMessage, SYSEX_END, SYSEX_START};
+
+// Tempo assumed until the first tempo event (120 BPM)
+pub const DEFAULT_MICROSECONDS_PER_QUARTER: u32 = 500_000;
+
+const META_EVENT: u8 = 0xFF;
+const META_END_OF_TRACK: u8 = 0x2F;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+
+// How delta-times in the file map to real time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    // Ticks per quarter note; real time follows the tempo map
+    Ppq(u16),
+    // Frames per second (24, 25, 29 for 29.97 drop-frame, 30) and ticks per frame
+    Smpte { fps: u8, ticks_per_frame: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmfEventKind {
+    Midi(MidiMessage),
+    Tempo(u32),  // Microseconds per quarter note
+    TimeSignature { numerator: u8, denominator: u8 },  // Denominator as a note value, e.g. 4
+    Meta(u8, Vec<u8>),  // Any other meta event, kept as raw data
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmfEvent {
+    pub tick: u64,  // Absolute position in ticks from the start of the track
+    pub kind: SmfEventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeSignature {
+    pub tick: u64,
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+// A tempo change and the time in seconds at which it takes effect
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TempoChange {
+    tick: u64,
+    seconds: f64,
+    microseconds_per_quarter: u32,
+}
+
+// A parsed Standard MIDI File (format 0 or 1)
+#[derive(Debug, Clone)]
+pub struct MidiFile {
+    pub format: u16,
+    pub timing: Timing,
+    pub tracks: Vec<Vec<SmfEvent>>,
+    tempo_map: Vec<TempoChange>,
+}
+
+// Big-endian reader over a byte slice that reports truncation as an error
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+    
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position.checked_add(length).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| "Unexpected end of MIDI file".to_string())?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+    
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+    
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+    
+    // Variable-length quantity: 7 bits per byte, high bit set on all but the last
+    fn variable_length(&mut self) -> Result<u32, String> {
+        let mut value: u32 = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Invalid variable-length quantity in MIDI file".to_string())
+    }
+}
+
+// Largest value a variable-length quantity holds in its four bytes
+pub const MAX_VARIABLE_LENGTH: u32 = 0x0FFF_FFFF;
+
+// Write a variable-length quantity (the inverse of `Reader::variable_length`)
+pub fn write_variable_length(output: &mut Vec<u8>, value: u32) -> Result<(), String> {
+    if value > MAX_VARIABLE_LENGTH {
+        return Err(format!("{} is too large for a MIDI variable-length quantity", value));
+    }
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        output.push(((value >> shift) & 0x7F) as u8 | 0x80);
+        shift -= 7;
+    }
+    output.push((value & 0x7F) as u8);
+    Ok(())
+}
+
+fn parse_track(data: &[u8]) -> Result<Vec<SmfEvent>, String> {
+    let mut reader = Reader::new(data);
+    let mut events = Vec::new();
+    let mut tick: u64 = 0;
+    let mut running_status: Option<u8> = None;
+    
+    while !reader.is_empty() {
+        tick += reader.variable_length()? as u64;
+        let first = reader.u8()?;
+        
+        match first {
+            META_EVENT => {
+                running_status = None;
+                let meta_type = reader.u8()?;
+                let length = reader.variable_length()? as usize;
+                let payload = reader.bytes(length)?;
+                
+                let kind = match meta_type {
+                    META_END_OF_TRACK => break,
+                    META_TEMPO if length >= 3 => {
+                        let tempo = (payload[0] as u32) << 16 | (payload[1] as u32) << 8 | payload[2] as u32;
+                        SmfEventKind::Tempo(tempo.max(1))
+                    },
+                    META_TIME_SIGNATURE if length >= 2 => SmfEventKind::TimeSignature {
+                        numerator: payload[0],
+                        denominator: 1u8.checked_shl(payload[1] as u32).unwrap_or(128),
+                    },
+                    _ => SmfEventKind::Meta(meta_type, payload.to_vec()),
+                };
+                events.push(SmfEvent { tick, kind });
+            },
+            SYSEX_START | SYSEX_END => {
+                running_status = None;
+                let length = reader.variable_length()? as usize;
+                let payload = reader.bytes(length)?;
+                
+                // F7 "escape" events carry arbitrary bytes; only complete F0 messages are kept
+                if first == SYSEX_START {
+                    let mut message = vec![SYSEX_START];
+                    message.extend_from_slice(payload);
+                    if message.last() != Some(&SYSEX_END) {
+                        message.push(SYSEX_END);
+                    }
+                    events.push(SmfEvent { tick, kind: SmfEventKind::Midi(MidiMessage::SysEx(message)) });
+                }
+            },
+            _ => {
+                // Channel message, possibly using running status
+                let (status, mut data) = if first & 0x80 != 0 {
+                    (first, [0u8; 2])
+                } else {
+                    let status = running_status.ok_or_else(|| "MIDI file data byte without status".to_string())?;
+                    (status, [first, 0])
+                };
+                if status >= 0xF0 {
+                    return Err(format!("Unexpected status byte {:#04x} in MIDI track", status));
+                }
+                
+                let already = if first & 0x80 != 0 { 0 } else { 1 };
+                for slot in data.iter_mut().take(data_bytes(status)).skip(already) {
+                    *slot = reader.u8()? & 0x7F;
+                }
+                
+                running_status = Some(status);
+                events.push(SmfEvent { tick, kind: SmfEventKind::Midi(MidiParser::channel_message(status, data)) });
+            },
+        }
+    }
+    
+    Ok(events)
+}
+
+impl MidiFile {
+    pub fn parse(data: &[u8]) -> Result<MidiFile, String> {
+        let mut reader = Reader::new(data);
+        
+        if reader.bytes(4)? != b"MThd" {
+            return Err("Not a Standard MIDI File (missing MThd header)".to_string());
+        }
+        let header_length = reader.u32()? as usize;
+        if header_length < 6 {
+            return Err("MIDI file header is too short".to_string());
+        }
+        let mut header = Reader::new(reader.bytes(header_length)?);
+        let format = header.u16()?;
+        let track_count = header.u16()?;
+        let division = header.u16()?;
+        
+        if format > 1 {
+            return Err(format!("Unsupported MIDI file format {}", format));
+        }
+        
+        let timing = if division & 0x8000 != 0 {
+            let fps = (-((division >> 8) as u8 as i8)) as u8;
+            let ticks_per_frame = (division & 0xFF) as u8;
+            if !matches!(fps, 24 | 25 | 29 | 30) || ticks_per_frame == 0 {
+                return Err("Invalid SMPTE timing in MIDI file".to_string());
+            }
+            Timing::Smpte { fps, ticks_per_frame }
+        } else if division == 0 {
+            return Err("MIDI file has zero ticks per quarter note".to_string());
+        } else {
+            Timing::Ppq(division)
+        };
+        
+        let mut tracks = Vec::with_capacity(track_count as usize);
+        while tracks.len() < track_count as usize && !reader.is_empty() {
+            let chunk_type = reader.bytes(4)?;
+            let length = reader.u32()? as usize;
+            let chunk = reader.bytes(length)?;
+            
+            // Unknown chunk types must be skipped
+            if chunk_type == b"MTrk" {
+                tracks.push(parse_track(chunk)?);
+            }
+        }
+        
+        let mut file = MidiFile { format, timing, tracks, tempo_map: Vec::new() };
+        file.build_tempo_map();
+        Ok(file)
+    }
+    
+    fn build_tempo_map(&mut self) {
+        // Tempo events can live in any track (normally the first in format 1)
+        let mut changes: Vec<(u64, u32)> = self.tracks.iter()
+            .flatten()
+            .filter_map(|event| match event.kind {
+                SmfEventKind::Tempo(tempo) => Some((event.tick, tempo)),
+                _ => None,
+            })
+            .collect();
+        changes.sort_by_key(|&(tick, _)| tick);
+        
+        let ppq = match self.timing {
+            Timing::Ppq(ppq) => ppq as f64,
+            Timing::Smpte { .. } => 1.0,
+        };
+        
+        let mut map = vec![TempoChange { tick: 0, seconds: 0.0, microseconds_per_quarter: DEFAULT_MICROSECONDS_PER_QUARTER }];
+        for (tick, tempo) in changes {
+            let last = map[map.len() - 1];
+            let seconds = last.seconds + (tick - last.tick) as f64 * last.microseconds_per_quarter as f64 / (ppq * 1_000_000.0);
+            if tick == last.tick {
+                map.pop();
+            }
+            map.push(TempoChange { tick, seconds, microseconds_per_quarter: tempo });
+        }
+        self.tempo_map = map;
+    }
+    
+    // Convert an absolute tick position to seconds
+    pub fn tick_to_seconds(&self, tick: u64) -> f64 {
+        match self.timing {
+            Timing::Ppq(ppq) => {
+                let index = self.tempo_map.partition_point(|change| change.tick <= tick).saturating_sub(1);
+                let change = self.tempo_map[index];
+                change.seconds + (tick - change.tick) as f64 * change.microseconds_per_quarter as f64 / (ppq as f64 * 1_000_000.0)
+            },
+            Timing::Smpte { fps, ticks_per_frame } => {
+                let frames_per_second = if fps == 29 { 29.97 } else { fps as f64 };
+                tick as f64 / (frames_per_second * ticks_per_frame as f64)
+            },
+        }
+    }
+    
+    // Tempo in effect at a tick, in beats per minute
+    pub fn tempo_at(&self, tick: u64) -> f32 {
+        let index = self.tempo_map.partition_point(|change| change.tick <= tick).saturating_sub(1);
+        60_000_000.0 / self.tempo_map[index].microseconds_per_quarter as f32
+    }
+    
+    // Every time signature change across all tracks, in tick order
+    pub fn time_signatures(&self) -> Vec<TimeSignature> {
+        let mut signatures: Vec<TimeSignature> = self.tracks.iter()
+            .flatten()
+            .filter_map(|event| match event.kind {
+                SmfEventKind::TimeSignature { numerator, denominator } => Some(TimeSignature { tick: event.tick, numerator, denominator }),
+                _ => None,
+            })
+            .collect();
+        signatures.sort_by_key(|signature| signature.tick);
+        signatures
+    }
+    
+    // Every MIDI message across all tracks with its time in seconds, sorted by time
+    pub fn timed_messages(&self) -> Vec<(f64, MidiMessage)> {
+        let mut messages: Vec<(u64, usize, &MidiMessage)> = self.tracks.iter()
+            .enumerate()
+            .flat_map(|(track, events)| events.iter().filter_map(move |event| match event.kind {
+                SmfEventKind::Midi(ref message) => Some((event.tick, track, message)),
+                _ => None,
+            }))
+            .collect();
+        
+        // Stable sort keeps each track's order within a tick
+        messages.sort_by_key(|&(tick, track, _)| (tick, track));
+        messages.into_iter()
+            .map(|(tick, _, message)| (self.tick_to_seconds(tick), message.clone()))
+            .collect()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+    
+    // Format 1 at 480 PPQ: a conductor track doubling the tempo after two beats, then
+    // notes and program changes leaning on running status
+    fn file() -> Vec<u8> {
+        let conductor = [
+            0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08,
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20,
+            0x87, 0x40, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let notes = [
+            0x00, 0x90, 0x3C, 0x64,
+            0x83, 0x60, 0x40, 0x50,
+            0x83, 0x60, 0x3C, 0x00,
+            0x83, 0x60, 0xC0, 0x05,
+            0x00, 0x06,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        
+        let mut out = chunk(b"MThd", &[0x00, 0x01, 0x00, 0x02, 0x01, 0xE0]);
+        out.extend(chunk(b"MTrk", &conductor));
+        out.extend(chunk(b"MTrk", &notes));
+        out
+    }
+    
+    #[test]
+    fn running_status() {
+        let file = MidiFile::parse(&file()).unwrap();
+        let events: Vec<(u64, SmfEventKind)> = file.tracks[1].iter().map(|event| (event.tick, event.kind.clone())).collect();
+        assert_eq!(events, vec![
+            (0, SmfEventKind::Midi(MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 })),
+            (480, SmfEventKind::Midi(MidiMessage::NoteOn { channel: 0, note: 64, velocity: 80 })),
+            (960, SmfEventKind::Midi(MidiMessage::NoteOff { channel: 0, note: 60, velocity: 0 })),
+            (1440, SmfEventKind::Midi(MidiMessage::ProgramChange { channel: 0, program: 5 })),
+            (1440, SmfEventKind::Midi(MidiMessage::ProgramChange { channel: 0, program: 6 })),
+        ]);
+    }
+    
+    #[test]
+    fn tempo_map() {
+        let file = MidiFile::parse(&file()).unwrap();
+        assert_eq!(file.timing, Timing::Ppq(480));
+        assert_eq!(file.time_signatures(), vec![TimeSignature { tick: 0, numerator: 3, denominator: 4 }]);
+        
+        assert_eq!(file.tempo_at(959), 120.0);
+        assert_eq!(file.tempo_at(960), 240.0);
+        assert_eq!(file.tick_to_seconds(480), 0.5);
+        assert_eq!(file.tick_to_seconds(960), 1.0);
+        assert_eq!(file.tick_to_seconds(1440), 1.25);
+        
+        let times: Vec<f64> = file.timed_messages().iter().map(|&(seconds, _)| seconds).collect();
+        assert_eq!(times, vec![0.0, 0.5, 1.0, 1.25, 1.25]);
+    }
+    
+    #[test]
+    fn variable_length_round_trip() {
+        for value in [0, 0x7F, 0x80, 0x3FFF, 0x4000, 0x1F_FFFF, 0x20_0000, MAX_VARIABLE_LENGTH] {
+            let mut bytes = Vec::new();
+            write_variable_length(&mut bytes, value).unwrap();
+            assert_eq!(Reader::new(&bytes).variable_length(), Ok(value));
+        }
+        assert!(write_variable_length(&mut Vec::new(), MAX_VARIABLE_LENGTH + 1).is_err());
+    }
+    
+    #[test]
+    fn truncated_input_is_rejected() {
+        // Only cuts between chunks leave a whole file, holding the tracks before the cut
+        let file = file();
+        let track_ends = [14, 14 + 8 + 27];
+        for len in 0..file.len() {
+            match MidiFile::parse(&file[..len]) {
+                Ok(parsed) => assert_eq!(track_ends.iter().position(|&end| end == len), Some(parsed.tracks.len())),
+                Err(_) => assert!(!track_ends.contains(&len)),
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,450 @@
+use serde::{Deserialize, Serialize};
+
+use crate::automation::ParameterTarget;
+use crate::effects::EffectsChain;
+use crate::lfo::NUM_LFOS;
+
+pub const NUM_MOD_SLOTS: usize = 16;
+pub const NUM_VOICE_ENVELOPES: usize = 3;
+
+// Where a modulation value comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModSource {
+    Lfo(usize),
+    Envelope(usize),  // 0: amplitude envelope, 1: modulation envelope, 2: MSEG
+    Velocity,
+    KeyNumber,
+    Cc(u8),
+    Aftertouch,      // Channel aftertouch
+    PolyAftertouch,  // Per-note pressure
+    Timbre,          // Per-note MPE timbre (CC 74)
+    PitchBend,
+    RandomPerNote,
+    Follower,   // Envelope follower level
+    Transient,  // Transient detector pulse
+}
+
+impl ModSource {
+    // Parse a source identifier such as "lfo:0", "env:1", "velocity" or "cc:74"
+    pub fn parse(id: &str) -> Option<ModSource> {
+        let (kind, index) = match id.split_once(':') {
+            Some((kind, index)) => (kind, Some(index.parse::<usize>().ok()?)),
+            None => (id, None),
+        };
+        
+        match (kind, index) {
+            ("lfo", Some(index)) if index < NUM_LFOS => Some(ModSource::Lfo(index)),
+            ("env", Some(index)) if index < NUM_VOICE_ENVELOPES => Some(ModSource::Envelope(index)),
+            ("velocity", None) => Some(ModSource::Velocity),
+            ("key", None) => Some(ModSource::KeyNumber),
+            ("cc", Some(index)) if index < 128 => Some(ModSource::Cc(index as u8)),
+            ("aftertouch", None) => Some(ModSource::Aftertouch),
+            ("poly_aftertouch", None) => Some(ModSource::PolyAftertouch),
+            ("timbre", None) => Some(ModSource::Timbre),
+            ("pitch_bend", None) => Some(ModSource::PitchBend),
+            ("random", None) => Some(ModSource::RandomPerNote),
+            ("follower", None) => Some(ModSource::Follower),
+            ("transient", None) => Some(ModSource::Transient),
+            _ => None,
+        }
+    }
+    
+    pub fn id(&self) -> String {
+        match self {
+            ModSource::Lfo(index) => format!("lfo:{}", index),
+            ModSource::Envelope(index) => format!("env:{}", index),
+            ModSource::Velocity => "velocity".to_string(),
+            ModSource::KeyNumber => "key".to_string(),
+            ModSource::Cc(cc) => format!("cc:{}", cc),
+            ModSource::Aftertouch => "aftertouch".to_string(),
+            ModSource::PolyAftertouch => "poly_aftertouch".to_string(),
+            ModSource::Timbre => "timbre".to_string(),
+            ModSource::PitchBend => "pitch_bend".to_string(),
+            ModSource::RandomPerNote => "random".to_string(),
+            ModSource::Follower => "follower".to_string(),
+            ModSource::Transient => "transient".to_string(),
+        }
+    }
+    
+    // Whether the value differs between voices
+    pub fn is_per_voice(&self) -> bool {
+        matches!(self, ModSource::Envelope(_) | ModSource::Velocity | ModSource::KeyNumber
+            | ModSource::RandomPerNote | ModSource::PolyAftertouch | ModSource::Timbre)
+    }
+}
+
+// What a modulation value is applied to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModDestination {
+    Effect { effect_id: usize, param: String },  // Any name accepted by `Effect::set_parameter`
+    Pitch,        // Semitones
+    Pan,          // -1 (left) to 1 (right)
+    SampleStart,  // Fraction of the sample length, applied at note-on
+}
+
+impl ModDestination {
+    // Parse a destination identifier such as "effect:0:cutoff", "pitch", "pan" or "sample_start"
+    pub fn parse(id: &str) -> Option<ModDestination> {
+        match id {
+            "pitch" => Some(ModDestination::Pitch),
+            "pan" => Some(ModDestination::Pan),
+            "sample_start" => Some(ModDestination::SampleStart),
+            _ => {
+                let mut parts = id.splitn(3, ':');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some("effect"), Some(effect_id), Some(param)) if !param.is_empty() => {
+                        Some(ModDestination::Effect {
+                            effect_id: effect_id.parse().ok()?,
+                            param: param.to_string(),
+                        })
+                    },
+                    _ => None,
+                }
+            }
+        }
+    }
+    
+    pub fn id(&self) -> String {
+        match self {
+            ModDestination::Effect { effect_id, param } => format!("effect:{}:{}", effect_id, param),
+            ModDestination::Pitch => "pitch".to_string(),
+            ModDestination::Pan => "pan".to_string(),
+            ModDestination::SampleStart => "sample_start".to_string(),
+        }
+    }
+}
+
+// Response curve applied to the source value before scaling by the amount
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ModCurve {
+    Linear,
+    Exponential,
+    Logarithmic,
+    SCurve,
+}
+
+impl ModCurve {
+    // 0: Linear, 1: Exponential, 2: Logarithmic, 3: S-Curve
+    pub fn from_index(index: u32) -> ModCurve {
+        match index {
+            1 => ModCurve::Exponential,
+            2 => ModCurve::Logarithmic,
+            3 => ModCurve::SCurve,
+            _ => ModCurve::Linear,
+        }
+    }
+    
+    pub fn index(&self) -> u32 {
+        match self {
+            ModCurve::Linear => 0,
+            ModCurve::Exponential => 1,
+            ModCurve::Logarithmic => 2,
+            ModCurve::SCurve => 3,
+        }
+    }
+    
+    // Shape the magnitude of a unipolar or bipolar value, keeping its sign
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = value.abs().min(1.0);
+        let shaped = match self {
+            ModCurve::Linear => magnitude,
+            ModCurve::Exponential => magnitude * magnitude,
+            ModCurve::Logarithmic => magnitude.sqrt(),
+            ModCurve::SCurve => magnitude * magnitude * (3.0 - 2.0 * magnitude),
+        };
+        shaped.copysign(value)
+    }
+}
+
+// Source values shared by every voice
+pub struct GlobalModSources {
+    pub lfos: [f32; NUM_LFOS],
+    pub cc: [f32; 128],      // 0 to 1
+    pub aftertouch: f32,     // 0 to 1
+    pub pitch_bend: f32,     // -1 to 1
+    pub follower: f32,       // 0 to 1
+    pub transient: f32,      // 0 or 1
+}
+
+impl GlobalModSources {
+    pub fn new() -> Self {
+        GlobalModSources {
+            lfos: [0.0; NUM_LFOS],
+            cc: [0.0; 128],
+            aftertouch: 0.0,
+            pitch_bend: 0.0,
+            follower: 0.0,
+            transient: 0.0,
+        }
+    }
+}
+
+impl Default for GlobalModSources {
+    fn default() -> Self {
+        GlobalModSources::new()
+    }
+}
+
+// Source values that belong to a single voice
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VoiceModSources {
+    pub envelopes: [f32; NUM_VOICE_ENVELOPES],
+    pub velocity: f32,  // 0 to 1
+    pub key: f32,       // MIDI note / 127
+    pub random: f32,    // -1 to 1, drawn at note-on
+    pub pressure: f32,  // Polyphonic aftertouch, 0 to 1
+    pub timbre: f32,    // MPE timbre, 0 to 1
+}
+
+// Resolved per-voice destination values
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VoiceModulation {
+    pub pitch: f32,
+    pub pan: f32,
+    pub sample_start: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SlotTarget {
+    Effect(usize),  // Index into `ModMatrix::effect_targets`
+    Pitch,
+    Pan,
+    SampleStart,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ModSlot {
+    source: ModSource,
+    target: SlotTarget,
+    amount: f32,
+    curve: ModCurve,
+}
+
+// A modulated effect parameter: the unmodulated value plus the summed modulation
+struct EffectTarget {
+    effect_id: usize,
+    param: String,
+    base: f32,
+    offset: f32,
+}
+
+pub struct ModMatrix {
+    slots: [Option<ModSlot>; NUM_MOD_SLOTS],
+    effect_targets: Vec<EffectTarget>,
+}
+
+impl ModMatrix {
+    pub fn new() -> Self {
+        ModMatrix {
+            slots: [None; NUM_MOD_SLOTS],
+            effect_targets: Vec::new(),
+        }
+    }
+    
+    // Route a source to a destination. Effect destinations capture their current value
+    // from the chain as the base that modulation is added to.
+    pub fn set_slot(&mut self, index: usize, source: ModSource, destination: &ModDestination,
+                    amount: f32, curve: ModCurve, chains: &mut [EffectsChain]) -> bool {
+        if index >= NUM_MOD_SLOTS {
+            return false;
+        }
+        
+        let target = match destination {
+            ModDestination::Effect { effect_id, param } => {
+                let base = match chains.first().and_then(|chain| chain.get_effect_parameter(*effect_id, param)) {
+                    Some(base) => base,
+                    None => return false,
+                };
+                SlotTarget::Effect(self.effect_target_index(*effect_id, param, base))
+            },
+            ModDestination::Pitch => SlotTarget::Pitch,
+            ModDestination::Pan => SlotTarget::Pan,
+            ModDestination::SampleStart => SlotTarget::SampleStart,
+        };
+        
+        self.slots[index] = Some(ModSlot { source, target, amount, curve });
+        self.remove_unused_targets(chains);
+        true
+    }
+    
+    pub fn clear_slot(&mut self, index: usize, chains: &mut [EffectsChain]) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            *slot = None;
+        }
+        self.remove_unused_targets(chains);
+    }
+    
+    // Empty every slot, restoring each modulated effect parameter to its base
+    pub fn clear(&mut self, chains: &mut [EffectsChain]) {
+        self.slots = [None; NUM_MOD_SLOTS];
+        self.remove_unused_targets(chains);
+    }
+    
+    // Drop effect destinations no slot routes to any more, leaving each parameter at
+    // its base so later changes to it aren't overridden
+    fn remove_unused_targets(&mut self, chains: &mut [EffectsChain]) {
+        let mut index = 0;
+        while index < self.effect_targets.len() {
+            if self.slots.iter().flatten().any(|slot| slot.target == SlotTarget::Effect(index)) {
+                index += 1;
+                continue;
+            }
+            
+            let target = self.effect_targets.remove(index);
+            for chain in chains.iter_mut() {
+                chain.set_effect_parameter(target.effect_id, &target.param, target.base);
+            }
+            for slot in self.slots.iter_mut().flatten() {
+                if let SlotTarget::Effect(other) = &mut slot.target {
+                    if *other > index {
+                        *other -= 1;
+                    }
+                }
+            }
+        }
+    }
+    
+    pub fn set_amount(&mut self, index: usize, amount: f32) {
+        if let Some(Some(slot)) = self.slots.get_mut(index) {
+            slot.amount = amount;
+        }
+    }
+    
+    // Source, destination, amount and curve of an occupied slot
+    pub fn slot(&self, index: usize) -> Option<(ModSource, ModDestination, f32, ModCurve)> {
+        let slot = self.slots.get(index)?.as_ref()?;
+        let destination = match slot.target {
+            SlotTarget::Effect(target) => ModDestination::Effect {
+                effect_id: self.effect_targets[target].effect_id,
+                param: self.effect_targets[target].param.clone(),
+            },
+            SlotTarget::Pitch => ModDestination::Pitch,
+            SlotTarget::Pan => ModDestination::Pan,
+            SlotTarget::SampleStart => ModDestination::SampleStart,
+        };
+        Some((slot.source, destination, slot.amount, slot.curve))
+    }
+    
+    fn effect_target_index(&mut self, effect_id: usize, param: &str, base: f32) -> usize {
+        match self.effect_targets.iter().position(|t| t.effect_id == effect_id && t.param == param) {
+            Some(index) => index,
+            None => {
+                self.effect_targets.push(EffectTarget {
+                    effect_id,
+                    param: param.to_string(),
+                    base,
+                    offset: 0.0,
+                });
+                self.effect_targets.len() - 1
+            }
+        }
+    }
+    
+    // Update the unmodulated value of an effect parameter. Returns false if the
+    // parameter is not a modulation destination, in which case the caller sets it directly.
+    pub fn set_base(&mut self, effect_id: usize, param: &str, value: f32, chains: &mut [EffectsChain]) -> bool {
+        let target = match self.effect_targets.iter_mut().find(|t| t.effect_id == effect_id && t.param == param) {
+            Some(target) => target,
+            None => return false,
+        };
+        
+        target.base = value;
+        for chain in chains.iter_mut() {
+            chain.set_effect_parameter(effect_id, param, target.base + target.offset);
+        }
+        true
+    }
+    
+    // Unmodulated value of an effect parameter, if it is a modulation destination
+    pub fn base(&self, effect_id: usize, param: &str) -> Option<f32> {
+        self.effect_targets.iter()
+            .find(|t| t.effect_id == effect_id && t.param == param)
+            .map(|t| t.base)
+    }
+    
+    fn source_value(source: ModSource, global: &GlobalModSources, voice: Option<&VoiceModSources>) -> f32 {
+        match source {
+            ModSource::Lfo(index) => global.lfos[index],
+            ModSource::Cc(cc) => global.cc[cc as usize],
+            ModSource::Aftertouch => global.aftertouch,
+            ModSource::PitchBend => global.pitch_bend,
+            ModSource::Follower => global.follower,
+            ModSource::Transient => global.transient,
+            ModSource::Envelope(index) => voice.map_or(0.0, |v| v.envelopes[index]),
+            ModSource::Velocity => voice.map_or(0.0, |v| v.velocity),
+            ModSource::KeyNumber => voice.map_or(0.0, |v| v.key),
+            ModSource::RandomPerNote => voice.map_or(0.0, |v| v.random),
+            ModSource::PolyAftertouch => voice.map_or(0.0, |v| v.pressure),
+            ModSource::Timbre => voice.map_or(0.0, |v| v.timbre),
+        }
+    }
+    
+    // Resolve effect destinations for this block. Per-voice sources follow `voice`,
+    // normally the most recently triggered voice.
+    pub fn resolve_effects(&mut self, global: &GlobalModSources, voice: Option<&VoiceModSources>,
+                           chains: &mut [EffectsChain]) {
+        if self.effect_targets.is_empty() {
+            return;
+        }
+        
+        for target in &mut self.effect_targets {
+            target.offset = 0.0;
+        }
+        
+        for slot in self.slots.iter().flatten() {
+            if let SlotTarget::Effect(index) = slot.target {
+                let value = slot.curve.apply(Self::source_value(slot.source, global, voice));
+                self.effect_targets[index].offset += value * slot.amount;
+            }
+        }
+        
+        for target in &self.effect_targets {
+            for chain in chains.iter_mut() {
+                chain.set_effect_parameter(target.effect_id, &target.param, target.base + target.offset);
+            }
+        }
+    }
+    
+    // Resolve the playback destinations of a single voice
+    pub fn resolve_voice(&self, global: &GlobalModSources, voice: &VoiceModSources) -> VoiceModulation {
+        let mut modulation = VoiceModulation::default();
+        
+        for slot in self.slots.iter().flatten() {
+            let value = slot.curve.apply(Self::source_value(slot.source, global, Some(voice))) * slot.amount;
+            match slot.target {
+                SlotTarget::Pitch => modulation.pitch += value,
+                SlotTarget::Pan => modulation.pan += value,
+                SlotTarget::SampleStart => modulation.sample_start += value,
+                SlotTarget::Effect(_) => {},
+            }
+        }
+        
+        modulation.pan = modulation.pan.clamp(-1.0, 1.0);
+        modulation.sample_start = modulation.sample_start.clamp(0.0, 1.0);
+        modulation
+    }
+}
+
+impl Default for ModMatrix {
+    fn default() -> Self {
+        ModMatrix::new()
+    }
+}
+
+// Effect parameter access that keeps the base value of modulated parameters up to date
+pub struct ModulatedEffects<'a> {
+    pub matrix: &'a mut ModMatrix,
+    pub chains: &'a mut [EffectsChain],
+}
+
+impl ParameterTarget for ModulatedEffects<'_> {
+    fn get_parameter(&self, effect_id: usize, param: &str) -> Option<f32> {
+        self.matrix.base(effect_id, param).or_else(|| self.chains.get_parameter(effect_id, param))
+    }
+    
+    fn set_parameter(&mut self, effect_id: usize, param: &str, value: f32) {
+        if !self.matrix.set_base(effect_id, param, value, self.chains) {
+            self.chains.set_parameter(effect_id, param, value);
+        }
+    }
+}
\ No newline at end of file
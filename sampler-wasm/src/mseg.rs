@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::EnvelopeCurve;
+
+// One breakpoint-to-breakpoint segment of a multi-segment envelope
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MsegSegment {
+    pub duration: f32,  // Seconds, or beats when tempo-synced
+    pub level: f32,     // Level reached at the end of the segment (0 to 1)
+    pub curve: EnvelopeCurve,
+}
+
+// The shape of a multi-segment envelope without its playback state, as saved in presets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MsegShape {
+    pub start_level: f32,
+    pub segments: Vec<MsegSegment>,
+    pub sustain_segment: Option<usize>,
+    pub loop_region: Option<(usize, usize)>,  // Inclusive
+    pub tempo_sync: bool,
+}
+
+// Multi-segment envelope generator with an optional sustain point and loop region
+#[derive(Debug, Clone)]
+pub struct Mseg {
+    // Shape
+    start_level: f32,
+    segments: Vec<MsegSegment>,
+    sustain_segment: Option<usize>,        // Hold at the end of this segment while the gate is on
+    loop_region: Option<(usize, usize)>,   // Repeat these segments (inclusive) while the gate is on
+    tempo_sync: bool,
+    tempo_bpm: f32,
+    
+    // Current state
+    gate: bool,
+    active: bool,
+    sustaining: bool,
+    segment_index: usize,
+    segment_time: f32,
+    segment_start_level: f32,
+    current_level: f32,
+    
+    // Sample rate for time calculations
+    sample_rate: f32,
+}
+
+impl Mseg {
+    pub fn new(sample_rate: f32) -> Self {
+        // Default to a simple attack / decay-to-sustain / release shape
+        let mut mseg = Mseg {
+            start_level: 0.0,
+            segments: Vec::new(),
+            sustain_segment: None,
+            loop_region: None,
+            tempo_sync: false,
+            tempo_bpm: 120.0,
+            
+            gate: false,
+            active: false,
+            sustaining: false,
+            segment_index: 0,
+            segment_time: 0.0,
+            segment_start_level: 0.0,
+            current_level: 0.0,
+            
+            sample_rate,
+        };
+        
+        mseg.set_segments(0.0, vec![
+            MsegSegment { duration: 0.01, level: 1.0, curve: EnvelopeCurve::Linear },
+            MsegSegment { duration: 0.1, level: 0.7, curve: EnvelopeCurve::Exponential },
+            MsegSegment { duration: 0.3, level: 0.0, curve: EnvelopeCurve::Exponential },
+        ]);
+        mseg.set_sustain(Some(1));
+        mseg
+    }
+    
+    pub fn set_segments(&mut self, start_level: f32, segments: Vec<MsegSegment>) {
+        self.start_level = start_level.clamp(0.0, 1.0);
+        self.segments = segments.into_iter()
+            .map(|segment| MsegSegment {
+                duration: segment.duration.max(0.0),
+                level: segment.level.clamp(0.0, 1.0),
+                curve: segment.curve,
+            })
+            .collect();
+        
+        // Drop sustain and loop markers that no longer point at a segment
+        let count = self.segments.len();
+        self.sustain_segment = self.sustain_segment.filter(|&index| index < count);
+        self.loop_region = self.loop_region.filter(|&(_, end)| end < count);
+        self.reset();
+    }
+    
+    pub fn segments(&self) -> &[MsegSegment] {
+        &self.segments
+    }
+    
+    pub fn set_sustain(&mut self, segment: Option<usize>) {
+        self.sustain_segment = segment.filter(|&index| index < self.segments.len());
+    }
+    
+    pub fn set_loop(&mut self, region: Option<(usize, usize)>) {
+        self.loop_region = region.filter(|&(start, end)| start <= end && end < self.segments.len());
+    }
+    
+    // Interpret segment durations as beats at the current tempo
+    pub fn set_tempo_sync(&mut self, enabled: bool) {
+        self.tempo_sync = enabled;
+    }
+    
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.tempo_bpm = bpm.clamp(1.0, 999.0);
+    }
+    
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+    
+    fn segment_seconds(&self, index: usize) -> f32 {
+        let duration = self.segments[index].duration;
+        if self.tempo_sync {
+            duration * 60.0 / self.tempo_bpm
+        } else {
+            duration
+        }
+    }
+    
+    pub fn trigger(&mut self) {
+        if self.segments.is_empty() {
+            return;
+        }
+        
+        // Continue from the current level when retriggered while still sounding (legato)
+        if !self.active {
+            self.current_level = self.start_level;
+        }
+        
+        self.gate = true;
+        self.active = true;
+        self.sustaining = false;
+        self.enter_segment(0);
+    }
+    
+    pub fn release(&mut self) {
+        if !self.active || !self.gate {
+            return;
+        }
+        self.gate = false;
+        self.sustaining = false;
+        
+        // Skip straight to whatever follows the sustain point or loop region
+        let release_start = match (self.sustain_segment, self.loop_region) {
+            (_, Some((_, loop_end))) => Some(loop_end + 1),
+            (Some(sustain), None) => Some(sustain + 1),
+            (None, None) => None,
+        };
+        
+        if let Some(release_start) = release_start {
+            if self.segment_index < release_start {
+                if release_start < self.segments.len() {
+                    self.enter_segment(release_start);
+                } else {
+                    self.active = false;
+                }
+            }
+        }
+    }
+    
+    pub fn reset(&mut self) {
+        self.gate = false;
+        self.active = false;
+        self.sustaining = false;
+        self.segment_index = 0;
+        self.segment_time = 0.0;
+        self.current_level = self.start_level;
+    }
+    
+    fn enter_segment(&mut self, index: usize) {
+        self.segment_index = index;
+        self.segment_time = 0.0;
+        self.segment_start_level = self.current_level;
+    }
+    
+    // Called when the current segment has reached its end level
+    fn advance(&mut self) {
+        let index = self.segment_index;
+        
+        if self.gate {
+            if let Some((loop_start, loop_end)) = self.loop_region {
+                if index == loop_end {
+                    self.enter_segment(loop_start);
+                    return;
+                }
+            }
+            
+            if self.sustain_segment == Some(index) {
+                self.sustaining = true;
+                return;
+            }
+        }
+        
+        if index + 1 < self.segments.len() {
+            self.enter_segment(index + 1);
+        } else {
+            self.active = false;
+        }
+    }
+    
+    pub fn process(&mut self) -> f32 {
+        if !self.active || self.sustaining {
+            return self.current_level;
+        }
+        
+        self.segment_time += 1.0 / self.sample_rate;
+        
+        // Zero-length segments jump straight to their level
+        let mut guard = self.segments.len() + 1;
+        while self.active && !self.sustaining && guard > 0 {
+            let segment = self.segments[self.segment_index];
+            let length = self.segment_seconds(self.segment_index);
+            
+            if self.segment_time >= length {
+                self.segment_time -= length;
+                self.current_level = segment.level;
+                self.advance();
+                guard -= 1;
+            } else {
+                let progress = segment.curve.shape(self.segment_time / length);
+                self.current_level = self.segment_start_level + (segment.level - self.segment_start_level) * progress;
+                break;
+            }
+        }
+        
+        self.current_level
+    }
+    
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+    
+    pub fn is_releasing(&self) -> bool {
+        self.active && !self.gate
+    }
+    
+    pub fn level(&self) -> f32 {
+        self.current_level
+    }
+    
+    pub fn shape(&self) -> MsegShape {
+        MsegShape {
+            start_level: self.start_level,
+            segments: self.segments.clone(),
+            sustain_segment: self.sustain_segment,
+            loop_region: self.loop_region,
+            tempo_sync: self.tempo_sync,
+        }
+    }
+    
+    // Replace the shape, checking it first so a bad one leaves the current shape alone
+    pub fn set_shape(&mut self, shape: &MsegShape) -> Result<(), String> {
+        if shape.segments.is_empty() {
+            return Err("MSEG shape has no segments".to_string());
+        }
+        let finite = shape.start_level.is_finite() && shape.segments.iter()
+            .all(|segment| segment.duration.is_finite() && segment.level.is_finite() && segment.curve.tension().is_finite());
+        if !finite {
+            return Err("MSEG shape has a value that isn't a finite number".to_string());
+        }
+        
+        self.set_segments(shape.start_level, shape.segments.clone());
+        self.set_tempo_sync(shape.tempo_sync);
+        self.set_sustain(shape.sustain_segment);
+        self.set_loop(shape.loop_region);
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,207 @@
+// Patch presets: everything about how the sampler sounds and responds, apart from the
+// sample data itself. Presets are written as JSON, or as MessagePack behind a short
+// header for a compact binary form, and carry a schema version so older presets can
+// be upgraded when the layout changes.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::midi::learn::MidiMapping;
+use crate::mseg::MsegShape;
+use crate::tuning::{KeyboardMapping, Scale};
+
+// Schema version written by this build
+pub const PRESET_VERSION: u32 = 1;
+
+// Start of a binary preset; anything else is read as JSON
+const BINARY_MAGIC: &[u8; 4] = b"SPRE";
+
+// Upgrade steps between schema versions: MIGRATIONS[n] turns a version n + 1 preset
+// into version n + 2. Append a step whenever PRESET_VERSION is bumped; fields that are
+// renamed, moved or given new meanings belong here rather than in the structs below.
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeSettings {
+    pub attack: f32,
+    pub decay: f32,
+    pub hold: f32,
+    pub sustain: f32,
+    pub release: f32,
+    pub attack_curve: f32,   // Tension, -1 to 1
+    pub decay_curve: f32,
+    pub release_curve: f32,
+    pub velocity_to_level: f32,
+    pub velocity_to_attack: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MpeZoneSettings {
+    pub members: u8,
+    pub member_bend_range: f32,
+    pub manager_bend_range: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MpeSettings {
+    pub lower_zone: Option<MpeZoneSettings>,
+    pub upper_zone: Option<MpeZoneSettings>,
+    pub pressure_to_amp: f32,
+    pub timbre_cutoff_low: f32,
+    pub timbre_cutoff_high: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LfoSettings {
+    pub shape: u32,
+    pub rate: f32,                 // Hz
+    pub sync_beats: Option<f32>,   // Cycle length when tempo-synced
+    pub phase_offset: f32,
+    pub retrigger: bool,
+    pub fade_in: f32,              // Seconds
+}
+
+// Parameter values of one effect in the chain, by name. Modulated parameters store
+// the value modulation is added to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectSettings {
+    pub name: String,
+    pub parameters: BTreeMap<String, f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModSlotSettings {
+    pub slot: usize,
+    pub source: String,        // e.g. "lfo:0"
+    pub destination: String,   // e.g. "effect:0:cutoff"
+    pub amount: f32,
+    pub curve: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroTargetSettings {
+    pub effect_id: usize,
+    pub param: String,
+    pub min: f32,
+    pub max: f32,
+    pub curve: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroSettings {
+    pub name: String,
+    pub value: f32,
+    pub cc: Option<u8>,
+    pub targets: Vec<MacroTargetSettings>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FollowerSettings {
+    pub input: u32,
+    pub attack: f32,
+    pub release: f32,
+    pub rms: bool,
+    pub transient_threshold_db: f32,
+    pub transient_floor_db: f32,
+    pub transient_retrigger_time: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub version: u32,
+    pub amp_envelope: EnvelopeSettings,
+    pub mod_envelope: EnvelopeSettings,
+    pub retrigger_mode: u32,
+    pub mseg: MsegShape,
+    pub mseg_amplitude: bool,
+    pub pitch_bend_range: f32,
+    pub tempo: f32,
+    pub scale: Scale,
+    pub keyboard_mapping: KeyboardMapping,
+    pub mpe: MpeSettings,
+    pub lfos: Vec<LfoSettings>,
+    pub effects: Vec<EffectSettings>,
+    pub mod_slots: Vec<ModSlotSettings>,
+    pub macros: Vec<MacroSettings>,
+    pub midi_mappings: Vec<MidiMapping>,
+    pub follower: FollowerSettings,
+    pub resample_quality: u32,
+}
+
+impl Preset {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+    
+    // Header followed by MessagePack with field names kept, so binary presets go
+    // through the same migrations as JSON ones
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut data = BINARY_MAGIC.to_vec();
+        data.extend(rmp_serde::to_vec_named(self).unwrap_or_default());
+        data
+    }
+    
+    // Read a JSON or binary preset, upgrading it from older schema versions
+    pub fn from_bytes(data: &[u8]) -> Result<Preset, String> {
+        let value: Value = if data.starts_with(BINARY_MAGIC) {
+            rmp_serde::from_slice(&data[BINARY_MAGIC.len()..]).map_err(|e| format!("Invalid binary preset: {}", e))?
+        } else {
+            serde_json::from_slice(data).map_err(|e| format!("Invalid preset JSON: {}", e))?
+        };
+        migrate(value)
+    }
+}
+
+fn migrate(mut value: Value) -> Result<Preset, String> {
+    let version = value.get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| "Preset has no schema version".to_string())?;
+    if version == 0 || version > PRESET_VERSION as u64 {
+        return Err(format!("Unsupported preset version {} (this build reads up to {})", version, PRESET_VERSION));
+    }
+    
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(&mut value);
+    }
+    value["version"] = Value::from(PRESET_VERSION);
+    
+    serde_json::from_value(value).map_err(|e| format!("Invalid preset: {}", e))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopeCurve;
+    use crate::mseg::MsegSegment;
+    use crate::processor::SamplerProcessorState;
+    
+    fn preset() -> Preset {
+        let mut processor = SamplerProcessorState::with_sample_rate(48000.0);
+        processor.set_mod_slot(0, "lfo:0", "effect:0:cutoff", 2000.0, 1);
+        processor.add_midi_mapping("cc:74", "effect:0:cutoff", 200.0, 8000.0, 1, true);
+        let mut preset = processor.to_preset();
+        preset.mseg = MsegShape {
+            start_level: 0.0,
+            segments: vec![
+                MsegSegment { duration: 0.1, level: 1.0, curve: EnvelopeCurve::Exponential },
+                MsegSegment { duration: 0.5, level: 0.4, curve: EnvelopeCurve::Tension(-0.5) },
+            ],
+            sustain_segment: Some(1),
+            loop_region: Some((0, 1)),
+            tempo_sync: false,
+        };
+        preset
+    }
+    
+    #[test]
+    fn json_round_trip() {
+        let preset = preset();
+        assert_eq!(Preset::from_bytes(preset.to_json().as_bytes()), Ok(preset));
+    }
+    
+    #[test]
+    fn binary_round_trip() {
+        let preset = preset();
+        assert_eq!(Preset::from_bytes(&preset.to_binary()), Ok(preset));
+    }
+}
\ No newline at end of file
@@ -1,18 +1,165 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+
 use wasm_bindgen::prelude::*;
-use js_sys::{Float32Array, Object};
+use js_sys::{Array, Float32Array, Function, Object, Reflect, Uint8Array};
 use web_sys::{console};
 
+use crate::audio_file::{self, DecodedAudio, SampleMetadata, WavEncoding};
+use crate::automation::{AutomationQueue, ParameterTarget, RampType};
+use crate::effects::EffectsChain;
+use crate::effects::delay::Delay;
+use crate::effects::distortion::{Distortion, DistortionType};
+use crate::effects::filter::{Filter, FilterType};
+use crate::envelope::{Envelope, EnvelopeCurve, RetriggerMode};
+use crate::follower::{DetectionMode, EnvelopeFollower, FollowerInput, TransientDetector};
+use crate::instrument::{Sf2File, SfzInstrument};
+use crate::keymap::{self, AlternationGroup, AlternationMode, Keymap, LoopMode, Zone};
+use crate::lfo::{Lfo, LfoShape, NUM_LFOS};
+use crate::macro_controls::{MacroBank, MacroTarget, NUM_MACROS};
+use crate::midi::{self, MidiFile, MidiLearn, MidiMessage, MidiParser, Recorder, Sequencer};
+use crate::midi::learn::{ControllerId, EnvelopeParam, LearnTarget, MidiMapping};
+use crate::midi::clock::{ClockFollower, ClockGenerator};
+use crate::midi::mpe::{self, MpeConfig};
+use crate::mseg::{Mseg, MsegShape};
+use crate::preset::{
+    EffectSettings, EnvelopeSettings, FollowerSettings, LfoSettings, MacroSettings, MacroTargetSettings,
+    ModSlotSettings, MpeSettings, MpeZoneSettings, Preset, PRESET_VERSION,
+};
+use crate::project::{
+    LoopRegion, Project, ProjectAlternationGroup, ProjectManifest, ProjectSample, ProjectZone, SessionMarker,
+    VideoReference, PROJECT_VERSION,
+};
+use crate::resample::{self, ResampleQuality, Resampler};
+use crate::tuning::{KeyboardMapping, Scale, Tuning};
+use crate::mod_matrix::{GlobalModSources, ModCurve, ModDestination, ModMatrix, ModSource, ModulatedEffects, NUM_MOD_SLOTS};
+use crate::utils::{self, XorShiftRng};
+use crate::voice::{Voice, MAX_VOICES};
+
+// Effect indices of the default chain, used as `effect_id` by the JS side
+pub const EFFECT_FILTER: usize = 0;
+pub const EFFECT_DELAY: usize = 1;
+pub const EFFECT_DISTORTION: usize = 2;
+
+const OUTPUT_CHANNELS: usize = 2;
+
+// Modulation is resolved once per this many frames
+const MODULATION_BLOCK_SIZE: usize = 32;
+
+// Offline renders run in blocks of this many frames; without a fixed length they stop
+// once a block peaks below the silence threshold, or after the tail limit
+const OFFLINE_BLOCK_SIZE: usize = 128;
+const OFFLINE_SILENCE_DB: f32 = -90.0;
+const OFFLINE_MAX_TAIL_SECONDS: f32 = 30.0;
+
+// Build the default effect chain with every effect starting out transparent
+fn build_effects_chain(sample_rate: f32) -> EffectsChain {
+    let mut chain = EffectsChain::new();
+    chain.add_effect(Box::new(Filter::new(FilterType::LowPass, sample_rate * 0.49, 0.707, sample_rate)));
+    chain.add_effect(Box::new(Delay::new(0.25, 0.3, 0.0, sample_rate)));
+    chain.add_effect(Box::new(Distortion::new(DistortionType::Soft, 1.0, 0.0, 1.0)));
+    chain
+}
+
+// Presets in a SoundFont as { name, bank, program } objects
+fn preset_list(file: &Sf2File) -> Result<Array, JsValue> {
+    let presets = Array::new();
+    for preset in &file.presets {
+        let entry = Object::new();
+        Reflect::set(&entry, &"name".into(), &JsValue::from_str(&preset.name))?;
+        Reflect::set(&entry, &"bank".into(), &JsValue::from(preset.bank))?;
+        Reflect::set(&entry, &"program".into(), &JsValue::from(preset.program))?;
+        presets.push(&entry);
+    }
+    Ok(presets)
+}
+
 #[wasm_bindgen]
 pub struct SamplerProcessorState {
     // Sample data
-    sample_data: Vec<f32>,
+    sample_data: Rc<[f32]>,
     sample_rate: f32,
+    sample_metadata: SampleMetadata,  // Loops, markers and tempo from the loaded file
+    sample_name: String,  // File name the sample is saved under in projects
+    resample_quality: ResampleQuality,  // Conversion of loaded samples to the output rate
+    keymap: Keymap,
+    amp_envelope: (f32, f32, f32, f32, f32),  // Instrument envelope for zones without their own
+    zone_selection: Vec<(usize, f32)>,  // Zones picked for the current note-on
+    
+    // Rate of the AudioContext we render into
+    output_sample_rate: f32,
     
     // Playback state
     playback_index: f64,
     is_playing: bool,
+    transport_position: f64,  // Seconds since the start of the timeline, shared with the video
+    transport_beats: f64,  // Quarter notes played, each frame counted at the tempo it played at
+    loop_region: LoopRegion,
+    markers: Vec<SessionMarker>,  // Timeline markers, in time order
+    video: Option<VideoReference>,  // Kept for projects; the video itself plays on the JS side
+    
+    // One effect chain per output channel, kept in lockstep
+    effects: Vec<EffectsChain>,
+    
+    // Sample-accurate parameter automation
+    automation: AutomationQueue,
+    current_frame: u64,
+    
+    // Voices playing the loaded sample
+    voices: Vec<Voice>,
+    note_counter: u64,
+    last_voice: Option<usize>,
+    retrigger_mode: RetriggerMode,
+    held_notes: Vec<u8>,  // Held keys in press order, used in mono mode
+    sustain_pedal: [bool; 16],
+    channel_bend: [f32; 16],  // Per-channel pitch bend (-1 to 1)
+    pitch_bend_range: f32,  // Semitones at full bend
+    tuning: Tuning,
+    
+    // MPE zones and the last per-channel expression, applied to notes as they start
+    mpe: MpeConfig,
+    channel_pressure: [f32; 16],
+    channel_timbre: [f32; 16],
+    mpe_pressure_to_amp: f32,
+    mpe_timbre_cutoff: (f32, f32),
+    rng: XorShiftRng,
     
-    // Future fields for looping, envelope, etc. will be added here
+    // Modulation sources and routing
+    lfos: Vec<Lfo>,
+    tempo_bpm: f32,
+    mod_sources: GlobalModSources,
+    mod_matrix: ModMatrix,
+    frames_until_modulation: usize,
+    
+    // Performance macros
+    macros: MacroBank,
+    
+    // Multi-segment envelope shape copied into every voice
+    mseg: Mseg,
+    
+    // Audio-derived modulation, analysed once per modulation block
+    follower: EnvelopeFollower,
+    transient_detector: TransientDetector,
+    follower_input: FollowerInput,
+    analysis_buffer: Vec<f32>,
+    
+    // Incoming MIDI, dispatched at the frame it was scheduled for
+    midi_parser: MidiParser,
+    midi_queue: VecDeque<(u64, MidiMessage)>,
+    sysex_queue: VecDeque<Vec<u8>>,  // SysEx passed through for the JS side to collect
+    sequencer: Sequencer,
+    recorder: Recorder,
+    record_clock: (f64, u64),  // Transport position and frame that takes made while stopped count from
+    midi_learn: MidiLearn,
+    clock_follower: ClockFollower,
+    follow_clock: bool,  // Let incoming MIDI clock drive tempo and transport
+    clock_generator: ClockGenerator,
+    
+    // Render scratch buffers, grown on demand and reused across blocks
+    left_buffer: Vec<f32>,
+    right_buffer: Vec<f32>,
+    input_buffer: Vec<f32>,
+    input_frames: usize,  // Valid frames of external input for the next render
 }
 
 #[wasm_bindgen]
@@ -21,18 +168,57 @@ impl SamplerProcessorState {
     pub fn new() -> SamplerProcessorState {
         console::log_1(&"Creating new SamplerProcessorState".into());
         
-        SamplerProcessorState {
-            sample_data: Vec::new(),
-            sample_rate: 44100.0,
-            playback_index: 0.0,
-            is_playing: false,
-        }
+        SamplerProcessorState::with_sample_rate(44100.0)
     }
     
     #[wasm_bindgen]
     pub fn process(&mut self, inputs: &JsValue, outputs: &JsValue, _parameters: &JsValue) -> bool {
-        // This will be implemented to process audio
-        // For now, just return true to keep the processor alive
+        // Keep the first channel of the first input for audio-derived modulation
+        let inputs: &Array = inputs.unchecked_ref();
+        let input = inputs.get(0);
+        if !input.is_undefined() {
+            let input_channels: Array = input.unchecked_into();
+            if input_channels.length() > 0 {
+                let channel: Float32Array = input_channels.get(0).unchecked_into();
+                let frames = channel.length() as usize;
+                if self.input_buffer.len() < frames {
+                    self.input_buffer.resize(frames, 0.0);
+                }
+                channel.copy_to(&mut self.input_buffer[..frames]);
+                self.input_frames = frames;
+            }
+        }
+        
+        let outputs: &Array = outputs.unchecked_ref();
+        let output = outputs.get(0);
+        if output.is_undefined() {
+            return true;
+        }
+        
+        let channels: Array = output.unchecked_into();
+        if channels.length() == 0 {
+            return true;
+        }
+        
+        let first_channel: Float32Array = channels.get(0).unchecked_into();
+        let frames = first_channel.length() as usize;
+        
+        self.render(frames);
+        
+        if channels.length() == 1 {
+            // Fold the stereo mix down for a mono output
+            for i in 0..frames {
+                self.left_buffer[i] = 0.5 * (self.left_buffer[i] + self.right_buffer[i]);
+            }
+            first_channel.copy_from(&self.left_buffer[..frames]);
+        } else {
+            for channel_index in 0..channels.length() {
+                let channel: Float32Array = channels.get(channel_index).unchecked_into();
+                let source = if channel_index % 2 == 0 { &self.left_buffer } else { &self.right_buffer };
+                channel.copy_from(&source[..frames]);
+            }
+        }
+        
         true
     }
     
@@ -41,24 +227,2120 @@ impl SamplerProcessorState {
         let mut sample_vec = vec![0.0; data.length() as usize];
         data.copy_to(&mut sample_vec);
         
-        self.sample_data = sample_vec;
-        self.sample_rate = sample_rate;
+        self.load_sample(sample_vec, sample_rate);
         
         console::log_1(&format!("Loaded sample data: {} samples at {}Hz", 
                                self.sample_data.len(), self.sample_rate).into());
     }
     
+    // Decode a WAV, AIFF or FLAC file in Rust, keeping its loop points, markers, root key and tempo
+    #[wasm_bindgen]
+    pub fn load_audio_file(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let audio = audio_file::decode(bytes).map_err(|e| JsValue::from_str(&e))?;
+        self.load_audio(audio);
+        
+        console::log_1(&format!("Loaded audio file: {} samples at {}Hz, {} loops, {} markers",
+                               self.sample_data.len(), self.sample_rate,
+                               self.sample_metadata.loops.len(), self.sample_metadata.markers.len()).into());
+        Ok(())
+    }
+    
+    // Loops from the loaded file as { start, end, pingPong } in sample frames (end exclusive)
+    #[wasm_bindgen]
+    pub fn sample_loops(&self) -> Result<Array, JsValue> {
+        let loops = Array::new();
+        for sample_loop in &self.sample_metadata.loops {
+            let entry = Object::new();
+            Reflect::set(&entry, &"start".into(), &JsValue::from(sample_loop.start as f64))?;
+            Reflect::set(&entry, &"end".into(), &JsValue::from(sample_loop.end as f64))?;
+            Reflect::set(&entry, &"pingPong".into(), &JsValue::from(sample_loop.ping_pong))?;
+            loops.push(&entry);
+        }
+        Ok(loops)
+    }
+    
+    // Markers from the loaded file as { position, time, name }, position in sample frames
+    #[wasm_bindgen]
+    pub fn sample_markers(&self) -> Result<Array, JsValue> {
+        let markers = Array::new();
+        for marker in &self.sample_metadata.markers {
+            let entry = Object::new();
+            Reflect::set(&entry, &"position".into(), &JsValue::from(marker.position as f64))?;
+            Reflect::set(&entry, &"time".into(), &JsValue::from(marker.position as f64 / self.sample_rate as f64))?;
+            Reflect::set(&entry, &"name".into(), &JsValue::from_str(&marker.name))?;
+            markers.push(&entry);
+        }
+        Ok(markers)
+    }
+    
+    // Tempo stored in the loaded file, or 0 if it has none
+    #[wasm_bindgen]
+    pub fn sample_tempo(&self) -> f32 {
+        self.sample_metadata.tempo.unwrap_or(0.0)
+    }
+    
+    // Bounce to a WAV file (encoding 0 = 16-bit, 1 = 24-bit, 2 = 32-bit float).
+    // `events` holds { time, data } MIDI messages, time in seconds from the start of
+    // the render. A duration of 0 renders until the events end and the sound dies away.
+    // A `sample_rate` other than 0 converts the bounce from the output rate. The render
+    // runs on a copy of the patch, instrument and transport (see `offline_copy`), so
+    // this processor's own state is left as it was.
+    #[wasm_bindgen]
+    pub fn render_to_wav(&self, duration: f64, events: &Array, encoding: u32, dither: bool,
+                         sample_rate: f32) -> Result<Vec<u8>, JsValue> {
+        let mut midi_events = Vec::with_capacity(events.length() as usize);
+        for event in events.iter() {
+            let time = Reflect::get(&event, &"time".into())?.as_f64().unwrap_or(0.0).max(0.0);
+            let data: Uint8Array = Reflect::get(&event, &"data".into())?.dyn_into()?;
+            midi_events.push(((time * self.output_sample_rate as f64).round() as u64, data.to_vec()));
+        }
+        
+        let frames = (duration > 0.0).then(|| (duration * self.output_sample_rate as f64).round() as usize);
+        let [mut left, mut right] = self.offline_copy().render_offline(frames, &midi_events);
+        
+        let target_rate = if sample_rate > 0.0 { sample_rate } else { self.output_sample_rate };
+        if target_rate != self.output_sample_rate {
+            let resampler = Resampler::new(self.output_sample_rate, target_rate, self.resample_quality)
+                .map_err(|error| JsValue::from_str(&error))?;
+            left = resampler.process(&left);
+            right = resampler.process(&right);
+        }
+        
+        console::log_1(&format!("Rendered {} frames offline at {}Hz", left.len(), target_rate).into());
+        Ok(audio_file::wav::encode(&[&left, &right], target_rate.round() as u32,
+                                   WavEncoding::from_index(encoding), dither))
+    }
+    
+    // Sample files an SFZ instrument refers to, so they can be fetched and decoded
+    // before `load_sfz`
+    #[wasm_bindgen]
+    pub fn sfz_sample_paths(&self, text: &str) -> Result<Array, JsValue> {
+        let instrument = SfzInstrument::parse(text).map_err(|e| JsValue::from_str(&e))?;
+        Ok(instrument.sample_paths().iter().map(|path| JsValue::from_str(path)).collect())
+    }
+    
+    // Load an SFZ instrument. `lookup(path)` returns the decoded sample as
+    // { data: Float32Array, sampleRate } or null if it isn't available; the
+    // paths that couldn't be loaded are returned.
+    #[wasm_bindgen]
+    pub fn load_sfz(&mut self, text: &str, lookup: &Function) -> Result<Array, JsValue> {
+        let instrument = SfzInstrument::parse(text).map_err(|e| JsValue::from_str(&e))?;
+        let (keymap, missing) = instrument.to_keymap(|path| {
+            let sample = lookup.call1(&JsValue::NULL, &JsValue::from_str(path)).ok()?;
+            let data: Float32Array = Reflect::get(&sample, &"data".into()).ok()?.dyn_into().ok()?;
+            let sample_rate = Reflect::get(&sample, &"sampleRate".into()).ok()?.as_f64()?;
+            Some((Rc::from(data.to_vec()), sample_rate as f32))
+        });
+        
+        console::log_1(&format!("Loaded SFZ instrument: {} zones, {} missing samples",
+                               keymap.zones().len(), missing.len()).into());
+        self.load_keymap(keymap);
+        Ok(missing.iter().map(|path| JsValue::from_str(path)).collect())
+    }
+    
+    // Presets in a SoundFont as { name, bank, program } objects
+    #[wasm_bindgen]
+    pub fn sf2_presets(&self, bytes: &[u8]) -> Result<Array, JsValue> {
+        let file = Sf2File::parse(bytes).map_err(|e| JsValue::from_str(&e))?;
+        preset_list(&file)
+    }
+    
+    // Load one preset of a SoundFont as the instrument, the first one when no bank and
+    // program are given. Returns every preset of the SoundFont, as `sf2_presets` does.
+    #[wasm_bindgen]
+    pub fn load_sf2(&mut self, bytes: &[u8], bank: Option<u16>, program: Option<u16>) -> Result<Array, JsValue> {
+        let file = Sf2File::parse(bytes).map_err(|e| JsValue::from_str(&e))?;
+        let index = match (bank, program) {
+            (Some(bank), Some(program)) => file.find_preset(bank, program)
+                .ok_or_else(|| JsValue::from_str(&format!("No preset {}:{} in SoundFont", bank, program)))?,
+            _ => 0,
+        };
+        let keymap = file.preset_keymap(index).map_err(|e| JsValue::from_str(&e))?;
+        
+        console::log_1(&format!("Loaded SoundFont preset '{}': {} zones", file.presets[index].name, keymap.zones().len()).into());
+        self.load_keymap(keymap);
+        preset_list(&file)
+    }
+    
+    // Quality of the conversion applied to samples loaded at a rate other than the
+    // output rate (0 = draft, 1 = standard, 2 = high); affects later loads and bounces
+    #[wasm_bindgen]
+    pub fn set_resample_quality(&mut self, quality: u32) {
+        self.resample_quality = ResampleQuality::from_index(quality);
+    }
+    
+    // Remove every zone; notes are silent until zones are added or a sample is loaded
+    #[wasm_bindgen]
+    pub fn clear_zones(&mut self) {
+        self.all_notes_off();
+        self.keymap.clear();
+    }
+    
+    // Add a zone playing `data` across the whole keyboard; returns its index
+    #[wasm_bindgen]
+    pub fn add_zone(&mut self, data: &Float32Array, sample_rate: f32, root_key: u8) -> usize {
+        let mut sample = vec![0.0; data.length() as usize];
+        data.copy_to(&mut sample);
+        
+        let sample = resample::resample(&sample, sample_rate, self.output_sample_rate, self.resample_quality);
+        let mut zone = Zone::new(Rc::from(sample), self.output_sample_rate);
+        zone.source_rate = sample_rate;
+        zone.root_key = root_key.min(127);
+        self.keymap.add_zone(zone)
+    }
+    
+    // Limit a zone to a key range and velocity range (inclusive)
+    #[wasm_bindgen]
+    pub fn set_zone_range(&mut self, index: usize, low_key: u8, high_key: u8, low_velocity: u8, high_velocity: u8) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            zone.key_range = (low_key.min(127), high_key.min(127));
+            zone.velocity_range = (low_velocity.min(127), high_velocity.min(127));
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn zone_count(&self) -> usize {
+        self.keymap.zones().len()
+    }
+    
+    // Fine tuning in cents
+    #[wasm_bindgen]
+    pub fn set_zone_tune(&mut self, index: usize, cents: f32) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            zone.tune = cents.clamp(-9600.0, 9600.0);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_zone_gain(&mut self, index: usize, db: f32) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            zone.gain = keymap::zone_gain(db);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_zone_pan(&mut self, index: usize, pan: f32) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            zone.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+    
+    // 0: No loop, 1: One-shot, 2: Continuous, 3: Sustain. `end` is exclusive, in frames
+    // of the sample as it was added, and follows it through any rate conversion.
+    #[wasm_bindgen]
+    pub fn set_zone_loop(&mut self, index: usize, mode: u32, start: usize, end: usize) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            let ratio = if zone.source_rate > 0.0 { zone.sample_rate as f64 / zone.source_rate as f64 } else { 1.0 };
+            let scale = |position: usize| (position as f64 * ratio).round() as usize;
+            zone.loop_mode = LoopMode::from_index(mode);
+            zone.loop_end = scale(end);
+            zone.loop_start = scale(start).min(zone.loop_end);
+        }
+    }
+    
+    // Fade the zone in across one velocity range and out across another; pass equal
+    // low and high values to leave that side without a fade
+    #[wasm_bindgen]
+    pub fn set_zone_velocity_crossfade(&mut self, index: usize, fade_in_low: u8, fade_in_high: u8,
+                                       fade_out_low: u8, fade_out_high: u8) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            zone.velocity_fade_in = (fade_in_high > fade_in_low).then_some((fade_in_low, fade_in_high));
+            zone.velocity_fade_out = (fade_out_high > fade_out_low).then_some((fade_out_low, fade_out_high));
+        }
+    }
+    
+    // Add a group whose zones take turns, in order or at random, over `length` positions
+    #[wasm_bindgen]
+    pub fn add_alternation_group(&mut self, random: bool, length: usize) -> usize {
+        let mode = if random { AlternationMode::Random } else { AlternationMode::RoundRobin };
+        self.keymap.add_group(AlternationGroup::new(mode, length))
+    }
+    
+    // File name of a zone's sample, used to find it again when a project only references it
+    #[wasm_bindgen]
+    pub fn set_zone_sample_name(&mut self, index: usize, name: &str) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            zone.sample_name = name.to_string();
+        }
+    }
+    
+    // Put a zone at `position` (0-based) of an alternation group
+    #[wasm_bindgen]
+    pub fn set_zone_alternation(&mut self, index: usize, group: usize, position: usize) {
+        if let Some(zone) = self.keymap.zone_mut(index) {
+            zone.alternation = Some((group, position));
+        }
+    }
+    
     #[wasm_bindgen]
     pub fn set_playback_state(&mut self, is_playing: bool) {
+        if is_playing && !self.is_playing {
+            self.clock_generator.start(self.transport_beats);
+        } else if !is_playing {
+            self.clock_generator.stop();
+            self.clock_generator.locate(0.0);
+        }
         self.is_playing = is_playing;
         
         if !is_playing {
             // Reset playback position when stopping
             self.playback_index = 0.0;
+            self.recorder.stop(self.transport_position);
+            self.transport_position = 0.0;
+            self.transport_beats = 0.0;
+            self.sequencer.seek(0.0);
         }
         
         console::log_1(&format!("Playback state set to: {}", is_playing).into());
     }
+    
+    // Move the transport (sample playback and MIDI file) to a time in seconds
+    #[wasm_bindgen]
+    pub fn seek(&mut self, time: f64) {
+        let time = time.max(0.0);
+        self.transport_position = time;
+        self.transport_beats = time * self.tempo_bpm as f64 / 60.0;
+        self.playback_index = time * self.sample_rate as f64;
+        self.sequencer.seek(time);
+        self.clock_generator.locate(self.transport_beats);
+        self.record_clock = (time, self.current_frame);
+    }
+    
+    #[wasm_bindgen]
+    pub fn transport_position(&self) -> f64 {
+        self.transport_position
+    }
+    
+    // Loop the transport between two times in seconds while playing
+    #[wasm_bindgen]
+    pub fn set_loop_region(&mut self, start: f64, end: f64, enabled: bool) {
+        let start = start.max(0.0);
+        self.loop_region = LoopRegion { start, end: end.max(start), enabled };
+    }
+    
+    // The transport loop as { start, end, enabled }
+    #[wasm_bindgen]
+    pub fn loop_region(&self) -> Result<Object, JsValue> {
+        let region = Object::new();
+        Reflect::set(&region, &"start".into(), &JsValue::from(self.loop_region.start))?;
+        Reflect::set(&region, &"end".into(), &JsValue::from(self.loop_region.end))?;
+        Reflect::set(&region, &"enabled".into(), &JsValue::from(self.loop_region.enabled))?;
+        Ok(region)
+    }
+    
+    // Add a named marker at a time in seconds; returns its index in time order
+    #[wasm_bindgen]
+    pub fn add_marker(&mut self, time: f64, name: &str) -> usize {
+        let time = time.max(0.0);
+        let index = self.markers.partition_point(|marker| marker.time <= time);
+        self.markers.insert(index, SessionMarker { time, name: name.to_string() });
+        index
+    }
+    
+    #[wasm_bindgen]
+    pub fn remove_marker(&mut self, index: usize) {
+        if index < self.markers.len() {
+            self.markers.remove(index);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+    }
+    
+    // Timeline markers as { time, name }, in time order
+    #[wasm_bindgen]
+    pub fn markers(&self) -> Result<Array, JsValue> {
+        let markers = Array::new();
+        for marker in &self.markers {
+            let entry = Object::new();
+            Reflect::set(&entry, &"time".into(), &JsValue::from(marker.time))?;
+            Reflect::set(&entry, &"name".into(), &JsValue::from_str(&marker.name))?;
+            markers.push(&entry);
+        }
+        Ok(markers)
+    }
+    
+    // Name of the loaded sample's file, used to find it again when a project only
+    // references it. Loading a sample clears the name.
+    #[wasm_bindgen]
+    pub fn set_sample_name(&mut self, name: &str) {
+        self.sample_name = name.to_string();
+    }
+    
+    #[wasm_bindgen]
+    pub fn sample_name(&self) -> String {
+        self.sample_name.clone()
+    }
+    
+    // The video playing alongside the timeline and the timeline position of its first
+    // frame in seconds, stored in projects. An empty path clears it.
+    #[wasm_bindgen]
+    pub fn set_video_reference(&mut self, path: &str, offset: f64) {
+        self.video = (!path.is_empty()).then(|| VideoReference { path: path.to_string(), offset });
+    }
+    
+    // The video reference as { path, offset }, or null
+    #[wasm_bindgen]
+    pub fn video_reference(&self) -> Result<JsValue, JsValue> {
+        let video = match &self.video {
+            Some(video) => video,
+            None => return Ok(JsValue::NULL),
+        };
+        let reference = Object::new();
+        Reflect::set(&reference, &"path".into(), &JsValue::from_str(&video.path))?;
+        Reflect::set(&reference, &"offset".into(), &JsValue::from(video.offset))?;
+        Ok(reference.into())
+    }
+    
+    // Set the rate of the AudioContext (the worklet's global `sampleRate`)
+    #[wasm_bindgen]
+    pub fn set_output_sample_rate(&mut self, sample_rate: f32) {
+        if sample_rate > 0.0 && sample_rate != self.output_sample_rate {
+            self.output_sample_rate = sample_rate;
+            // Effects and routing keep their settings; only rate-dependent state changes
+            for chain in &mut self.effects {
+                chain.set_sample_rate(sample_rate);
+            }
+            
+            for lfo in &mut self.lfos {
+                lfo.set_sample_rate(sample_rate);
+            }
+            self.mseg.set_sample_rate(sample_rate);
+            self.follower.set_sample_rate(sample_rate);
+            self.clock_follower.set_sample_rate(sample_rate);
+            self.transient_detector.set_sample_rate(sample_rate);
+            for voice in &mut self.voices {
+                voice.set_sample_rate(sample_rate);
+            }
+            self.conform_sample_rate();
+        }
+    }
+    
+    // Set the tempo used by tempo-synced modulation
+    #[wasm_bindgen]
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.tempo_bpm = bpm.clamp(1.0, 999.0);
+        for lfo in &mut self.lfos {
+            lfo.set_tempo(self.tempo_bpm);
+        }
+        self.mseg.set_tempo(self.tempo_bpm);
+        for voice in &mut self.voices {
+            voice.mseg.set_tempo(self.tempo_bpm);
+        }
+    }
+    
+    // 0: Sine, 1: Triangle, 2: Saw, 3: Square, 4: Sample & Hold, 5: Smoothed Random
+    #[wasm_bindgen]
+    pub fn set_lfo_shape(&mut self, index: usize, shape: u32) {
+        if let Some(lfo) = self.lfos.get_mut(index) {
+            lfo.set_shape(LfoShape::from_index(shape));
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_lfo_rate(&mut self, index: usize, rate_hz: f32) {
+        if let Some(lfo) = self.lfos.get_mut(index) {
+            lfo.set_rate(rate_hz);
+        }
+    }
+    
+    // Sync the LFO to the tempo with a cycle length in beats; 0 returns to the free rate
+    #[wasm_bindgen]
+    pub fn set_lfo_sync(&mut self, index: usize, beats: f32) {
+        if let Some(lfo) = self.lfos.get_mut(index) {
+            lfo.set_sync(beats);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_lfo_phase_offset(&mut self, index: usize, offset: f32) {
+        if let Some(lfo) = self.lfos.get_mut(index) {
+            lfo.set_phase_offset(offset);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_lfo_retrigger(&mut self, index: usize, retrigger: bool) {
+        if let Some(lfo) = self.lfos.get_mut(index) {
+            lfo.set_retrigger(retrigger);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_lfo_fade_in(&mut self, index: usize, fade_in_seconds: f32) {
+        if let Some(lfo) = self.lfos.get_mut(index) {
+            lfo.set_fade_in(fade_in_seconds);
+        }
+    }
+    
+    // Current LFO output, e.g. for drawing it in the UI
+    #[wasm_bindgen]
+    pub fn lfo_value(&self, index: usize) -> f32 {
+        self.lfos.get(index).map_or(0.0, |lfo| lfo.value())
+    }
+    
+    // Set an effect parameter immediately. For modulated parameters this sets the
+    // value that modulation is added to.
+    #[wasm_bindgen]
+    pub fn set_effect_parameter(&mut self, effect_id: usize, param: &str, value: f32) -> bool {
+        if self.effects[0].get_effect_parameter(effect_id, param).is_none() {
+            return false;
+        }
+        
+        let mut target = ModulatedEffects { matrix: &mut self.mod_matrix, chains: &mut self.effects };
+        target.set_parameter(effect_id, param, value);
+        true
+    }
+    
+    // Start a voice for a note (velocity 0 to 1) on MIDI channel 1
+    #[wasm_bindgen]
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        self.channel_note_on(0, note, velocity);
+    }
+    
+    // Release a note played with `note_on`
+    #[wasm_bindgen]
+    pub fn note_off(&mut self, note: u8) {
+        self.channel_note_off(0, note);
+    }
+    
+    // Silence all voices immediately
+    #[wasm_bindgen]
+    pub fn all_notes_off(&mut self) {
+        for voice in &mut self.voices {
+            voice.kill();
+        }
+        self.last_voice = None;
+        self.held_notes.clear();
+    }
+    
+    // Sustain pedal for MIDI channel 1 (CC 64 on other channels arrives through `handle_midi`)
+    #[wasm_bindgen]
+    pub fn set_sustain_pedal(&mut self, down: bool) {
+        self.set_sustain(0, down);
+    }
+    
+    // Pitch bend range in semitones for every channel
+    #[wasm_bindgen]
+    pub fn set_pitch_bend_range(&mut self, semitones: f32) {
+        self.pitch_bend_range = semitones.clamp(0.0, 96.0);
+        self.update_voice_bends();
+    }
+    
+    // Load a Scala .scl scale, keeping the current keyboard mapping
+    #[wasm_bindgen]
+    pub fn load_scala_scale(&mut self, text: &str) -> Result<(), JsValue> {
+        let scale = Scale::parse_scl(text).map_err(|e| JsValue::from_str(&e))?;
+        console::log_1(&format!("Loaded scale '{}' with {} notes", scale.description, scale.len()).into());
+        self.tuning.set_scale(scale);
+        self.retune_voices();
+        Ok(())
+    }
+    
+    // Load a Scala .kbm keyboard mapping, including its reference pitch
+    #[wasm_bindgen]
+    pub fn load_keyboard_mapping(&mut self, text: &str) -> Result<(), JsValue> {
+        let mapping = KeyboardMapping::parse_kbm(text).map_err(|e| JsValue::from_str(&e))?;
+        self.tuning.set_mapping(mapping);
+        self.retune_voices();
+        Ok(())
+    }
+    
+    // Tune `note` to `frequency` Hz (e.g. 69 at 432 Hz)
+    #[wasm_bindgen]
+    pub fn set_reference_pitch(&mut self, note: u8, frequency: f32) {
+        self.tuning.set_reference_pitch(note, frequency as f64);
+        self.retune_voices();
+    }
+    
+    // Back to 12-TET with A4 = 440 Hz
+    #[wasm_bindgen]
+    pub fn reset_tuning(&mut self) {
+        self.tuning = Tuning::new();
+        self.retune_voices();
+    }
+    
+    // Frequency of a key under the current tuning, 0 if the key is unmapped
+    #[wasm_bindgen]
+    pub fn note_frequency(&self, note: u8) -> f32 {
+        self.tuning.frequency(note).unwrap_or(0.0) as f32
+    }
+    
+    // Configure an MPE zone: lower (manager channel 1) or upper (manager channel 16)
+    // with `members` member channels. 0 members removes the zone.
+    #[wasm_bindgen]
+    pub fn set_mpe_zone(&mut self, upper: bool, members: u8) {
+        if upper {
+            self.mpe.set_upper_zone(members);
+        } else {
+            self.mpe.set_lower_zone(members);
+        }
+        self.update_voice_bends();
+    }
+    
+    // Bend ranges in semitones for a zone's member channels (per note) and manager channel (whole zone)
+    #[wasm_bindgen]
+    pub fn set_mpe_bend_ranges(&mut self, upper: bool, member_range: f32, manager_range: f32) {
+        self.mpe.set_bend_ranges(upper, member_range, manager_range);
+        self.update_voice_bends();
+    }
+    
+    // How much per-note pressure controls amplitude (0 to 1), and the filter cutoff
+    // range in Hz swept by per-note timbre
+    #[wasm_bindgen]
+    pub fn set_mpe_expression(&mut self, pressure_to_amp: f32, cutoff_low: f32, cutoff_high: f32) {
+        self.mpe_pressure_to_amp = pressure_to_amp.clamp(0.0, 1.0);
+        self.mpe_timbre_cutoff = (cutoff_low, cutoff_high);
+        
+        for voice in &mut self.voices {
+            if voice.is_active() && self.mpe.member_zone(voice.channel()).is_some() {
+                voice.set_expression(self.mpe_pressure_to_amp, Some(self.mpe_timbre_cutoff));
+            }
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_envelope_parameters(&mut self, attack: f32, decay: f32, hold: f32, sustain: f32, release: f32) {
+        self.amp_envelope = (attack, decay, hold, sustain, release);
+        for voice in &mut self.voices {
+            voice.amp_envelope.set_parameters(attack, decay, hold, sustain, release);
+        }
+    }
+    
+    // Velocity sensitivity of the amplitude envelope's peak level and attack time (0 to 1)
+    #[wasm_bindgen]
+    pub fn set_envelope_velocity(&mut self, to_level: f32, to_attack: f32) {
+        for voice in &mut self.voices {
+            voice.amp_envelope.set_velocity_sensitivity(to_level, to_attack);
+        }
+    }
+    
+    // Same for the modulation envelope, which ignores velocity by default
+    #[wasm_bindgen]
+    pub fn set_mod_envelope_velocity(&mut self, to_level: f32, to_attack: f32) {
+        for voice in &mut self.voices {
+            voice.mod_envelope.set_velocity_sensitivity(to_level, to_attack);
+        }
+    }
+    
+    // 0: Reset (restart from zero), 1: Legato (restart from the current level),
+    // 2: Mono (single voice, no retrigger while keys are held)
+    #[wasm_bindgen]
+    pub fn set_envelope_retrigger_mode(&mut self, mode: u32) {
+        let mode = RetriggerMode::from_index(mode);
+        if mode != self.retrigger_mode {
+            self.all_notes_off();
+        }
+        
+        self.retrigger_mode = mode;
+        for voice in &mut self.voices {
+            voice.amp_envelope.set_retrigger_mode(mode);
+            voice.mod_envelope.set_retrigger_mode(mode);
+        }
+    }
+    
+    // Curve tension per stage, from -1 (slow start) through 0 (linear) to 1 (fast start)
+    #[wasm_bindgen]
+    pub fn set_envelope_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        for voice in &mut self.voices {
+            voice.amp_envelope.set_curves(EnvelopeCurve::Tension(attack), EnvelopeCurve::Tension(decay), EnvelopeCurve::Tension(release));
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_mod_envelope_parameters(&mut self, attack: f32, decay: f32, hold: f32, sustain: f32, release: f32) {
+        for voice in &mut self.voices {
+            voice.mod_envelope.set_parameters(attack, decay, hold, sustain, release);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_mod_envelope_curves(&mut self, attack: f32, decay: f32, release: f32) {
+        for voice in &mut self.voices {
+            voice.mod_envelope.set_curves(EnvelopeCurve::Tension(attack), EnvelopeCurve::Tension(decay), EnvelopeCurve::Tension(release));
+        }
+    }
+    
+    // Load a multi-segment envelope shape in the JSON written by `export_mseg`
+    #[wasm_bindgen]
+    pub fn set_mseg(&mut self, definition: &str) -> Result<(), JsValue> {
+        let shape: MsegShape = serde_json::from_str(definition)
+            .map_err(|error| JsValue::from_str(&format!("Invalid MSEG shape: {}", error)))?;
+        self.mseg.set_shape(&shape).map_err(|error| JsValue::from_str(&error))?;
+        self.all_notes_off();
+        for voice in &mut self.voices {
+            voice.mseg = self.mseg.clone();
+        }
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn export_mseg(&self) -> String {
+        serde_json::to_string(&self.mseg.shape()).unwrap_or_default()
+    }
+    
+    // Use the MSEG rather than the ADHSR envelope for voice amplitude
+    #[wasm_bindgen]
+    pub fn set_mseg_amplitude(&mut self, enabled: bool) {
+        for voice in &mut self.voices {
+            voice.set_mseg_amplitude(enabled);
+        }
+    }
+    
+    // 0: listen to the voices, 1: listen to the worklet's external input
+    #[wasm_bindgen]
+    pub fn set_follower_input(&mut self, input: u32) {
+        self.follower_input = FollowerInput::from_index(input);
+        self.follower.reset();
+        self.transient_detector.reset();
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_follower_parameters(&mut self, attack: f32, release: f32, use_rms: bool) {
+        let mode = if use_rms { DetectionMode::Rms } else { DetectionMode::Peak };
+        self.follower.set_parameters(attack, release, mode);
+    }
+    
+    // Jump in dB over the recent level that counts as a hit, level floor in dB,
+    // and minimum time between hits in seconds
+    #[wasm_bindgen]
+    pub fn set_transient_parameters(&mut self, threshold_db: f32, floor_db: f32, retrigger_time: f32) {
+        self.transient_detector.set_parameters(threshold_db, floor_db, retrigger_time);
+    }
+    
+    // Current follower level, e.g. for driving video effects
+    #[wasm_bindgen]
+    pub fn follower_value(&self) -> f32 {
+        self.follower.level()
+    }
+    
+    // Number of transients detected so far; poll and compare to spot new hits
+    #[wasm_bindgen]
+    pub fn transient_count(&self) -> u32 {
+        self.transient_detector.trigger_count()
+    }
+    
+    // Route a modulation source to a destination, e.g. ("lfo:0", "effect:0:cutoff", 2000.0, 0).
+    // Curves: 0: Linear, 1: Exponential, 2: Logarithmic, 3: S-Curve
+    #[wasm_bindgen]
+    pub fn set_mod_slot(&mut self, slot: usize, source: &str, destination: &str, amount: f32, curve: u32) -> bool {
+        let (source, destination) = match (ModSource::parse(source), ModDestination::parse(destination)) {
+            (Some(source), Some(destination)) => (source, destination),
+            _ => return false,
+        };
+        
+        self.mod_matrix.set_slot(slot, source, &destination, amount, ModCurve::from_index(curve), &mut self.effects)
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_mod_amount(&mut self, slot: usize, amount: f32) {
+        self.mod_matrix.set_amount(slot, amount);
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_mod_slot(&mut self, slot: usize) {
+        self.mod_matrix.clear_slot(slot, &mut self.effects);
+    }
+    
+    // MIDI controller value (0 to 1), used as a modulation source and to drive macros
+    #[wasm_bindgen]
+    pub fn set_controller(&mut self, cc: u8, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        if let Some(slot) = self.mod_sources.cc.get_mut(cc as usize) {
+            *slot = value;
+        }
+        
+        let mut target = ModulatedEffects { matrix: &mut self.mod_matrix, chains: &mut self.effects };
+        self.macros.handle_cc(cc, value, &mut target);
+    }
+    
+    // Move a macro knob (value 0 to 1)
+    #[wasm_bindgen]
+    pub fn set_macro_value(&mut self, index: usize, value: f32) {
+        let mut target = ModulatedEffects { matrix: &mut self.mod_matrix, chains: &mut self.effects };
+        self.macros.set_value(index, value, &mut target);
+    }
+    
+    #[wasm_bindgen]
+    pub fn macro_value(&self, index: usize) -> f32 {
+        self.macros.get(index).map_or(0.0, |control| control.value())
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_macro_name(&mut self, index: usize, name: &str) {
+        self.macros.set_name(index, name);
+    }
+    
+    // Map a macro onto an effect parameter over min..max (max below min inverts).
+    // Curves: 0: Linear, 1: Exponential, 2: Logarithmic, 3: S-Curve
+    #[wasm_bindgen]
+    pub fn add_macro_target(&mut self, index: usize, effect_id: usize, param: &str, min: f32, max: f32, curve: u32) -> bool {
+        if self.effects[0].get_effect_parameter(effect_id, param).is_none() {
+            return false;
+        }
+        
+        self.macros.add_target(index, MacroTarget {
+            effect_id,
+            param: param.to_string(),
+            min,
+            max,
+            curve: ModCurve::from_index(curve),
+        })
+    }
+    
+    #[wasm_bindgen]
+    pub fn remove_macro_target(&mut self, index: usize, effect_id: usize, param: &str) {
+        self.macros.remove_target(index, effect_id, param);
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_macro_targets(&mut self, index: usize) {
+        self.macros.clear_targets(index);
+    }
+    
+    // Assign a MIDI CC (0-127) to a macro; any other value unassigns it
+    #[wasm_bindgen]
+    pub fn set_macro_cc(&mut self, index: usize, cc: i32) {
+        let cc = if (0..128).contains(&cc) { Some(cc as u8) } else { None };
+        self.macros.assign_cc(index, cc);
+    }
+    
+    // Channel aftertouch as a modulation source (value 0 to 1)
+    #[wasm_bindgen]
+    pub fn set_aftertouch(&mut self, value: f32) {
+        self.mod_sources.aftertouch = value.clamp(0.0, 1.0);
+    }
+    
+    // Pitch bend for MIDI channel 1 (value -1 to 1), also a modulation source
+    #[wasm_bindgen]
+    pub fn set_pitch_bend(&mut self, value: f32) {
+        self.set_channel_pitch_bend(0, value);
+    }
+    
+    // Parse raw MIDI bytes and play them `frame_offset` frames into the next render
+    #[wasm_bindgen]
+    pub fn handle_midi(&mut self, bytes: &[u8], frame_offset: u32) {
+        let frame = self.current_frame + frame_offset as u64;
+        let queue = &mut self.midi_queue;
+        let sysex_queue = &mut self.sysex_queue;
+        
+        self.midi_parser.parse(bytes, |message| match message {
+            MidiMessage::SysEx(data) => sysex_queue.push_back(data),
+            message => {
+                // Keep the queue in frame order, preserving arrival order within a frame
+                let index = queue.iter().rposition(|&(queued, _)| queued <= frame).map_or(0, |i| i + 1);
+                queue.insert(index, (frame, message));
+            },
+        });
+    }
+    
+    // Load a Standard MIDI File to play along with the transport
+    #[wasm_bindgen]
+    pub fn load_midi_file(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let file = MidiFile::parse(bytes).map_err(|e| JsValue::from_str(&e))?;
+        self.sequencer.load(&file, self.transport_position);
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_midi_file(&mut self) {
+        self.sequencer.clear();
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_sequencer_enabled(&mut self, enabled: bool) {
+        self.sequencer.set_enabled(enabled);
+    }
+    
+    // Length of the loaded MIDI file in seconds
+    #[wasm_bindgen]
+    pub fn midi_file_duration(&self) -> f64 {
+        self.sequencer.duration()
+    }
+    
+    // Start capturing MIDI received through `handle_midi`; overdub keeps the previous take
+    #[wasm_bindgen]
+    pub fn start_recording(&mut self, overdub: bool) {
+        self.record_clock = (self.transport_position, self.current_frame);
+        self.recorder.start(overdub, self.tempo_bpm);
+    }
+    
+    #[wasm_bindgen]
+    pub fn stop_recording(&mut self) {
+        self.recorder.stop(self.record_time());
+    }
+    
+    #[wasm_bindgen]
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_recording()
+    }
+    
+    // Snap recorded note starts to a grid in beats (0.25 = sixteenths); 0 disables
+    #[wasm_bindgen]
+    pub fn set_record_quantize(&mut self, grid_beats: f32) {
+        self.recorder.set_quantize(grid_beats);
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_recording(&mut self) {
+        self.recorder.clear();
+    }
+    
+    // The recorded take as a format 1 .mid file
+    #[wasm_bindgen]
+    pub fn export_recording(&self) -> Result<Vec<u8>, JsValue> {
+        self.recorder.to_midi_file().map_err(|error| JsValue::from_str(&error))
+    }
+    
+    // Map the next controller (CC or NRPN) that arrives onto a target such as
+    // "effect:0:cutoff", "env:0:attack" or "macro:2". Returns false for unknown targets.
+    #[wasm_bindgen]
+    pub fn arm_midi_learn(&mut self, target: &str, min: f32, max: f32, curve: u32, soft_takeover: bool) -> bool {
+        match LearnTarget::parse(target).filter(|target| self.learn_target_value(target).is_some()) {
+            Some(target) => {
+                self.midi_learn.arm(target, min, max, ModCurve::from_index(curve), soft_takeover);
+                true
+            },
+            None => false,
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn cancel_midi_learn(&mut self) {
+        self.midi_learn.disarm();
+    }
+    
+    #[wasm_bindgen]
+    pub fn is_midi_learn_armed(&self) -> bool {
+        self.midi_learn.is_armed()
+    }
+    
+    // Map a controller ("cc:74", "nrpn:1024") directly, without learning
+    #[wasm_bindgen]
+    pub fn add_midi_mapping(&mut self, controller: &str, target: &str, min: f32, max: f32, curve: u32, soft_takeover: bool) -> bool {
+        let controller = match ControllerId::parse(controller) {
+            Some(controller) => controller,
+            None => return false,
+        };
+        match LearnTarget::parse(target).filter(|target| self.learn_target_value(target).is_some()) {
+            Some(target) => {
+                self.midi_learn.add_mapping(MidiMapping::new(controller, target, min, max, ModCurve::from_index(curve), soft_takeover));
+                true
+            },
+            None => false,
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn remove_midi_mapping(&mut self, target: &str) {
+        if let Some(target) = LearnTarget::parse(target) {
+            self.midi_learn.remove_mapping(&target);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_midi_mappings(&mut self) {
+        self.midi_learn.clear();
+    }
+    
+    // Every mapping as a JSON array of { controller, target, min, max, curve, soft_takeover }
+    #[wasm_bindgen]
+    pub fn export_midi_mappings(&self) -> String {
+        serde_json::to_string(self.midi_learn.mappings()).unwrap_or_default()
+    }
+    
+    // Replace every mapping with those in the JSON written by `export_midi_mappings`
+    #[wasm_bindgen]
+    pub fn import_midi_mappings(&mut self, definition: &str) -> Result<(), JsValue> {
+        let mappings: Vec<MidiMapping> = serde_json::from_str(definition)
+            .map_err(|error| JsValue::from_str(&format!("Invalid MIDI mappings: {}", error)))?;
+        mappings.iter().try_for_each(MidiMapping::validate).map_err(|error| JsValue::from_str(&error))?;
+        self.midi_learn.set_mappings(&mappings);
+        Ok(())
+    }
+    
+    // The current patch (envelopes, tuning, modulation, effects and mappings) as JSON
+    #[wasm_bindgen]
+    pub fn export_preset(&self) -> String {
+        self.to_preset().to_json()
+    }
+    
+    // The current patch in the compact binary preset format
+    #[wasm_bindgen]
+    pub fn export_preset_binary(&self) -> Vec<u8> {
+        self.to_preset().to_binary()
+    }
+    
+    // Load a preset in either format, upgrading presets saved by older versions
+    #[wasm_bindgen]
+    pub fn import_preset(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        let preset = Preset::from_bytes(data).map_err(|error| JsValue::from_str(&error))?;
+        self.apply_preset(&preset).map_err(|error| JsValue::from_str(&error))
+    }
+    
+    // The whole session as a project file. Without `embed_audio` samples are only
+    // referenced by name (see `set_sample_name` and `set_zone_sample_name`), and a
+    // sample without one fails the export.
+    #[wasm_bindgen]
+    pub fn export_project(&self, embed_audio: bool) -> Result<Vec<u8>, JsValue> {
+        let project = self.to_project(embed_audio).map_err(|error| JsValue::from_str(&error))?;
+        Ok(project.write())
+    }
+    
+    // Open a project file. `lookup(name)` returns the bytes of a referenced sample file
+    // as a Uint8Array, or null if it isn't available; the names of samples that
+    // couldn't be found are returned.
+    #[wasm_bindgen]
+    pub fn import_project(&mut self, data: &[u8], lookup: &Function) -> Result<Array, JsValue> {
+        let project = Project::read(data).map_err(|error| JsValue::from_str(&error))?;
+        let missing = self.apply_project(&project, |name| {
+            let file = lookup.call1(&JsValue::NULL, &JsValue::from_str(name)).ok()?;
+            Some(file.dyn_into::<Uint8Array>().ok()?.to_vec())
+        }).map_err(|error| JsValue::from_str(&error))?;
+        Ok(missing.iter().map(|name| JsValue::from_str(name)).collect())
+    }
+    
+    // Follow incoming MIDI clock: tempo from the clock, transport from start/stop/continue
+    // and song position pointer
+    #[wasm_bindgen]
+    pub fn set_clock_follow(&mut self, enabled: bool) {
+        self.follow_clock = enabled;
+    }
+    
+    // Tempo estimated from incoming MIDI clock, or 0 until it has locked
+    #[wasm_bindgen]
+    pub fn clock_tempo(&self) -> f32 {
+        self.clock_follower.tempo().unwrap_or(0.0)
+    }
+    
+    #[wasm_bindgen]
+    pub fn is_clock_locked(&self) -> bool {
+        self.clock_follower.is_locked()
+    }
+    
+    // Generate MIDI clock, transport and song position from our own transport
+    #[wasm_bindgen]
+    pub fn set_clock_output(&mut self, enabled: bool) {
+        self.clock_generator.set_enabled(enabled);
+        if enabled {
+            self.clock_generator.locate(self.transport_beats);
+            if self.is_playing {
+                self.clock_generator.start(self.transport_beats);
+            }
+        }
+    }
+    
+    // Number of MIDI bytes waiting for `take_midi_output`; check it first so quiet
+    // render quanta don't allocate an empty array
+    #[wasm_bindgen]
+    pub fn midi_output_len(&self) -> usize {
+        self.clock_generator.output_len()
+    }
+    
+    // MIDI bytes generated since the last call, ready to send to a MIDI output
+    #[wasm_bindgen]
+    pub fn take_midi_output(&mut self) -> Vec<u8> {
+        self.clock_generator.take_output()
+    }
+    
+    // Next SysEx message received through `handle_midi`, if any
+    #[wasm_bindgen]
+    pub fn next_sysex(&mut self) -> Option<Vec<u8>> {
+        self.sysex_queue.pop_front()
+    }
+    
+    // Jump a parameter to `value` exactly at `frame_time` (like AudioParam.setValueAtTime)
+    #[wasm_bindgen]
+    pub fn schedule_parameter(&mut self, effect_id: usize, param: &str, value: f32, frame_time: f64) {
+        self.automation.schedule(effect_id, param, value, frame_time.max(0.0) as u64, RampType::Step);
+    }
+    
+    // Ramp linearly from the previous event to reach `value` at `frame_time`
+    #[wasm_bindgen]
+    pub fn schedule_linear_ramp(&mut self, effect_id: usize, param: &str, value: f32, frame_time: f64) {
+        self.automation.schedule(effect_id, param, value, frame_time.max(0.0) as u64, RampType::Linear);
+    }
+    
+    // Ramp exponentially from the previous event to reach `value` at `frame_time`
+    #[wasm_bindgen]
+    pub fn schedule_exponential_ramp(&mut self, effect_id: usize, param: &str, value: f32, frame_time: f64) {
+        self.automation.schedule(effect_id, param, value, frame_time.max(0.0) as u64, RampType::Exponential);
+    }
+    
+    // Drop every event for a parameter scheduled at or after `frame_time`
+    #[wasm_bindgen]
+    pub fn cancel_scheduled_parameters(&mut self, effect_id: usize, param: &str, frame_time: f64) {
+        self.automation.cancel(effect_id, param, frame_time.max(0.0) as u64);
+    }
+    
+    // Align the processor's frame clock with the worklet's `currentFrame`
+    #[wasm_bindgen]
+    pub fn set_current_frame(&mut self, frame: f64) {
+        self.current_frame = frame.max(0.0) as u64;
+    }
+    
+    #[wasm_bindgen]
+    pub fn current_frame(&self) -> f64 {
+        self.current_frame as f64
+    }
+}
+
+impl SamplerProcessorState {
+    pub fn with_sample_rate(output_sample_rate: f32) -> SamplerProcessorState {
+        SamplerProcessorState {
+            sample_data: Rc::from(Vec::new()),
+            sample_rate: 44100.0,
+            sample_metadata: SampleMetadata::default(),
+            sample_name: String::new(),
+            resample_quality: ResampleQuality::Standard,
+            keymap: Keymap::new(),
+            amp_envelope: Envelope::new(output_sample_rate).parameters(),
+            zone_selection: Vec::with_capacity(MAX_VOICES),
+            output_sample_rate,
+            playback_index: 0.0,
+            is_playing: false,
+            transport_position: 0.0,
+            transport_beats: 0.0,
+            loop_region: LoopRegion::default(),
+            markers: Vec::new(),
+            video: None,
+            effects: (0..OUTPUT_CHANNELS).map(|_| build_effects_chain(output_sample_rate)).collect(),
+            automation: AutomationQueue::new(),
+            current_frame: 0,
+            voices: (0..MAX_VOICES).map(|_| Voice::new(output_sample_rate)).collect(),
+            note_counter: 0,
+            last_voice: None,
+            retrigger_mode: RetriggerMode::Legato,
+            held_notes: Vec::with_capacity(128),
+            sustain_pedal: [false; 16],
+            channel_bend: [0.0; 16],
+            pitch_bend_range: 2.0,
+            tuning: Tuning::new(),
+            
+            mpe: MpeConfig::new(),
+            channel_pressure: [0.0; 16],
+            channel_timbre: [0.5; 16],
+            mpe_pressure_to_amp: 0.5,
+            mpe_timbre_cutoff: (200.0, 20000.0),
+            rng: XorShiftRng::new(0x5EED),
+            lfos: (0..NUM_LFOS).map(|i| Lfo::with_seed(output_sample_rate, i as u32 + 1)).collect(),
+            tempo_bpm: 120.0,
+            mod_sources: GlobalModSources::new(),
+            mod_matrix: ModMatrix::new(),
+            frames_until_modulation: 0,
+            macros: MacroBank::new(),
+            mseg: Mseg::new(output_sample_rate),
+            follower: EnvelopeFollower::new(output_sample_rate),
+            transient_detector: TransientDetector::new(output_sample_rate),
+            follower_input: FollowerInput::Voices,
+            analysis_buffer: Vec::with_capacity(MODULATION_BLOCK_SIZE),
+            midi_parser: MidiParser::new(),
+            midi_queue: VecDeque::new(),
+            sysex_queue: VecDeque::new(),
+            sequencer: Sequencer::new(),
+            recorder: Recorder::new(),
+            record_clock: (0.0, 0),
+            midi_learn: MidiLearn::new(),
+            clock_follower: ClockFollower::new(output_sample_rate),
+            follow_clock: false,
+            clock_generator: ClockGenerator::new(),
+            
+            left_buffer: Vec::new(),
+            right_buffer: Vec::new(),
+            input_buffer: Vec::new(),
+            input_frames: 0,
+        }
+    }
+    
+    pub fn load_sample(&mut self, data: Vec<f32>, sample_rate: f32) {
+        self.all_notes_off();
+        let (data, sample_rate) = if sample_rate > 0.0 && sample_rate != self.output_sample_rate {
+            (resample::resample(&data, sample_rate, self.output_sample_rate, self.resample_quality), self.output_sample_rate)
+        } else {
+            (data, sample_rate)
+        };
+        self.sample_data = Rc::from(data);
+        self.sample_rate = sample_rate;
+        self.sample_metadata = SampleMetadata::default();
+        self.sample_name.clear();
+        self.keymap = Keymap::single(self.sample_data.clone(), sample_rate);
+    }
+    
+    // Load decoded audio and its metadata. The first channel becomes the sample, and
+    // the file's root key, tuning and first loop carry over to its zone.
+    pub fn load_audio(&mut self, mut audio: DecodedAudio) {
+        let data = if audio.channels.is_empty() { Vec::new() } else { audio.channels.swap_remove(0) };
+        self.load_sample(data, audio.sample_rate);
+        
+        // Positions follow the sample through any rate conversion
+        let mut metadata = std::mem::take(&mut audio.metadata);
+        if audio.sample_rate > 0.0 {
+            metadata.scale_positions(self.sample_rate as f64 / audio.sample_rate as f64);
+        }
+        if let Some(zone) = self.keymap.zone_mut(0) {
+            zone.root_key = metadata.root_key.unwrap_or(zone.root_key);
+            zone.tune = metadata.fine_tune;
+            // Zones only loop forwards, so a ping-pong loop plays as a forward one;
+            // `sample_loops` still reports it as ping-pong
+            if let Some(sample_loop) = metadata.loops.first() {
+                zone.loop_mode = LoopMode::Continuous;
+                zone.loop_start = sample_loop.start;
+                zone.loop_end = sample_loop.end;
+            }
+        }
+        self.sample_metadata = metadata;
+    }
+    
+    // A new processor with this one's patch, instrument, tuning, markers and transport
+    // position, but none of its voices, queued MIDI, automation, MIDI file or effect
+    // tails, for bounces that mustn't disturb live playback
+    pub fn offline_copy(&self) -> SamplerProcessorState {
+        let mut copy = SamplerProcessorState::with_sample_rate(self.output_sample_rate);
+        // A preset taken from a running processor always applies
+        let _ = copy.apply_preset(&self.to_preset());
+        
+        copy.sample_data = self.sample_data.clone();
+        copy.sample_rate = self.sample_rate;
+        copy.sample_metadata = self.sample_metadata.clone();
+        copy.sample_name = self.sample_name.clone();
+        copy.keymap = self.keymap.clone();
+        copy.markers = self.markers.clone();
+        copy.video = self.video.clone();
+        copy.loop_region = self.loop_region.clone();
+        copy.seek(self.transport_position);
+        copy.is_playing = self.is_playing;
+        copy
+    }
+    
+    // Render faster than real time from the current state. `events` are raw MIDI
+    // messages at frame offsets from the start of the render. Without a frame count the
+    // render runs past the last event until voices, sample playback and effect tails
+    // fall silent.
+    pub fn render_offline(&mut self, frames: Option<usize>, events: &[(u64, Vec<u8>)]) -> [Vec<f32>; 2] {
+        self.all_notes_off();
+        self.midi_queue.clear();
+        for (offset, bytes) in events {
+            self.handle_midi(bytes, *offset as u32);
+        }
+        
+        let last_event = events.iter().map(|&(offset, _)| offset as usize + 1).max().unwrap_or(0);
+        let tail = (OFFLINE_MAX_TAIL_SECONDS * self.output_sample_rate) as usize;
+        let limit = frames.unwrap_or(last_event + tail);
+        let silence = utils::db_to_linear(OFFLINE_SILENCE_DB);
+        
+        let mut output = [Vec::with_capacity(frames.unwrap_or(last_event)), Vec::with_capacity(frames.unwrap_or(last_event))];
+        while output[0].len() < limit {
+            let block = OFFLINE_BLOCK_SIZE.min(limit - output[0].len());
+            self.render(block);
+            output[0].extend_from_slice(&self.left_buffer[..block]);
+            output[1].extend_from_slice(&self.right_buffer[..block]);
+            
+            let sounding = self.voices.iter().any(Voice::is_active)
+                || (self.is_playing && (self.playback_index as usize) < self.sample_data.len())
+                || utils::calculate_peak(&self.left_buffer[..block]).max(utils::calculate_peak(&self.right_buffer[..block])) >= silence;
+            if frames.is_none() && output[0].len() >= last_event && !sounding {
+                break;
+            }
+        }
+        
+        // Leave nothing from the bounce sounding, and drop the clock it generated
+        self.all_notes_off();
+        self.clock_generator.take_output();
+        output
+    }
+    
+    // Replace the instrument with a multisample keymap
+    pub fn load_keymap(&mut self, mut keymap: Keymap) {
+        self.all_notes_off();
+        keymap.resample(self.output_sample_rate, self.resample_quality);
+        self.keymap = keymap;
+    }
+    
+    // Snapshot of the patch: envelopes, tuning, modulation, effects and mappings.
+    // Voices all share the same settings, so the first one speaks for them.
+    pub fn to_preset(&self) -> Preset {
+        let envelope_settings = |envelope: &Envelope, (attack, decay, hold, sustain, release): (f32, f32, f32, f32, f32)| {
+            let (attack_curve, decay_curve, release_curve) = envelope.curves();
+            let (velocity_to_level, velocity_to_attack) = envelope.velocity_sensitivity();
+            EnvelopeSettings {
+                attack, decay, hold, sustain, release,
+                attack_curve, decay_curve, release_curve,
+                velocity_to_level, velocity_to_attack,
+            }
+        };
+        let zone_settings = |upper: bool| self.mpe.zone(upper).map(|zone| MpeZoneSettings {
+            members: zone.members,
+            member_bend_range: zone.member_bend_range,
+            manager_bend_range: zone.manager_bend_range,
+        });
+        let voice = &self.voices[0];
+        let chain = &self.effects[0];
+        let (follower_attack, follower_release, follower_mode) = self.follower.parameters();
+        let (threshold_db, floor_db, retrigger_time) = self.transient_detector.parameters();
+        
+        Preset {
+            version: PRESET_VERSION,
+            amp_envelope: envelope_settings(&voice.amp_envelope, self.amp_envelope),
+            mod_envelope: envelope_settings(&voice.mod_envelope, voice.mod_envelope.parameters()),
+            retrigger_mode: self.retrigger_mode.index(),
+            mseg: self.mseg.shape(),
+            mseg_amplitude: voice.mseg_amplitude(),
+            pitch_bend_range: self.pitch_bend_range,
+            tempo: self.tempo_bpm,
+            scale: self.tuning.scale().clone(),
+            keyboard_mapping: self.tuning.mapping().clone(),
+            mpe: MpeSettings {
+                lower_zone: zone_settings(false),
+                upper_zone: zone_settings(true),
+                pressure_to_amp: self.mpe_pressure_to_amp,
+                timbre_cutoff_low: self.mpe_timbre_cutoff.0,
+                timbre_cutoff_high: self.mpe_timbre_cutoff.1,
+            },
+            lfos: self.lfos.iter().map(|lfo| LfoSettings {
+                shape: lfo.shape().index(),
+                rate: lfo.rate(),
+                sync_beats: lfo.sync(),
+                phase_offset: lfo.phase_offset(),
+                retrigger: lfo.retrigger(),
+                fade_in: lfo.fade_in(),
+            }).collect(),
+            effects: (0..chain.count()).map(|effect_id| EffectSettings {
+                name: chain.effect_name(effect_id).unwrap_or_default().to_string(),
+                parameters: chain.effect_parameter_names(effect_id).iter()
+                    .filter_map(|&param| {
+                        let value = self.mod_matrix.base(effect_id, param).or_else(|| chain.get_effect_parameter(effect_id, param))?;
+                        Some((param.to_string(), value))
+                    })
+                    .collect(),
+            }).collect(),
+            mod_slots: (0..NUM_MOD_SLOTS).filter_map(|slot| {
+                let (source, destination, amount, curve) = self.mod_matrix.slot(slot)?;
+                Some(ModSlotSettings { slot, source: source.id(), destination: destination.id(), amount, curve: curve.index() })
+            }).collect(),
+            macros: (0..NUM_MACROS).filter_map(|index| self.macros.get(index)).map(|control| MacroSettings {
+                name: control.name().to_string(),
+                value: control.value(),
+                cc: control.cc(),
+                targets: control.targets().iter().map(|target| MacroTargetSettings {
+                    effect_id: target.effect_id,
+                    param: target.param.clone(),
+                    min: target.min,
+                    max: target.max,
+                    curve: target.curve.index(),
+                }).collect(),
+            }).collect(),
+            midi_mappings: self.midi_learn.mappings().to_vec(),
+            follower: FollowerSettings {
+                input: self.follower_input.index(),
+                attack: follower_attack,
+                release: follower_release,
+                rms: follower_mode == DetectionMode::Rms,
+                transient_threshold_db: threshold_db,
+                transient_floor_db: floor_db,
+                transient_retrigger_time: retrigger_time,
+            },
+            resample_quality: self.resample_quality.index(),
+        }
+    }
+    
+    // Restore a patch written by `to_preset`. The preset is checked before anything
+    // changes, so a bad one leaves the current patch alone.
+    pub fn apply_preset(&mut self, preset: &Preset) -> Result<(), String> {
+        let mut mseg = self.mseg.clone();
+        mseg.set_shape(&preset.mseg)?;
+        preset.midi_mappings.iter().try_for_each(MidiMapping::validate)?;
+        preset.scale.validate()?;
+        preset.keyboard_mapping.validate()?;
+        
+        let chain = &self.effects[0];
+        let has_parameter = |effect_id: usize, param: &str| chain.get_effect_parameter(effect_id, param).is_some();
+        for slot in &preset.mod_slots {
+            if slot.slot >= NUM_MOD_SLOTS || ModSource::parse(&slot.source).is_none() {
+                return Err(format!("Preset has an invalid modulation slot {}", slot.slot));
+            }
+            match ModDestination::parse(&slot.destination) {
+                Some(ModDestination::Effect { effect_id, param }) if !has_parameter(effect_id, &param) => {
+                    return Err(format!("Preset modulates unknown parameter '{}'", slot.destination));
+                },
+                None => return Err(format!("Preset modulates unknown destination '{}'", slot.destination)),
+                _ => {},
+            }
+        }
+        if preset.macros.len() > NUM_MACROS {
+            return Err(format!("Preset has {} macros, more than {}", preset.macros.len(), NUM_MACROS));
+        }
+        for target in preset.macros.iter().flat_map(|settings| &settings.targets) {
+            if !has_parameter(target.effect_id, &target.param) {
+                return Err(format!("Preset macro targets unknown parameter '{}'", target.param));
+            }
+        }
+        
+        self.all_notes_off();
+        
+        let amp = &preset.amp_envelope;
+        self.set_envelope_parameters(amp.attack, amp.decay, amp.hold, amp.sustain, amp.release);
+        self.set_envelope_curves(amp.attack_curve, amp.decay_curve, amp.release_curve);
+        self.set_envelope_velocity(amp.velocity_to_level, amp.velocity_to_attack);
+        let modulation = &preset.mod_envelope;
+        self.set_mod_envelope_parameters(modulation.attack, modulation.decay, modulation.hold, modulation.sustain, modulation.release);
+        self.set_mod_envelope_curves(modulation.attack_curve, modulation.decay_curve, modulation.release_curve);
+        self.set_mod_envelope_velocity(modulation.velocity_to_level, modulation.velocity_to_attack);
+        self.set_envelope_retrigger_mode(preset.retrigger_mode);
+        
+        self.mseg = mseg;
+        for voice in &mut self.voices {
+            voice.mseg = self.mseg.clone();
+        }
+        self.set_mseg_amplitude(preset.mseg_amplitude);
+        self.set_tempo(preset.tempo);
+        
+        self.tuning.set_scale(preset.scale.clone());
+        self.tuning.set_mapping(preset.keyboard_mapping.clone());
+        self.pitch_bend_range = preset.pitch_bend_range.clamp(0.0, 96.0);
+        for (upper, zone) in [(false, &preset.mpe.lower_zone), (true, &preset.mpe.upper_zone)] {
+            let members = zone.as_ref().map_or(0, |zone| zone.members);
+            if upper {
+                self.mpe.set_upper_zone(members);
+            } else {
+                self.mpe.set_lower_zone(members);
+            }
+            if let Some(zone) = zone {
+                self.mpe.set_bend_ranges(upper, zone.member_bend_range, zone.manager_bend_range);
+            }
+        }
+        self.update_voice_bends();
+        self.set_mpe_expression(preset.mpe.pressure_to_amp, preset.mpe.timbre_cutoff_low, preset.mpe.timbre_cutoff_high);
+        
+        for (lfo, settings) in self.lfos.iter_mut().zip(&preset.lfos) {
+            lfo.set_shape(LfoShape::from_index(settings.shape));
+            lfo.set_rate(settings.rate);
+            lfo.set_sync(settings.sync_beats.unwrap_or(0.0));
+            lfo.set_phase_offset(settings.phase_offset);
+            lfo.set_retrigger(settings.retrigger);
+            lfo.set_fade_in(settings.fade_in);
+        }
+        
+        // Effect values go in before the routing, which captures them as modulation bases.
+        // The old routing lets go of its destinations first so none keeps a stale offset.
+        self.mod_matrix.clear(&mut self.effects);
+        for (effect_id, settings) in preset.effects.iter().enumerate() {
+            if self.effects[0].effect_name(effect_id) != Some(settings.name.as_str()) {
+                continue;
+            }
+            for (param, &value) in &settings.parameters {
+                for chain in &mut self.effects {
+                    chain.set_effect_parameter(effect_id, param, value);
+                }
+            }
+        }
+        for slot in &preset.mod_slots {
+            self.set_mod_slot(slot.slot, &slot.source, &slot.destination, slot.amount, slot.curve);
+        }
+        
+        for (index, settings) in preset.macros.iter().enumerate() {
+            self.set_macro_name(index, &settings.name);
+            self.macros.assign_cc(index, settings.cc.filter(|&cc| cc < 128));
+            self.clear_macro_targets(index);
+            for target in &settings.targets {
+                self.add_macro_target(index, target.effect_id, &target.param, target.min, target.max, target.curve);
+            }
+            self.set_macro_value(index, settings.value);
+        }
+        self.midi_learn.set_mappings(&preset.midi_mappings);
+        
+        self.set_follower_input(preset.follower.input);
+        self.set_follower_parameters(preset.follower.attack, preset.follower.release, preset.follower.rms);
+        self.set_transient_parameters(
+            preset.follower.transient_threshold_db,
+            preset.follower.transient_floor_db,
+            preset.follower.transient_retrigger_time,
+        );
+        self.set_resample_quality(preset.resample_quality);
+        Ok(())
+    }
+    
+    // Snapshot of the session: the patch, the transport's sample, every keymap zone and
+    // the samples they play, timeline markers, the video reference, loop region and
+    // transport position. Without `embed_audio` every sample needs a file name to be
+    // referenced by.
+    pub fn to_project(&self, embed_audio: bool) -> Result<Project, String> {
+        let mut buffers: Vec<Rc<[f32]>> = Vec::new();
+        let mut samples = Vec::new();
+        let mut audio = Vec::new();
+        let mut add_sample = |data: &Rc<[f32]>, sample_rate: f32, name: &str, metadata: &SampleMetadata| {
+            if let Some(index) = buffers.iter().position(|buffer| Rc::ptr_eq(buffer, data)) {
+                return Ok(index);
+            }
+            if !embed_audio && name.is_empty() {
+                return Err(format!("Sample {} has no file name to reference; export it with embedded audio", buffers.len() + 1));
+            }
+            if embed_audio {
+                audio.push(audio_file::wav::encode(&[data], sample_rate.round() as u32, WavEncoding::Float32, false));
+            }
+            buffers.push(data.clone());
+            samples.push(ProjectSample {
+                name: name.to_string(),
+                embedded: embed_audio,
+                sample_rate,
+                metadata: metadata.clone(),
+            });
+            Ok(buffers.len() - 1)
+        };
+        
+        // The transport's sample goes first, so zones sharing it pick up its name and metadata
+        let transport_sample = if self.sample_data.is_empty() {
+            None
+        } else {
+            Some(add_sample(&self.sample_data, self.sample_rate, &self.sample_name, &self.sample_metadata)?)
+        };
+        let mut zones = Vec::with_capacity(self.keymap.zones().len());
+        for zone in self.keymap.zones() {
+            let sample = add_sample(&zone.sample, zone.sample_rate, &zone.sample_name, &SampleMetadata::default())?;
+            zones.push(ProjectZone::new(zone, sample));
+        }
+        
+        Ok(Project {
+            manifest: ProjectManifest {
+                version: PROJECT_VERSION,
+                samples,
+                transport_sample,
+                zones,
+                alternation_groups: self.keymap.groups().iter().map(ProjectAlternationGroup::new).collect(),
+                markers: self.markers.clone(),
+                video: self.video.clone(),
+                loop_region: self.loop_region.clone(),
+                transport_position: self.transport_position,
+            },
+            preset: self.to_preset(),
+            audio,
+        })
+    }
+    
+    // Restore a session written by `to_project`. `lookup(name)` returns the file of a
+    // sample the project only references; the names of samples that couldn't be
+    // found are returned, and the zones playing them are left empty. Samples are
+    // decoded before anything changes, so a bad file leaves the session alone.
+    pub fn apply_project<F>(&mut self, project: &Project, mut lookup: F) -> Result<Vec<String>, String>
+    where
+        F: FnMut(&str) -> Option<Vec<u8>>,
+    {
+        let manifest = &project.manifest;
+        let mut embedded = project.audio.iter();
+        let mut missing = Vec::new();
+        
+        // Each sample as its data, the file's rate, and the ratio of the file's rate to
+        // the one positions were saved at
+        let mut buffers: Vec<(Rc<[f32]>, f32, f64)> = Vec::new();
+        for sample in &manifest.samples {
+            let file = if sample.embedded {
+                Some(embedded.next().ok_or_else(|| "Project file is missing embedded audio".to_string())?.clone())
+            } else {
+                lookup(&sample.name)
+            };
+            match file.map(|file| audio_file::decode(&file)).transpose()? {
+                Some(mut audio) => {
+                    let data = if audio.channels.is_empty() { Vec::new() } else { audio.channels.swap_remove(0) };
+                    let saved_rate = if sample.sample_rate > 0.0 { sample.sample_rate } else { audio.sample_rate };
+                    buffers.push((Rc::from(data), audio.sample_rate, audio.sample_rate as f64 / saved_rate as f64));
+                },
+                None => {
+                    missing.push(sample.name.clone());
+                    buffers.push((Rc::from(Vec::new()), sample.sample_rate, 1.0));
+                },
+            }
+        }
+        
+        self.apply_preset(&project.preset)?;
+        if self.is_playing {
+            self.set_playback_state(false);
+        }
+        
+        match manifest.transport_sample {
+            Some(index) => {
+                let (data, sample_rate, ratio) = &buffers[index];
+                self.load_sample(data.to_vec(), *sample_rate);
+                
+                // Metadata positions follow the sample through any rate conversion
+                let mut metadata = manifest.samples[index].metadata.clone();
+                if *sample_rate > 0.0 {
+                    metadata.scale_positions(ratio * self.sample_rate as f64 / *sample_rate as f64);
+                }
+                self.sample_metadata = metadata;
+                self.sample_name = manifest.samples[index].name.clone();
+            },
+            None => self.load_sample(Vec::new(), self.output_sample_rate),
+        }
+        
+        let mut keymap = Keymap::new();
+        for group in &manifest.alternation_groups {
+            keymap.add_group(group.to_group());
+        }
+        for settings in &manifest.zones {
+            let (data, sample_rate, ratio) = &buffers[settings.sample];
+            let mut zone = settings.to_zone(data.clone(), *sample_rate, *ratio);
+            zone.sample_name = manifest.samples[settings.sample].name.clone();
+            keymap.add_zone(zone);
+        }
+        self.load_keymap(keymap);
+        
+        // Zones of the transport's sample share its buffer again, as they did when saved
+        if let Some(index) = manifest.transport_sample {
+            for (zone_index, settings) in manifest.zones.iter().enumerate() {
+                let shared = self.sample_data.clone();
+                if let Some(zone) = self.keymap.zone_mut(zone_index).filter(|_| settings.sample == index) {
+                    if zone.sample.len() == shared.len() {
+                        zone.sample = shared;
+                    }
+                }
+            }
+        }
+        
+        self.markers = manifest.markers.clone();
+        self.video = manifest.video.clone();
+        self.loop_region = manifest.loop_region.clone();
+        self.seek(manifest.transport_position);
+        Ok(missing)
+    }
+    
+    // Render `frames` frames into the internal stereo buffers
+    pub fn render(&mut self, frames: usize) {
+        if self.left_buffer.len() < frames {
+            self.left_buffer.resize(frames, 0.0);
+            self.right_buffer.resize(frames, 0.0);
+        }
+        
+        let has_automation = self.automation.has_pending();
+        let frame_seconds = 1.0 / self.output_sample_rate as f64;
+        
+        for i in 0..frames {
+            // MIDI file events falling inside this frame
+            let until = if self.is_playing { self.transport_position + frame_seconds } else { f64::NEG_INFINITY };
+            while let Some(message) = self.sequencer.next_due(until) {
+                self.dispatch_midi(message);
+            }
+            
+            while self.midi_queue.front().is_some_and(|&(frame, _)| frame <= self.current_frame) {
+                if let Some((_, message)) = self.midi_queue.pop_front() {
+                    self.recorder.record(self.record_time(), &message);
+                    self.dispatch_midi(message);
+                }
+            }
+            
+            if self.is_playing {
+                self.transport_position += frame_seconds;
+                self.transport_beats += frame_seconds * self.tempo_bpm as f64 / 60.0;
+                
+                // Jump back to the loop start, taking the sample and MIDI file with it
+                let region = &self.loop_region;
+                if region.enabled && region.end > region.start && self.transport_position >= region.end {
+                    self.seek(region.start);
+                }
+                self.clock_generator.advance(self.transport_beats);
+            }
+            
+            if has_automation {
+                let mut target = ModulatedEffects { matrix: &mut self.mod_matrix, chains: &mut self.effects };
+                self.automation.apply(self.current_frame, &mut target);
+            }
+            
+            for (value, lfo) in self.mod_sources.lfos.iter_mut().zip(self.lfos.iter_mut()) {
+                *value = lfo.process();
+            }
+            
+            if self.frames_until_modulation == 0 {
+                self.resolve_modulation();
+                self.frames_until_modulation = MODULATION_BLOCK_SIZE;
+            }
+            self.frames_until_modulation -= 1;
+            
+            let playback = self.next_sample();
+            let mut left = playback;
+            let mut right = playback;
+            for voice in &mut self.voices {
+                let (voice_left, voice_right) = voice.process(self.keymap.sample(voice.zone()));
+                left += voice_left;
+                right += voice_right;
+            }
+            
+            let analysed = match self.follower_input {
+                FollowerInput::Voices => 0.5 * (left + right),
+                FollowerInput::External if i < self.input_frames => self.input_buffer[i],
+                FollowerInput::External => 0.0,
+            };
+            if self.analysis_buffer.len() < MODULATION_BLOCK_SIZE {
+                self.analysis_buffer.push(analysed);
+            }
+            
+            self.left_buffer[i] = self.effects[0].process(left);
+            self.right_buffer[i] = self.effects[1].process(right);
+            
+            self.current_frame += 1;
+        }
+        
+        self.input_frames = 0;
+        self.clock_follower.check_timeout(self.current_frame);
+    }
+    
+    // Convert loaded samples to a new output rate. The single-sample keymap shares its
+    // buffer with `sample_data`, so it is converted once and shared again.
+    fn conform_sample_rate(&mut self) {
+        self.all_notes_off();
+        let shared = self.keymap.zones().len() == 1
+            && self.keymap.zone(0).is_some_and(|zone| Rc::ptr_eq(&zone.sample, &self.sample_data));
+        self.keymap.resample(self.output_sample_rate, self.resample_quality);
+        
+        if self.sample_data.is_empty() || self.sample_rate == self.output_sample_rate {
+            return;
+        }
+        self.sample_metadata.scale_positions(self.output_sample_rate as f64 / self.sample_rate as f64);
+        self.sample_data = match self.keymap.zone(0) {
+            Some(zone) if shared => zone.sample.clone(),
+            _ => Rc::from(resample::resample(&self.sample_data, self.sample_rate, self.output_sample_rate, self.resample_quality)),
+        };
+        self.playback_index *= self.output_sample_rate as f64 / self.sample_rate as f64;
+        self.sample_rate = self.output_sample_rate;
+    }
+    
+    // Start a voice for a note on a MIDI channel (0-15, velocity 0 to 1)
+    pub fn channel_note_on(&mut self, channel: u8, note: u8, velocity: f32) {
+        if velocity <= 0.0 {
+            self.channel_note_off(channel, note);
+            return;
+        }
+        
+        let channel = channel & 0x0F;
+        let note = note.min(127);
+        
+        // Keys the tuning leaves unmapped are silent
+        let Some(key_pitch) = self.tuning.semitones(note) else {
+            return;
+        };
+        
+        // Zones under this key and velocity, one voice each
+        let mut selection = std::mem::take(&mut self.zone_selection);
+        selection.clear();
+        let velocity_key = (velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+        self.keymap.select(note, velocity_key, &mut self.rng, &mut selection);
+        if selection.is_empty() {
+            self.zone_selection = selection;
+            return;
+        }
+        
+        self.note_counter += 1;
+        let random = self.rng.next_bipolar();
+        let bend = self.bend_semitones(channel);
+        
+        if self.retrigger_mode == RetriggerMode::Mono {
+            self.held_notes.retain(|&held| held != note);
+            self.held_notes.push(note);
+            
+            // Mono plays only the first layer
+            selection.truncate(1);
+        }
+        
+        let mut index = 0;
+        for &(zone_index, gain) in &selection {
+            // Mono plays every note on the first voice; other modes reuse a voice
+            // already playing the same note and zone so its envelope can retrigger
+            let existing = match self.retrigger_mode {
+                RetriggerMode::Mono => Some(0),
+                _ => self.voices.iter().position(|voice| {
+                    voice.is_active() && voice.channel() == channel && voice.note() == note && voice.zone() == zone_index
+                }),
+            };
+            
+            index = existing.unwrap_or_else(|| self.allocate_voice());
+            let Some(zone) = self.keymap.zone(zone_index) else {
+                continue;
+            };
+            let voice = &mut self.voices[index];
+            
+            // Zones may bring their own amplitude envelope
+            let (attack, decay, hold, sustain, release) = zone.amp_envelope.unwrap_or(self.amp_envelope);
+            voice.amp_envelope.set_parameters(attack, decay, hold, sustain, release);
+            
+            let restarted = if voice.is_active() {
+                voice.retrigger(channel, note, velocity, random, self.note_counter)
+            } else {
+                voice.start(channel, note, velocity, random, self.note_counter);
+                true
+            };
+            voice.set_zone(zone_index, zone, gain);
+            voice.set_key_pitch(key_pitch);
+            voice.set_pitch_bend(bend);
+            
+            // Notes on MPE member channels pick up that channel's latest pressure and timbre
+            if self.mpe.member_zone(channel).is_some() {
+                voice.set_expression(self.mpe_pressure_to_amp, Some(self.mpe_timbre_cutoff));
+                voice.set_pressure(self.channel_pressure[channel as usize]);
+                voice.set_timbre(self.channel_timbre[channel as usize]);
+            } else {
+                voice.set_expression(0.0, None);
+            }
+            
+            let modulation = self.mod_matrix.resolve_voice(&self.mod_sources, &voice.mod_sources());
+            voice.apply_modulation(modulation, self.output_sample_rate);
+            if restarted {
+                voice.apply_sample_start(zone.sample.len());
+            }
+        }
+        self.zone_selection = selection;
+        
+        self.last_voice = Some(index);
+        for lfo in &mut self.lfos {
+            lfo.trigger();
+        }
+    }
+    
+    // Release a note on a MIDI channel, or hold it if the channel's sustain pedal is down
+    pub fn channel_note_off(&mut self, channel: u8, note: u8) {
+        let channel = channel & 0x0F;
+        let sustain = self.sustain_down(channel);
+        
+        if self.retrigger_mode == RetriggerMode::Mono {
+            self.held_notes.retain(|&held| held != note);
+            
+            // Fall back to the most recent key still held without retriggering, passing
+            // over keys the tuning leaves unmapped
+            let previous = self.held_notes.iter().rev()
+                .find_map(|&held| Some((held, self.tuning.semitones(held)?)));
+            let voice = &mut self.voices[0];
+            if voice.is_active() && voice.note() == note {
+                match previous {
+                    Some((previous, semitones)) => voice.set_note(previous, semitones),
+                    None if sustain => voice.set_sustained(true),
+                    None => voice.release(),
+                }
+            }
+            return;
+        }
+        
+        for voice in &mut self.voices {
+            if voice.is_active() && voice.channel() == channel && voice.note() == note && !voice.is_releasing() {
+                if sustain {
+                    voice.set_sustained(true);
+                } else {
+                    voice.release();
+                }
+            }
+        }
+    }
+    
+    // Sustain pedal: while down, note-offs on the channel are deferred until it comes up
+    pub fn set_sustain(&mut self, channel: u8, down: bool) {
+        self.sustain_pedal[(channel & 0x0F) as usize] = down;
+        if down {
+            return;
+        }
+        
+        for index in 0..self.voices.len() {
+            let voice = &self.voices[index];
+            if voice.is_sustained() && !self.sustain_down(voice.channel()) {
+                self.voices[index].release();
+            }
+        }
+    }
+    
+    // Whether notes on a channel are held by a sustain pedal, its own or its MPE zone's manager
+    fn sustain_down(&self, channel: u8) -> bool {
+        self.sustain_pedal[channel as usize]
+            || self.mpe.member_zone(channel).is_some_and(|zone| self.sustain_pedal[zone.manager as usize])
+    }
+    
+    // Bend in semitones for notes on a channel, including the MPE zone-wide bend
+    fn bend_semitones(&self, channel: u8) -> f32 {
+        let bend = self.channel_bend[channel as usize];
+        match self.mpe.member_zone(channel) {
+            Some(zone) => bend * zone.member_bend_range + self.channel_bend[zone.manager as usize] * zone.manager_bend_range,
+            None => bend * self.pitch_bend_range,
+        }
+    }
+    
+    // Pitch bend for one channel (-1 to 1), scaled by the bend range
+    pub fn set_channel_pitch_bend(&mut self, channel: u8, value: f32) {
+        let value = value.clamp(-1.0, 1.0);
+        self.channel_bend[(channel & 0x0F) as usize] = value;
+        
+        // Per-note bends on MPE member channels are not a global modulation source
+        if self.mpe.member_zone(channel & 0x0F).is_none() {
+            self.mod_sources.pitch_bend = value;
+        }
+        self.update_voice_bends();
+    }
+    
+    fn update_voice_bends(&mut self) {
+        let bends: [f32; 16] = std::array::from_fn(|channel| self.bend_semitones(channel as u8));
+        for voice in &mut self.voices {
+            if voice.is_active() {
+                voice.set_pitch_bend(bends[voice.channel() as usize]);
+            }
+        }
+    }
+    
+    fn dispatch_midi(&mut self, message: MidiMessage) {
+        match message {
+            MidiMessage::NoteOn { channel, note, velocity } => self.channel_note_on(channel, note, velocity as f32 / 127.0),
+            MidiMessage::NoteOff { channel, note, .. } => self.channel_note_off(channel, note),
+            MidiMessage::ControlChange { controller: midi::CC_ALL_SOUND_OFF, .. } => self.all_notes_off(),
+            MidiMessage::ControlChange { channel, controller: midi::CC_ALL_NOTES_OFF, .. } => {
+                // Channel mode message: only the notes played on this channel end, so an
+                // MPE member channel leaves the rest of the zone sounding
+                if self.retrigger_mode == RetriggerMode::Mono && self.voices[0].channel() == channel {
+                    self.held_notes.clear();
+                }
+                for voice in &mut self.voices {
+                    if voice.channel() == channel {
+                        voice.release();
+                    }
+                }
+            },
+            MidiMessage::ControlChange { channel, controller: midi::CC_SUSTAIN, value } => {
+                // The pedal still holds notes, and can also be learned like any other controller
+                self.set_sustain(channel, value >= 64);
+                self.apply_midi_mappings(channel, midi::CC_SUSTAIN, value);
+                self.set_controller(midi::CC_SUSTAIN, value as f32 / 127.0);
+            },
+            MidiMessage::ControlChange { channel, controller: mpe::CC_TIMBRE, value } if self.mpe.member_zone(channel).is_some() => {
+                let timbre = value as f32 / 127.0;
+                self.channel_timbre[channel as usize] = timbre;
+                for voice in &mut self.voices {
+                    if voice.is_active() && voice.channel() == channel {
+                        voice.set_timbre(timbre);
+                    }
+                }
+            },
+            MidiMessage::ControlChange { channel, controller, value } => {
+                self.apply_midi_mappings(channel, controller, value);
+                self.set_controller(controller, value as f32 / 127.0);
+            },
+            MidiMessage::ChannelAftertouch { channel, pressure } if self.mpe.member_zone(channel).is_some() => {
+                let pressure = pressure as f32 / 127.0;
+                self.channel_pressure[channel as usize] = pressure;
+                for voice in &mut self.voices {
+                    if voice.is_active() && voice.channel() == channel {
+                        voice.set_pressure(pressure);
+                    }
+                }
+            },
+            MidiMessage::ChannelAftertouch { pressure, .. } => self.set_aftertouch(pressure as f32 / 127.0),
+            MidiMessage::PolyAftertouch { channel, note, pressure } => {
+                for voice in &mut self.voices {
+                    if voice.is_active() && voice.channel() == channel && voice.note() == note {
+                        voice.set_pressure(pressure as f32 / 127.0);
+                    }
+                }
+            },
+            MidiMessage::PitchBend { channel, value } => {
+                let center = midi::PITCH_BEND_CENTER as f32;
+                self.set_channel_pitch_bend(channel, (value as f32 - center) / center);
+            },
+            MidiMessage::Clock => {
+                self.clock_follower.tick(self.current_frame);
+                if let Some(tempo) = self.clock_follower.tempo().filter(|_| self.follow_clock) {
+                    if (tempo - self.tempo_bpm).abs() > 0.01 {
+                        self.set_tempo(tempo);
+                    }
+                }
+            },
+            MidiMessage::Start | MidiMessage::Continue | MidiMessage::Stop | MidiMessage::SongPosition(_) => {
+                self.clock_follower.handle(&message);
+                if self.follow_clock {
+                    self.follow_transport(&message);
+                }
+            },
+            // No programs yet
+            MidiMessage::ProgramChange { .. } | MidiMessage::SysEx(_) => {},
+        }
+    }
+    
+    // Apply a tuning change to notes already sounding
+    fn retune_voices(&mut self) {
+        for voice in &mut self.voices {
+            if let Some(key_pitch) = self.tuning.semitones(voice.note()) {
+                voice.set_key_pitch(key_pitch);
+            }
+        }
+    }
+    
+    // Time recorded events are stamped with: the transport position while playing,
+    // otherwise the frame clock counted on from where the transport stood
+    fn record_time(&self) -> f64 {
+        if self.is_playing {
+            return self.transport_position;
+        }
+        let (position, frame) = self.record_clock;
+        position + self.current_frame.saturating_sub(frame) as f64 / self.output_sample_rate as f64
+    }
+    
+    // Mirror an external sequencer's transport messages on our own transport
+    fn follow_transport(&mut self, message: &MidiMessage) {
+        match message {
+            MidiMessage::Start => {
+                self.seek(0.0);
+                self.is_playing = true;
+                self.clock_generator.start(0.0);
+            },
+            MidiMessage::Continue => {
+                self.is_playing = true;
+                self.clock_generator.start(self.transport_beats);
+            },
+            MidiMessage::Stop => {
+                // Pause in place, ending notes the sequencer was holding
+                self.is_playing = false;
+                self.sequencer.seek(self.transport_position);
+                self.clock_generator.stop();
+                self.record_clock = (self.transport_position, self.current_frame);
+            },
+            MidiMessage::SongPosition(_) => {
+                self.seek(self.clock_follower.beat_position() * 60.0 / self.tempo_bpm as f64);
+            },
+            _ => {},
+        }
+    }
+    
+    // Route a control change through MIDI learn and its mappings
+    fn apply_midi_mappings(&mut self, channel: u8, cc: u8, value: u8) {
+        let (controller, value) = match self.midi_learn.handle_cc(channel, cc, value) {
+            Some(event) => event,
+            None => return,
+        };
+        
+        for index in 0..self.midi_learn.mappings().len() {
+            let mapping = &self.midi_learn.mappings()[index];
+            if mapping.controller != controller {
+                continue;
+            }
+            
+            let target = mapping.target.clone();
+            let current = self.learn_target_value(&target);
+            if let Some(output) = self.midi_learn.take_over(index, value, current) {
+                self.set_learn_target(&target, output);
+                let written = self.learn_target_value(&target);
+                self.midi_learn.note_written(index, written);
+            }
+        }
+    }
+    
+    fn learn_target_value(&self, target: &LearnTarget) -> Option<f32> {
+        match *target {
+            LearnTarget::EffectParameter { effect_id, ref param } => {
+                self.mod_matrix.base(effect_id, param).or_else(|| self.effects.get_parameter(effect_id, param))
+            },
+            LearnTarget::Envelope { index, param } => {
+                let (attack, decay, hold, sustain, release) = match index {
+                    0 => self.amp_envelope,
+                    _ => self.voices[0].mod_envelope.parameters(),
+                };
+                Some(match param {
+                    EnvelopeParam::Attack => attack,
+                    EnvelopeParam::Decay => decay,
+                    EnvelopeParam::Hold => hold,
+                    EnvelopeParam::Sustain => sustain,
+                    EnvelopeParam::Release => release,
+                })
+            },
+            LearnTarget::Macro(index) => self.macros.get(index).map(|control| control.value()),
+        }
+    }
+    
+    fn set_learn_target(&mut self, target: &LearnTarget, value: f32) {
+        match *target {
+            LearnTarget::EffectParameter { effect_id, ref param } => {
+                let mut effects = ModulatedEffects { matrix: &mut self.mod_matrix, chains: &mut self.effects };
+                effects.set_parameter(effect_id, param, value);
+            },
+            LearnTarget::Envelope { index, param } => {
+                let (mut attack, mut decay, mut hold, mut sustain, mut release) = match index {
+                    0 => self.amp_envelope,
+                    _ => self.voices[0].mod_envelope.parameters(),
+                };
+                match param {
+                    EnvelopeParam::Attack => attack = value,
+                    EnvelopeParam::Decay => decay = value,
+                    EnvelopeParam::Hold => hold = value,
+                    EnvelopeParam::Sustain => sustain = value,
+                    EnvelopeParam::Release => release = value,
+                }
+                if index == 0 {
+                    self.set_envelope_parameters(attack, decay, hold, sustain, release);
+                } else {
+                    self.set_mod_envelope_parameters(attack, decay, hold, sustain, release);
+                }
+            },
+            LearnTarget::Macro(index) => self.set_macro_value(index, value),
+        }
+    }
+    
+    // Resolve the modulation matrix for effect parameters and every active voice
+    fn resolve_modulation(&mut self) {
+        // Analyse the audio gathered since the last resolve
+        self.mod_sources.follower = self.follower.process_block(&self.analysis_buffer);
+        self.mod_sources.transient = self.transient_detector.process_block(&self.analysis_buffer);
+        self.analysis_buffer.clear();
+        
+        for voice in self.voices.iter_mut().filter(|voice| voice.is_active()) {
+            let modulation = self.mod_matrix.resolve_voice(&self.mod_sources, &voice.mod_sources());
+            voice.apply_modulation(modulation, self.output_sample_rate);
+        }
+        
+        // Per-voice sources driving effect parameters follow the most recent note
+        let last_voice = self.last_voice
+            .map(|index| &self.voices[index])
+            .filter(|voice| voice.is_active())
+            .map(|voice| voice.mod_sources());
+        self.mod_matrix.resolve_effects(&self.mod_sources, last_voice.as_ref(), &mut self.effects);
+    }
+    
+    // Pick a free voice, or steal a releasing one, or finally the oldest
+    fn allocate_voice(&mut self) -> usize {
+        if let Some(index) = self.voices.iter().position(|voice| !voice.is_active()) {
+            return index;
+        }
+        
+        let oldest = |candidates: &mut dyn Iterator<Item = (usize, &Voice)>| {
+            candidates.min_by_key(|(_, voice)| voice.age()).map(|(index, _)| index)
+        };
+        
+        oldest(&mut self.voices.iter().enumerate().filter(|(_, voice)| voice.is_releasing()))
+            .or_else(|| oldest(&mut self.voices.iter().enumerate()))
+            .unwrap_or(0)
+    }
+    
+    pub fn left_output(&self) -> &[f32] {
+        &self.left_buffer
+    }
+    
+    pub fn right_output(&self) -> &[f32] {
+        &self.right_buffer
+    }
+    
+    // Read the next sample of the loaded data, converting between sample and output rates
+    fn next_sample(&mut self) -> f32 {
+        if !self.is_playing || self.sample_data.is_empty() {
+            return 0.0;
+        }
+        
+        // Past its end the sample falls silent; the transport, MIDI file and clock carry on
+        let index = self.playback_index as usize;
+        if index >= self.sample_data.len() {
+            return 0.0;
+        }
+        
+        let next = self.sample_data.get(index + 1).copied().unwrap_or(0.0);
+        let fraction = (self.playback_index - index as f64) as f32;
+        let sample = utils::lerp(self.sample_data[index], next, fraction);
+        
+        self.playback_index += (self.sample_rate / self.output_sample_rate) as f64;
+        sample
+    }
+}
+
+impl Default for SamplerProcessorState {
+    fn default() -> Self {
+        SamplerProcessorState::new()
+    }
 }
 
 #[wasm_bindgen]
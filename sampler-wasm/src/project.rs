@@ -0,0 +1,260 @@
+// Project files: a whole session in one file, so it opens exactly as it was saved.
+// The container is a RIFF form of type 'SPRJ' holding
+//
+//   proj  JSON manifest: samples, zones, markers, video reference, loop region and transport
+//   prst  the patch, as a binary preset
+//   audi  one per embedded sample, as a 32-bit float WAV file, in the order of the manifest
+//
+// A sample that isn't embedded is referenced by file name and looked up again on load.
+// Unknown chunks are skipped, so later versions can add chunks older readers ignore.
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::audio_file::SampleMetadata;
+use crate::effects::filter::FilterType;
+use crate::keymap::{AlternationGroup, AlternationMode, LoopMode, Zone, ZoneFilter};
+use crate::preset::Preset;
+
+// Manifest schema version written by this build
+pub const PROJECT_VERSION: u32 = 1;
+
+const FORM_TYPE: &[u8; 4] = b"SPRJ";
+
+// One sample buffer of the session; zones and the transport refer to samples by index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectSample {
+    pub name: String,  // File name a referenced sample is found by
+    pub embedded: bool,
+    pub sample_rate: f32,  // Rate the positions of its zones and metadata are counted at
+    pub metadata: SampleMetadata,  // Loops, markers and tempo from the sample's file
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectZoneFilter {
+    pub filter_type: u32,
+    pub cutoff: f32,
+    pub resonance: f32,
+}
+
+// A keymap zone, with positions in frames at its sample's `sample_rate`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectZone {
+    pub sample: usize,
+    pub root_key: u8,
+    pub key_range: (u8, u8),
+    pub velocity_range: (u8, u8),
+    pub tune: f32,  // Cents
+    pub gain: f32,  // Linear
+    pub pan: f32,
+    pub loop_mode: u32,
+    pub loop_start: usize,
+    pub loop_end: usize,  // Exclusive
+    pub velocity_fade_in: Option<(u8, u8)>,
+    pub velocity_fade_out: Option<(u8, u8)>,
+    pub alternation: Option<(usize, usize)>,  // Group index and position
+    pub amp_envelope: Option<(f32, f32, f32, f32, f32)>,
+    pub filter: Option<ProjectZoneFilter>,
+}
+
+impl ProjectZone {
+    pub fn new(zone: &Zone, sample: usize) -> Self {
+        ProjectZone {
+            sample,
+            root_key: zone.root_key,
+            key_range: zone.key_range,
+            velocity_range: zone.velocity_range,
+            tune: zone.tune,
+            gain: zone.gain,
+            pan: zone.pan,
+            loop_mode: zone.loop_mode.index(),
+            loop_start: zone.loop_start,
+            loop_end: zone.loop_end,
+            velocity_fade_in: zone.velocity_fade_in,
+            velocity_fade_out: zone.velocity_fade_out,
+            alternation: zone.alternation,
+            amp_envelope: zone.amp_envelope,
+            filter: zone.filter.map(|filter| ProjectZoneFilter {
+                filter_type: filter.filter_type.index(),
+                cutoff: filter.cutoff,
+                resonance: filter.resonance,
+            }),
+        }
+    }
+    
+    // Rebuild the zone over `sample`, scaling positions by `ratio` for a sample file
+    // whose rate differs from the one they were saved at
+    pub fn to_zone(&self, sample: Rc<[f32]>, sample_rate: f32, ratio: f64) -> Zone {
+        let scale = |position: usize| (position as f64 * ratio).round() as usize;
+        let length = sample.len();
+        let mut zone = Zone::new(sample, sample_rate);
+        zone.root_key = self.root_key.min(127);
+        zone.key_range = (self.key_range.0.min(127), self.key_range.1.min(127));
+        zone.velocity_range = (self.velocity_range.0.min(127), self.velocity_range.1.min(127));
+        zone.tune = self.tune.clamp(-9600.0, 9600.0);
+        zone.gain = self.gain;
+        zone.pan = self.pan.clamp(-1.0, 1.0);
+        zone.loop_mode = LoopMode::from_index(self.loop_mode);
+        zone.loop_end = scale(self.loop_end).min(length);
+        zone.loop_start = scale(self.loop_start).min(zone.loop_end);
+        zone.velocity_fade_in = self.velocity_fade_in;
+        zone.velocity_fade_out = self.velocity_fade_out;
+        zone.alternation = self.alternation;
+        zone.amp_envelope = self.amp_envelope;
+        zone.filter = self.filter.as_ref().map(|filter| ZoneFilter {
+            filter_type: FilterType::from_index(filter.filter_type),
+            cutoff: filter.cutoff,
+            resonance: filter.resonance,
+        });
+        zone
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectAlternationGroup {
+    pub random: bool,
+    pub length: usize,
+}
+
+impl ProjectAlternationGroup {
+    pub fn new(group: &AlternationGroup) -> Self {
+        ProjectAlternationGroup { random: group.mode == AlternationMode::Random, length: group.length }
+    }
+    
+    pub fn to_group(&self) -> AlternationGroup {
+        let mode = if self.random { AlternationMode::Random } else { AlternationMode::RoundRobin };
+        AlternationGroup::new(mode, self.length)
+    }
+}
+
+// A named point on the timeline, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMarker {
+    pub time: f64,
+    pub name: String,
+}
+
+// The video played alongside the timeline, which the project refers to but never embeds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoReference {
+    pub path: String,
+    pub offset: f64,  // Timeline position in seconds of the video's first frame
+}
+
+// Transport loop in seconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoopRegion {
+    pub start: f64,
+    pub end: f64,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectManifest {
+    pub version: u32,
+    pub samples: Vec<ProjectSample>,
+    pub transport_sample: Option<usize>,  // Sample the transport plays
+    pub zones: Vec<ProjectZone>,
+    pub alternation_groups: Vec<ProjectAlternationGroup>,
+    pub markers: Vec<SessionMarker>,
+    pub video: Option<VideoReference>,
+    pub loop_region: LoopRegion,
+    pub transport_position: f64,  // Seconds
+}
+
+impl ProjectManifest {
+    // Check every index the manifest holds, so a damaged file fails before it's applied
+    fn validate(&self) -> Result<(), String> {
+        let sample_count = self.samples.len();
+        if self.transport_sample.is_some_and(|sample| sample >= sample_count) {
+            return Err("Project transport sample doesn't exist".to_string());
+        }
+        for (index, zone) in self.zones.iter().enumerate() {
+            if zone.sample >= sample_count {
+                return Err(format!("Project zone {} refers to a missing sample", index + 1));
+            }
+            if zone.alternation.is_some_and(|(group, _)| group >= self.alternation_groups.len()) {
+                return Err(format!("Project zone {} refers to a missing alternation group", index + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub manifest: ProjectManifest,
+    pub preset: Preset,
+    pub audio: Vec<Vec<u8>>,  // Files of the embedded samples, in manifest order
+}
+
+impl Project {
+    pub fn write(&self) -> Vec<u8> {
+        let mut data = b"RIFF\0\0\0\0".to_vec();
+        data.extend_from_slice(FORM_TYPE);
+        write_chunk(&mut data, b"proj", serde_json::to_string(&self.manifest).unwrap_or_default().as_bytes());
+        write_chunk(&mut data, b"prst", &self.preset.to_binary());
+        for audio in &self.audio {
+            write_chunk(&mut data, b"audi", audio);
+        }
+        
+        let size = (data.len() - 8) as u32;
+        data[4..8].copy_from_slice(&size.to_le_bytes());
+        data
+    }
+    
+    pub fn read(data: &[u8]) -> Result<Project, String> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != FORM_TYPE {
+            return Err("Not a project file".to_string());
+        }
+        
+        let mut manifest = None;
+        let mut preset = None;
+        let mut audio = Vec::new();
+        
+        let mut offset = 12;
+        while offset + 8 <= data.len() {
+            let id = &data[offset..offset + 4];
+            let size = u32::from_le_bytes([data[offset + 4], data[offset + 5], data[offset + 6], data[offset + 7]]) as usize;
+            let body_start = offset + 8;
+            let body = &data[body_start..body_start.saturating_add(size).min(data.len())];
+            
+            match id {
+                b"proj" => manifest = Some(read_manifest(body)?),
+                b"prst" => preset = Some(Preset::from_bytes(body)?),
+                b"audi" => audio.push(body.to_vec()),
+                _ => {},
+            }
+            
+            offset = body_start.saturating_add(size).saturating_add(size & 1);
+        }
+        
+        Ok(Project {
+            manifest: manifest.ok_or_else(|| "Project file has no 'proj' chunk".to_string())?,
+            preset: preset.ok_or_else(|| "Project file has no 'prst' chunk".to_string())?,
+            audio,
+        })
+    }
+}
+
+fn read_manifest(body: &[u8]) -> Result<ProjectManifest, String> {
+    let value: Value = serde_json::from_slice(body).map_err(|e| format!("Invalid project manifest: {}", e))?;
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version == 0 || version > PROJECT_VERSION as u64 {
+        return Err(format!("Unsupported project version {} (this build reads up to {})", version, PROJECT_VERSION));
+    }
+    let manifest: ProjectManifest = serde_json::from_value(value).map_err(|e| format!("Invalid project manifest: {}", e))?;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+// Chunk header, body and the pad byte that keeps chunks word-aligned
+fn write_chunk(data: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    data.extend_from_slice(id);
+    data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    data.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        data.push(0);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,179 @@
+use std::f64::consts::PI;
+
+// Filter phases between input samples; intermediate positions interpolate linearly
+// between neighbouring phases
+const PHASES: usize = 256;
+
+// Passband edge as a fraction of the lower Nyquist frequency
+const ROLLOFF: f64 = 0.95;
+
+// Rates and conversion ratios the resampler accepts. Rates often come straight from
+// file headers, and the kernel widens with the downsampling ratio, so anything
+// outside these would allocate without bound.
+const MIN_RATE: f32 = 1000.0;
+const MAX_RATE: f32 = 768_000.0;
+const MAX_RATIO: f64 = 128.0;
+
+// Resampling quality: longer kernels and stronger windows give a flatter passband
+// and deeper stopband at more cost per sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResampleQuality {
+    Draft,
+    Standard,
+    High,
+}
+
+impl ResampleQuality {
+    pub fn from_index(index: u32) -> Self {
+        match index {
+            0 => ResampleQuality::Draft,
+            2 => ResampleQuality::High,
+            _ => ResampleQuality::Standard,
+        }
+    }
+    
+    pub fn index(&self) -> u32 {
+        match self {
+            ResampleQuality::Draft => 0,
+            ResampleQuality::Standard => 1,
+            ResampleQuality::High => 2,
+        }
+    }
+    
+    // (zero crossings each side of the kernel centre, Kaiser window beta)
+    fn kernel(self) -> (usize, f64) {
+        match self {
+            ResampleQuality::Draft => (4, 5.0),
+            ResampleQuality::Standard => (16, 8.0),
+            ResampleQuality::High => (32, 10.0),
+        }
+    }
+}
+
+// Polyphase windowed-sinc sample-rate converter for whole buffers
+pub struct Resampler {
+    step: f64,          // Input samples per output sample
+    ratio: f64,         // Output rate / input rate
+    half_width: usize,  // Kernel taps each side of the centre, in input samples
+    table: Vec<f32>,    // (PHASES + 1) rows of 2 * half_width taps
+}
+
+impl Resampler {
+    pub fn new(from_rate: f32, to_rate: f32, quality: ResampleQuality) -> Result<Self, String> {
+        for rate in [from_rate, to_rate] {
+            if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+                return Err(format!("Can't resample at {}Hz", rate));
+            }
+        }
+        let ratio = to_rate as f64 / from_rate as f64;
+        if !(1.0 / MAX_RATIO..=MAX_RATIO).contains(&ratio) {
+            return Err(format!("Can't resample from {}Hz to {}Hz", from_rate, to_rate));
+        }
+        let (zero_crossings, beta) = quality.kernel();
+        
+        // Downsampling lowers the cutoff below the input Nyquist and widens the kernel
+        let scale = ratio.min(1.0);
+        let cutoff = 0.5 * scale * ROLLOFF;  // Cycles per input sample
+        let half_width = (zero_crossings as f64 / scale).ceil() as usize;
+        let taps = 2 * half_width;
+        
+        let window_norm = bessel_i0(beta);
+        let mut table = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let fraction = phase as f64 / PHASES as f64;
+            let row_start = table.len();
+            for tap in 0..taps {
+                // Distance from the output position to input sample `tap`
+                let x = tap as f64 - (half_width as f64 - 1.0) - fraction;
+                let window_position = x / half_width as f64;
+                let window = if window_position.abs() >= 1.0 {
+                    0.0
+                } else {
+                    bessel_i0(beta * (1.0 - window_position * window_position).sqrt()) / window_norm
+                };
+                table.push((2.0 * cutoff * sinc(2.0 * cutoff * x) * window) as f32);
+            }
+            
+            // Unity gain at DC for every phase
+            let sum: f32 = table[row_start..].iter().sum();
+            if sum.abs() > f32::EPSILON {
+                for coefficient in &mut table[row_start..] {
+                    *coefficient /= sum;
+                }
+            }
+        }
+        
+        Ok(Resampler { step: 1.0 / ratio, ratio, half_width, table })
+    }
+    
+    // Convert a whole buffer; samples beyond either end count as silence
+    pub fn process(&self, input: &[f32]) -> Vec<f32> {
+        let output_len = (input.len() as f64 * self.ratio).ceil() as usize;
+        let taps = 2 * self.half_width;
+        let mut output = Vec::with_capacity(output_len);
+        
+        for n in 0..output_len {
+            let position = n as f64 * self.step;
+            let index = position.floor() as isize;
+            let phase = (position - index as f64) * PHASES as f64;
+            let phase_index = (phase as usize).min(PHASES - 1);
+            let blend = (phase - phase_index as f64) as f32;
+            
+            let row = &self.table[phase_index * taps..(phase_index + 1) * taps];
+            let next_row = &self.table[(phase_index + 1) * taps..(phase_index + 2) * taps];
+            let first = index - self.half_width as isize + 1;
+            
+            let mut sum = 0.0;
+            for tap in 0..taps {
+                let source = first + tap as isize;
+                if source < 0 || source as usize >= input.len() {
+                    continue;
+                }
+                let coefficient = row[tap] + (next_row[tap] - row[tap]) * blend;
+                sum += input[source as usize] * coefficient;
+            }
+            output.push(sum);
+        }
+        output
+    }
+    
+    // Map a position in input samples to output samples
+    pub fn convert_position(&self, position: usize) -> usize {
+        (position as f64 * self.ratio).round() as usize
+    }
+}
+
+// Convert `input` from one rate to another, returning it unchanged if they match or
+// either rate is out of the resampler's range
+pub fn resample(input: &[f32], from_rate: f32, to_rate: f32, quality: ResampleQuality) -> Vec<f32> {
+    if from_rate == to_rate {
+        return input.to_vec();
+    }
+    match Resampler::new(from_rate, to_rate, quality) {
+        Ok(resampler) => resampler.process(input),
+        Err(_) => input.to_vec(),
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Zeroth-order modified Bessel function of the first kind, for the Kaiser window
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= (half_x / k as f64) * (half_x / k as f64);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
\ No newline at end of file
@@ -0,0 +1,401 @@
+use serde::{Deserialize, Serialize};
+
+use crate::voice::ROOT_KEY;
+
+// Longest keyboard mapping accepted. Sizes come straight from the file, so anything
+// longer is taken as a damaged one rather than allocated.
+const MAX_MAP_SIZE: usize = 1024;
+
+// A scale from a Scala .scl file: pitches of degrees 1..=n in cents above the
+// tonic. The last degree is the period the scale repeats at (usually 2/1).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scale {
+    pub description: String,
+    pub cents: Vec<f64>,
+}
+
+impl Scale {
+    // 12-tone equal temperament
+    pub fn equal_temperament() -> Self {
+        Scale {
+            description: "12-TET".to_string(),
+            cents: (1..=12).map(|step| step as f64 * 100.0).collect(),
+        }
+    }
+    
+    // Parse a Scala scale: a description line, the note count, then one pitch per line,
+    // either in cents (contains a '.') or as a ratio ("3/2", "2"). Lines starting with
+    // '!' are comments and anything after a pitch is ignored.
+    pub fn parse_scl(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        
+        let description = lines.next()
+            .ok_or_else(|| "Empty scale file".to_string())?
+            .trim()
+            .to_string();
+        
+        let count = lines.next()
+            .and_then(|line| line.split_whitespace().next())
+            .and_then(|field| field.parse::<usize>().ok())
+            .ok_or_else(|| "Scale file has no valid note count".to_string())?;
+        
+        if count == 0 {
+            return Err("Scale has no notes".to_string());
+        }
+        
+        let mut cents = Vec::new();
+        for line in lines {
+            if cents.len() == count {
+                break;
+            }
+            let Some(field) = line.split_whitespace().next() else {
+                continue;
+            };
+            cents.push(parse_pitch(field).ok_or_else(|| format!("Invalid scale pitch: '{}'", line.trim()))?);
+        }
+        
+        if cents.len() < count {
+            return Err(format!("Scale lists {} notes but only {} were found", count, cents.len()));
+        }
+        
+        let scale = Scale { description, cents };
+        scale.validate()?;
+        Ok(scale)
+    }
+    
+    // Checks shared by parsed scales and those read back from presets
+    pub fn validate(&self) -> Result<(), String> {
+        match self.cents.last() {
+            None => Err("Scale has no notes".to_string()),
+            Some(_) if self.cents.iter().any(|cents| !cents.is_finite()) => Err("Scale has a pitch that isn't a number".to_string()),
+            Some(&period) if period <= 0.0 => Err("Scale period must be above the tonic".to_string()),
+            _ => Ok(()),
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.cents.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.cents.is_empty()
+    }
+    
+    fn period(&self) -> f64 {
+        self.cents[self.cents.len() - 1]
+    }
+    
+    // Cents of any degree, counting whole periods up or down from the tonic
+    fn degree_cents(&self, degree: i64) -> f64 {
+        let size = self.cents.len() as i64;
+        let periods = degree.div_euclid(size);
+        let step = degree.rem_euclid(size) as usize;
+        let within = if step == 0 { 0.0 } else { self.cents[step - 1] };
+        periods as f64 * self.period() + within
+    }
+}
+
+fn parse_pitch(field: &str) -> Option<f64> {
+    if field.contains('.') {
+        return field.parse::<f64>().ok().filter(|cents| cents.is_finite());
+    }
+    
+    let (numerator, denominator) = match field.split_once('/') {
+        Some((numerator, denominator)) => (numerator.parse::<f64>().ok()?, denominator.parse::<f64>().ok()?),
+        None => (field.parse::<f64>().ok()?, 1.0),
+    };
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return None;
+    }
+    Some(1200.0 * (numerator / denominator).log2())
+}
+
+// How MIDI keys map onto scale degrees, from a Scala .kbm file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyboardMapping {
+    pub first_note: u8,
+    pub last_note: u8,
+    pub middle_note: u8,       // Key playing the scale's tonic
+    pub reference_note: u8,    // Key tuned to `reference_frequency`
+    pub reference_frequency: f64,
+    pub octave_degree: usize,  // Degree the mapping repeats at, 0 for the scale's period
+    pub map: Vec<Option<usize>>,  // Degree per key in one repetition, None for unmapped keys; empty maps linearly
+}
+
+impl Default for KeyboardMapping {
+    // Every key mapped linearly, tonic on middle C, A4 = 440 Hz
+    fn default() -> Self {
+        KeyboardMapping {
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: 69,
+            reference_frequency: 440.0,
+            octave_degree: 0,
+            map: Vec::new(),
+        }
+    }
+}
+
+impl KeyboardMapping {
+    // Parse a Scala keyboard mapping: map size, first note, last note, middle note,
+    // reference note, reference frequency, octave degree, then one degree (or 'x')
+    // per key of the map. Missing trailing map entries are unmapped.
+    pub fn parse_kbm(text: &str) -> Result<Self, String> {
+        let mut fields = text.lines()
+            .filter(|line| !line.starts_with('!'))
+            .filter_map(|line| line.split_whitespace().next());
+        
+        let mut header = |name: &str| -> Result<f64, String> {
+            fields.next()
+                .and_then(|field| field.parse::<f64>().ok())
+                .filter(|value| value.is_finite() && *value >= 0.0)
+                .ok_or_else(|| format!("Keyboard mapping has no valid {}", name))
+        };
+        
+        let size = header("map size")? as usize;
+        let note = |value: f64| value.min(127.0) as u8;
+        let first_note = note(header("first note")?);
+        let last_note = note(header("last note")?);
+        let middle_note = note(header("middle note")?);
+        let reference_note = note(header("reference note")?);
+        let reference_frequency = header("reference frequency")?;
+        let octave_degree = header("octave degree")? as usize;
+        
+        if size > MAX_MAP_SIZE {
+            return Err(format!("Keyboard mapping size {} is larger than {}", size, MAX_MAP_SIZE));
+        }
+        
+        let mut map = Vec::new();
+        for field in fields.take(size) {
+            if field.eq_ignore_ascii_case("x") {
+                map.push(None);
+            } else {
+                let degree = field.parse::<usize>()
+                    .map_err(|_| format!("Invalid keyboard mapping entry: '{}'", field))?;
+                map.push(Some(degree));
+            }
+        }
+        map.resize(size, None);
+        
+        let mapping = KeyboardMapping {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+            map,
+        };
+        mapping.validate()?;
+        Ok(mapping)
+    }
+    
+    // Checks shared by parsed mappings and those read back from presets
+    pub fn validate(&self) -> Result<(), String> {
+        let notes = [self.first_note, self.last_note, self.middle_note, self.reference_note];
+        if notes.iter().any(|&note| note > 127) {
+            return Err("Keyboard mapping note is out of range".to_string());
+        }
+        if !self.reference_frequency.is_finite() || self.reference_frequency <= 0.0 {
+            return Err("Keyboard mapping reference frequency must be positive".to_string());
+        }
+        if self.map.len() > MAX_MAP_SIZE {
+            return Err(format!("Keyboard mapping size {} is larger than {}", self.map.len(), MAX_MAP_SIZE));
+        }
+        Ok(())
+    }
+    
+    // Cents of a key above the scale's tonic. `ignore_unmapped` treats an unmapped key
+    // as its position in the map, which is how the reference key is placed.
+    fn key_cents(&self, scale: &Scale, note: u8, ignore_unmapped: bool) -> Option<f64> {
+        let offset = note as i64 - self.middle_note as i64;
+        if self.map.is_empty() {
+            return Some(scale.degree_cents(offset));
+        }
+        
+        let size = self.map.len() as i64;
+        let repeats = offset.div_euclid(size);
+        let index = offset.rem_euclid(size) as usize;
+        let degree = match self.map[index] {
+            Some(degree) => degree,
+            None if ignore_unmapped => index,
+            None => return None,
+        };
+        
+        let repeat_cents = match self.octave_degree {
+            0 => scale.period(),
+            degree => scale.degree_cents(degree as i64),
+        };
+        Some(repeats as f64 * repeat_cents + scale.degree_cents(degree as i64))
+    }
+}
+
+// A scale laid out on the keyboard: the frequency of every MIDI key, or None where
+// the mapping leaves a key silent
+#[derive(Debug, Clone)]
+pub struct Tuning {
+    scale: Scale,
+    mapping: KeyboardMapping,
+    frequencies: [Option<f64>; 128],
+}
+
+impl Tuning {
+    // Standard tuning: 12-TET with A4 = 440 Hz
+    pub fn new() -> Self {
+        let mut tuning = Tuning {
+            scale: Scale::equal_temperament(),
+            mapping: KeyboardMapping::default(),
+            frequencies: [None; 128],
+        };
+        tuning.update();
+        tuning
+    }
+    
+    pub fn scale(&self) -> &Scale {
+        &self.scale
+    }
+    
+    pub fn mapping(&self) -> &KeyboardMapping {
+        &self.mapping
+    }
+    
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+        self.update();
+    }
+    
+    // Mappings with degrees beyond the scale wrap by its period
+    pub fn set_mapping(&mut self, mapping: KeyboardMapping) {
+        self.mapping = mapping;
+        self.update();
+    }
+    
+    // Tune `note` to `frequency` Hz, keeping the scale and key layout
+    pub fn set_reference_pitch(&mut self, note: u8, frequency: f64) {
+        self.mapping.reference_note = note.min(127);
+        self.mapping.reference_frequency = frequency.clamp(1.0, 20000.0);
+        self.update();
+    }
+    
+    fn update(&mut self) {
+        let mapping = &self.mapping;
+        let reference_cents = mapping.key_cents(&self.scale, mapping.reference_note, true).unwrap_or(0.0);
+        
+        for (note, frequency) in self.frequencies.iter_mut().enumerate() {
+            let note = note as u8;
+            *frequency = (note >= mapping.first_note && note <= mapping.last_note)
+                .then(|| mapping.key_cents(&self.scale, note, false))
+                .flatten()
+                .map(|cents| mapping.reference_frequency * 2.0f64.powf((cents - reference_cents) / 1200.0));
+        }
+    }
+    
+    // Frequency of a key in Hz, None if unmapped
+    pub fn frequency(&self, note: u8) -> Option<f64> {
+        self.frequencies[note.min(127) as usize]
+    }
+    
+    // Playback ratio for a key, relative to the sample playing ROOT_KEY in standard tuning
+    pub fn ratio(&self, note: u8) -> Option<f64> {
+        self.frequency(note).map(|frequency| frequency / root_frequency())
+    }
+    
+    // The same ratio in semitones, as voices apply pitch
+    pub fn semitones(&self, note: u8) -> Option<f32> {
+        self.ratio(note).map(|ratio| (12.0 * ratio.log2()) as f32)
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::new()
+    }
+}
+
+// Frequency of ROOT_KEY in standard tuning, matching utils::midi_to_freq in double precision
+fn root_frequency() -> f64 {
+    440.0 * 2.0f64.powf((ROOT_KEY as f64 - 69.0) / 12.0)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SCL: &str = "! meantone.scl\n!\nQuarter-comma meantone fragment\n 4\n!\n 193.157\n 5/4 major third\n 3/2\n 2\n";
+    const KBM: &str = "! Twelve keys, C# silent, A4 = 432 Hz\n12\n0\n127\n60\n69\n432.0\n12\n! Mapping\n0\nx\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n";
+    
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+    
+    #[test]
+    fn parses_scl() {
+        let scale = Scale::parse_scl(SCL).unwrap();
+        assert_eq!(scale.description, "Quarter-comma meantone fragment");
+        assert_eq!(scale.len(), 4);
+        assert_close(scale.cents[0], 193.157);
+        assert_close(scale.cents[1], 1200.0 * 1.25f64.log2());
+        assert_close(scale.cents[2], 1200.0 * 1.5f64.log2());
+        assert_close(scale.cents[3], 1200.0);
+        
+        assert!(Scale::parse_scl("Empty\n0\n").is_err());
+        assert!(Scale::parse_scl("Bad ratio\n1\n0/1\n").is_err());
+        assert!(Scale::parse_scl("Descending\n1\n-100.0\n").is_err());
+    }
+    
+    #[test]
+    fn parses_kbm() {
+        let mapping = KeyboardMapping::parse_kbm(KBM).unwrap();
+        assert_eq!((mapping.first_note, mapping.last_note), (0, 127));
+        assert_eq!((mapping.middle_note, mapping.reference_note), (60, 69));
+        assert_eq!(mapping.reference_frequency, 432.0);
+        assert_eq!(mapping.octave_degree, 12);
+        assert_eq!(mapping.map.len(), 12);
+        assert_eq!(mapping.map[1], None);
+        assert_eq!(mapping.map[9], Some(9));
+        
+        assert!(KeyboardMapping::parse_kbm("2000\n0\n127\n60\n69\n440.0\n12\n").is_err());
+        assert!(KeyboardMapping::parse_kbm("1\n0\n127\n60\n69\n0.0\n1\n0\n").is_err());
+    }
+    
+    #[test]
+    fn reference_pitch() {
+        // The mapping's reference key sounds its frequency and the rest follow the scale
+        let mut tuning = Tuning::new();
+        tuning.set_mapping(KeyboardMapping::parse_kbm(KBM).unwrap());
+        assert_close(tuning.frequency(69).unwrap(), 432.0);
+        assert_close(tuning.frequency(57).unwrap(), 216.0);
+        assert_close(tuning.frequency(72).unwrap(), 432.0 * 2.0f64.powf(3.0 / 12.0));
+        assert_eq!(tuning.frequency(61), None);
+        
+        // Moving the reference keeps the layout
+        tuning.set_reference_pitch(60, 256.0);
+        assert_close(tuning.frequency(60).unwrap(), 256.0);
+        assert_close(tuning.frequency(69).unwrap(), 256.0 * 2.0f64.powf(9.0 / 12.0));
+        assert_eq!(tuning.frequency(73), None);
+        
+        // Standard tuning plays the sample at its root key unchanged
+        assert_close(Tuning::new().ratio(ROOT_KEY).unwrap(), 1.0);
+    }
+    
+    #[test]
+    fn truncated_input_is_rejected() {
+        // Cut before the last pitch, or before the last header field, the file is
+        // an error; later cuts only shorten a value or the map
+        let last_pitch = SCL.rfind(" 2\n").unwrap();
+        for len in 0..SCL.len() {
+            let result = Scale::parse_scl(&SCL[..len]);
+            if len <= last_pitch {
+                assert!(result.is_err());
+            }
+        }
+        
+        let octave_degree = KBM.find("12\n!").unwrap();
+        for len in 0..KBM.len() {
+            let result = KeyboardMapping::parse_kbm(&KBM[..len]);
+            if len <= octave_degree {
+                assert!(result.is_err());
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,5 +1,3 @@
-use wasm_bindgen::prelude::*;
-
 // Convert a time in seconds to a sample index
 pub fn time_to_sample_index(time_seconds: f32, sample_rate: f32) -> usize {
     (time_seconds * sample_rate) as usize
@@ -112,4 +110,37 @@ impl ParameterSmoother {
     pub fn is_close_to_target(&self, threshold: f32) -> bool {
         (self.current_value - self.target_value).abs() < threshold
     }
+}
+
+// Small xorshift generator for audio-rate randomness (not cryptographically secure)
+pub struct XorShiftRng {
+    state: u32,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u32) -> Self {
+        XorShiftRng {
+            // Zero is a fixed point of xorshift, so never use it as the state
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+    
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+    
+    // Uniform value in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+    
+    // Uniform value in [-1, 1)
+    pub fn next_bipolar(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
 }
\ No newline at end of file
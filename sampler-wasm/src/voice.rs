@@ -0,0 +1,360 @@
+use std::f32::consts::FRAC_PI_4;
+
+use crate::effects::Effect;
+use crate::effects::filter::{Filter, FilterType};
+use crate::envelope::Envelope;
+use crate::keymap::{LoopMode, Zone, ZoneFilter};
+use crate::mod_matrix::{VoiceModSources, VoiceModulation};
+use crate::mseg::Mseg;
+use crate::utils;
+
+pub const MAX_VOICES: usize = 16;
+
+// Note the loaded sample plays back at its original pitch
+pub const ROOT_KEY: u8 = 60;
+
+// A single playing note of one keymap zone
+pub struct Voice {
+    // Note state
+    channel: u8,
+    note: u8,
+    key_pitch: f32,    // Semitones from ROOT_KEY for this note under the current tuning
+    velocity: f32,
+    random: f32,
+    age: u64,  // Note-on counter value, used for voice stealing
+    pressure: f32,     // Polyphonic aftertouch or MPE pressure (0 to 1)
+    timbre: f32,       // MPE timbre (CC 74, 0 to 1)
+    bend: f32,         // Channel pitch bend in semitones
+    sustained: bool,   // Note-off received while the sustain pedal was down
+
+    // Zone being played
+    zone: usize,
+    zone_pitch: f32,   // Semitones added for the zone's root key and tune
+    zone_gain: f32,    // Zone gain including velocity crossfades
+    zone_pan: f32,
+    source_rate: f32,  // Sample rate of the zone's sample
+    loop_mode: LoopMode,
+    loop_region: Option<(usize, usize)>,
+    zone_filter: Option<ZoneFilter>,
+
+    // Playback state
+    position: f64,
+    increment: f64,
+    rate_ratio: f64,  // Sample rate / output rate
+    active: bool,
+
+    // Per-voice envelopes (amplitude and modulation)
+    pub amp_envelope: Envelope,
+    pub mod_envelope: Envelope,
+    pub mseg: Mseg,
+    mseg_amplitude: bool,  // Use the MSEG instead of `amp_envelope` for amplitude
+
+    // Per-note expression (MPE): pressure scales amplitude, timbre opens the filter
+    pressure_to_amp: f32,              // 0 = ignore pressure, 1 = pressure alone sets the level
+    timbre_cutoff: Option<(f32, f32)>, // Cutoff range in Hz swept by timbre, None to bypass the filter
+    filter: Filter,
+
+    // Latest resolved modulation
+    modulation: VoiceModulation,
+}
+
+impl Voice {
+    pub fn new(sample_rate: f32) -> Self {
+        // Velocity only shapes the amplitude envelope unless asked to, so mod envelope
+        // routings keep their depth
+        let mut mod_envelope = Envelope::new(sample_rate);
+        mod_envelope.set_velocity_sensitivity(0.0, 0.0);
+
+        Voice {
+            channel: 0,
+            note: ROOT_KEY,
+            key_pitch: 0.0,
+            velocity: 0.0,
+            random: 0.0,
+            age: 0,
+            pressure: 0.0,
+            timbre: 0.5,
+            bend: 0.0,
+            sustained: false,
+
+            zone: 0,
+            zone_pitch: 0.0,
+            zone_gain: 1.0,
+            zone_pan: 0.0,
+            source_rate: sample_rate,
+            loop_mode: LoopMode::NoLoop,
+            loop_region: None,
+            zone_filter: None,
+
+            position: 0.0,
+            increment: 1.0,
+            rate_ratio: 1.0,
+            active: false,
+
+            amp_envelope: Envelope::new(sample_rate),
+            mod_envelope,
+            mseg: Mseg::new(sample_rate),
+            mseg_amplitude: false,
+
+            pressure_to_amp: 0.0,
+            timbre_cutoff: None,
+            filter: Filter::new(FilterType::LowPass, 20000.0, 0.707, sample_rate),
+
+            modulation: VoiceModulation::default(),
+        }
+    }
+
+    pub fn start(&mut self, channel: u8, note: u8, velocity: f32, random: f32, age: u64) {
+        self.channel = channel;
+        self.note = note;
+        self.key_pitch = note as f32 - ROOT_KEY as f32;
+        self.velocity = velocity.clamp(0.0, 1.0);
+        self.random = random;
+        self.age = age;
+        self.pressure = 0.0;
+        self.sustained = false;
+        self.filter.reset();
+        self.position = 0.0;
+        self.active = true;
+
+        self.amp_envelope.trigger_with_velocity(self.velocity);
+        self.mod_envelope.trigger_with_velocity(self.velocity);
+        self.mseg.trigger();
+    }
+
+    // Play a new note on a voice that may still be sounding. The envelopes decide
+    // (by their retrigger mode) whether to restart; playback only restarts with them.
+    // Returns true if playback restarted.
+    pub fn retrigger(&mut self, channel: u8, note: u8, velocity: f32, random: f32, age: u64) -> bool {
+        if !self.active {
+            self.start(channel, note, velocity, random, age);
+            return true;
+        }
+
+        self.channel = channel;
+        self.note = note;
+        self.key_pitch = note as f32 - ROOT_KEY as f32;
+        self.velocity = velocity.clamp(0.0, 1.0);
+        self.random = random;
+        self.age = age;
+        self.sustained = false;
+
+        let restarted = self.amp_envelope.trigger_with_velocity(self.velocity);
+        self.mod_envelope.trigger_with_velocity(self.velocity);
+        if restarted {
+            self.mseg.trigger();
+            self.position = 0.0;
+        }
+        restarted
+    }
+
+    // Change the pitch of a held note without retriggering (mono legato)
+    pub fn set_note(&mut self, note: u8, key_pitch: f32) {
+        self.note = note;
+        self.set_key_pitch(key_pitch);
+    }
+
+    // Play `zone` (the keymap's zone at `index`) with `gain` from its velocity crossfades
+    pub fn set_zone(&mut self, index: usize, zone: &Zone, gain: f32) {
+        self.zone = index;
+        self.zone_pitch = zone.pitch_offset();
+        self.zone_gain = gain;
+        self.zone_pan = zone.pan;
+        self.source_rate = zone.sample_rate;
+        self.loop_mode = zone.loop_mode;
+        self.loop_region = zone.loop_region();
+
+        self.zone_filter = zone.filter;
+        let filter = zone.filter.unwrap_or(ZoneFilter { filter_type: FilterType::LowPass, cutoff: 20000.0, resonance: 0.707 });
+        self.filter.set_filter_type(filter.filter_type);
+        self.filter.set_cutoff(filter.cutoff);
+        self.filter.set_resonance(filter.resonance);
+        self.update_increment();
+    }
+
+    pub fn zone(&self) -> usize {
+        self.zone
+    }
+
+    // Pitch of the note in semitones from ROOT_KEY, for tunings other than 12-TET
+    pub fn set_key_pitch(&mut self, semitones: f32) {
+        self.key_pitch = semitones;
+        self.update_increment();
+    }
+
+    pub fn release(&mut self) {
+        self.sustained = false;
+
+        // One-shot zones play out regardless of note-off
+        if self.loop_mode == LoopMode::OneShot {
+            return;
+        }
+        self.amp_envelope.release();
+        self.mod_envelope.release();
+        self.mseg.release();
+    }
+
+    pub fn kill(&mut self) {
+        self.active = false;
+        self.sustained = false;
+        self.amp_envelope.reset();
+        self.mod_envelope.reset();
+        self.mseg.reset();
+    }
+
+    pub fn set_mseg_amplitude(&mut self, enabled: bool) {
+        self.mseg_amplitude = enabled;
+    }
+
+    pub fn mseg_amplitude(&self) -> bool {
+        self.mseg_amplitude
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // True once note-off has been received and the voice is fading out
+    pub fn is_releasing(&self) -> bool {
+        if self.mseg_amplitude {
+            self.mseg.is_releasing()
+        } else {
+            self.amp_envelope.is_releasing()
+        }
+    }
+
+    pub fn note(&self) -> u8 {
+        self.note
+    }
+
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    // Hold the note after note-off until the sustain pedal comes up
+    pub fn set_sustained(&mut self, sustained: bool) {
+        self.sustained = sustained;
+    }
+
+    pub fn is_sustained(&self) -> bool {
+        self.sustained
+    }
+
+    pub fn set_pressure(&mut self, pressure: f32) {
+        self.pressure = pressure.clamp(0.0, 1.0);
+    }
+
+    pub fn set_timbre(&mut self, timbre: f32) {
+        self.timbre = timbre.clamp(0.0, 1.0);
+        if let Some((low, high)) = self.timbre_cutoff {
+            self.filter.set_cutoff(low * (high / low).powf(self.timbre));
+        }
+    }
+
+    // Enable per-note expression for the current note; pass 0 and None for plain MIDI notes
+    pub fn set_expression(&mut self, pressure_to_amp: f32, timbre_cutoff: Option<(f32, f32)>) {
+        self.pressure_to_amp = pressure_to_amp.clamp(0.0, 1.0);
+        self.timbre_cutoff = timbre_cutoff.map(|(low, high)| (low.max(20.0), high.max(20.0)));
+        self.set_timbre(self.timbre);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.amp_envelope.set_sample_rate(sample_rate);
+        self.mod_envelope.set_sample_rate(sample_rate);
+        self.mseg.set_sample_rate(sample_rate);
+        let filter = self.zone_filter.unwrap_or(ZoneFilter { filter_type: FilterType::LowPass, cutoff: 20000.0, resonance: 0.707 });
+        self.filter = Filter::new(filter.filter_type, filter.cutoff, filter.resonance, sample_rate);
+        self.set_timbre(self.timbre);
+    }
+
+    // Channel pitch bend in semitones, applied on top of the note and pitch modulation
+    pub fn set_pitch_bend(&mut self, semitones: f32) {
+        self.bend = semitones;
+        self.update_increment();
+    }
+
+    pub fn age(&self) -> u64 {
+        self.age
+    }
+
+    pub fn mod_sources(&self) -> VoiceModSources {
+        VoiceModSources {
+            envelopes: [self.amp_envelope.level(), self.mod_envelope.level(), self.mseg.level()],
+            velocity: self.velocity,
+            key: self.note as f32 / 127.0,
+            random: self.random,
+            pressure: self.pressure,
+            timbre: self.timbre,
+        }
+    }
+
+    // Apply the resolved modulation for playback at `output_sample_rate`
+    pub fn apply_modulation(&mut self, modulation: VoiceModulation, output_sample_rate: f32) {
+        self.modulation = modulation;
+        self.rate_ratio = (self.source_rate / output_sample_rate) as f64;
+        self.update_increment();
+    }
+
+    fn update_increment(&mut self) {
+        let semitones = self.key_pitch + self.zone_pitch + self.modulation.pitch + self.bend;
+        self.increment = utils::semitones_to_ratio(semitones) as f64 * self.rate_ratio;
+    }
+
+    // Jump to the modulated sample start, used right after note-on
+    pub fn apply_sample_start(&mut self, sample_length: usize) {
+        self.position = self.modulation.sample_start as f64 * sample_length as f64;
+    }
+
+    // Render one frame of the sample and return the panned (left, right) pair
+    pub fn process(&mut self, sample_data: &[f32]) -> (f32, f32) {
+        if !self.active {
+            return (0.0, 0.0);
+        }
+
+        let envelope_level = self.amp_envelope.process();
+        self.mod_envelope.process();
+        let mseg_level = self.mseg.process();
+
+        // The ADHSR envelope applies velocity itself; the MSEG does not
+        let (amplitude, sounding) = if self.mseg_amplitude {
+            (mseg_level * self.velocity, self.mseg.is_active())
+        } else {
+            (envelope_level, self.amp_envelope.is_active())
+        };
+
+        // Sustain loops stop looping once the note is released
+        let looping = match self.loop_mode {
+            LoopMode::Continuous => self.loop_region,
+            LoopMode::Sustain if !self.is_releasing() => self.loop_region,
+            _ => None,
+        };
+        if let Some((start, end)) = looping {
+            if self.position >= end as f64 {
+                self.position = start as f64 + (self.position - end as f64) % (end - start) as f64;
+            }
+        }
+
+        let index = self.position as usize;
+        if index >= sample_data.len() || !sounding {
+            self.kill();
+            return (0.0, 0.0);
+        }
+
+        let next = match looping {
+            Some((start, end)) if index + 1 >= end => sample_data[start],
+            _ => sample_data.get(index + 1).copied().unwrap_or(0.0),
+        };
+        let fraction = (self.position - index as f64) as f32;
+        let expression = 1.0 - self.pressure_to_amp + self.pressure_to_amp * self.pressure;
+        let mut sample = utils::lerp(sample_data[index], next, fraction) * amplitude * expression * self.zone_gain;
+        if self.timbre_cutoff.is_some() || self.zone_filter.is_some() {
+            sample = self.filter.process(sample);
+        }
+
+        self.position += self.increment;
+
+        // Equal-power pan
+        let angle = ((self.modulation.pan + self.zone_pan).clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+        (sample * angle.cos(), sample * angle.sin())
+    }
+}
\ No newline at end of file